@@ -0,0 +1,136 @@
+//! This module contains methods to read and modify many cells of a grid
+//! at once, taking a single lock on the buffer of the cells instead of
+//! one per cell like `get_cell_state` and `set_cell_state`.
+
+use std::ops::Deref;
+
+use super::vulkano::buffer::cpu_access::ReadLock;
+
+use error::GridErrorKind;
+use packed;
+use Cells;
+use Grid;
+
+/// The cells of a `Grid`, one byte per cell, row by row, returned by
+/// `Grid::cells`.
+///
+/// The buffer of the cells stays locked while it is alive, unless the
+/// cells are bit-packed: they are then unpacked into a copy.
+pub struct CellsGuard<'a> {
+    inner: Guard<'a>,
+}
+
+enum Guard<'a> {
+    Locked(ReadLock<'a, [u8]>),
+    Unpacked(Vec<u8>),
+}
+
+impl<'a> Deref for CellsGuard<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.inner {
+            Guard::Locked(ref cells) => cells,
+            Guard::Unpacked(ref cells) => cells,
+        }
+    }
+}
+
+impl Grid {
+    /// Returns the cells of the current `Grid`, one byte per cell: the
+    /// cell at the coordinates (`x`, `y`) is at the index
+    /// `y * width + x`.
+    pub fn cells(&self) -> CellsGuard<'_> {
+        let inner = match self.cells {
+            Cells::Bytes(ref cells) => Guard::Locked(cells.read().unwrap()),
+            Cells::Packed(ref words) => Guard::Unpacked(packed::unpack_u32(
+                &words.read().unwrap(),
+                self.width,
+                self.height,
+            )),
+        };
+
+        CellsGuard { inner }
+    }
+
+    /// Returns an iterator over the coordinates of the living cells,
+    /// row by row.
+    pub fn live_cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let width = self.width;
+        let cells = self.cells();
+
+        (0..cells.len())
+            .filter(move |&i| cells[i] == 255)
+            .map(move |i| (i % width, i / width))
+    }
+
+    /// Modifies the states of the cells given as `(x, y, state)`.
+    /// Returns `Err(GridErrorKind::OutOfBoundCoords)` as soon as some
+    /// coordinates are out of bounds, the previous cells being modified.
+    pub fn set_cells<I>(&mut self, cells: I) -> Result<(), GridErrorKind>
+    where
+        I: IntoIterator<Item = (usize, usize, u8)>,
+    {
+        self.edited();
+        if self.journal.is_none() {
+            return self.write_cell_states(cells);
+        }
+
+        // The previous states of the modified cells are recorded, taking
+        // into account the cells given several times
+        let cells: Vec<(usize, usize, u8)> = cells.into_iter().collect();
+        let changes = {
+            let (width, height) = (self.width, self.height);
+            let mut current = self.read_cells();
+            cells
+                .iter()
+                .take_while(|&&(x, y, _)| x < width && y < height)
+                .map(|&(x, y, state)| {
+                    let before = current[y * width + x];
+                    current[y * width + x] = state;
+                    (x, y, before, state)
+                })
+                .collect()
+        };
+        self.record_cells(changes);
+
+        self.write_cell_states(cells)
+    }
+
+    fn write_cell_states<I>(&mut self, cells: I) -> Result<(), GridErrorKind>
+    where
+        I: IntoIterator<Item = (usize, usize, u8)>,
+    {
+        self.changed_tiles = None;
+        let (width, height) = (self.width, self.height);
+        let words_per_row = width.div_ceil(32);
+
+        match self.cells {
+            Cells::Bytes(ref buffer) => {
+                let mut buffer = buffer.write().unwrap();
+                for (x, y, state) in cells {
+                    if x >= width || y >= height {
+                        return Err(GridErrorKind::OutOfBoundCoords);
+                    }
+                    buffer[y * width + x] = state;
+                }
+            }
+            Cells::Packed(ref words) => {
+                let mut words = words.write().unwrap();
+                for (x, y, state) in cells {
+                    if x >= width || y >= height {
+                        return Err(GridErrorKind::OutOfBoundCoords);
+                    }
+                    let word = &mut words[y * words_per_row + x / 32];
+                    if state != 0 {
+                        *word |= 1 << (x % 32);
+                    } else {
+                        *word &= !(1 << (x % 32));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
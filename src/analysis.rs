@@ -14,10 +14,243 @@ use super::vulkano::image::StorageImage;
 use super::vulkano::pipeline::ComputePipeline;
 use super::vulkano::sync::GpuFuture;
 
+use super::vulkan::chs;
 use super::vulkan::fms;
+use super::vulkan::pcs;
+use Cells;
 use Grid;
 
+/// Odd factors of the hash of the patterns, see `hash_pattern`.
+const HASH_FACTORS: [u32; 4] = [0x9E37_79B1, 0x85EB_CA77, 0xC2B2_AE3D, 0x27D4_EB2F];
+
+/// A reduction of the cells of an image computed on the GPU, see
+/// `Grid::reduce`.
+pub(crate) enum Reduction {
+    /// The number of living cells.
+    Population(Arc<CpuAccessibleBuffer<u32>>),
+    /// The population and the hash of the pattern, created by
+    /// `Grid::new_hash_buffer`.
+    Hash(Arc<CpuAccessibleBuffer<[u32]>>),
+}
+
 impl Grid {
+    /// Returns the number of living cells of the current `Grid`.
+    pub fn get_population(&self) -> u64 {
+        // Bit-packed rows are padded with dead cells
+        if let Cells::Packed(ref words) = self.cells {
+            return words
+                .read()
+                .unwrap()
+                .iter()
+                .map(|word| u64::from(word.count_ones()))
+                .sum();
+        }
+
+        self.get_population_in(0, 0, self.width, self.height)
+    }
+
+    /// Returns the number of living cells of the `width` x `height`
+    /// region whose north west corner is at (`x`, `y`). The cells beyond
+    /// the edges of the grid are ignored.
+    pub fn get_population_in(&self, x: usize, y: usize, width: usize, height: usize) -> u64 {
+        let width = width.min(self.width.saturating_sub(x));
+        let height = height.min(self.height.saturating_sub(y));
+        if width == 0 || height == 0 {
+            return 0;
+        }
+
+        if self.is_tiled() || self.is_bit_packed() {
+            return self
+                .read_region(x, y, width, height)
+                .iter()
+                .filter(|&&cell| cell == 255)
+                .count() as u64;
+        }
+
+        let cells_img = StorageImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d {
+                width: self.width as u32,
+                height: self.height as u32,
+            },
+            Format::R8Unorm,
+            Some(self.queue.family()),
+        )
+        .expect("failed to create image");
+
+        let population =
+            CpuAccessibleBuffer::from_data(self.device.clone(), BufferUsage::all(), 0u32)
+                .expect("failed to create buffer");
+
+        let builder = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .copy_buffer_to_image(self.byte_cells(), cells_img.clone())
+            .unwrap();
+        let command_buffer = self
+            .count_population(
+                builder,
+                cells_img.clone(),
+                (x, y, width, height),
+                population.clone(),
+            )
+            .build()
+            .unwrap();
+
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let count = *population.read().unwrap();
+        u64::from(count)
+    }
+
+    /// Adds to `builder` the dispatch of the `pcs` shader, which adds the
+    /// number of living cells of the `(x, y, width, height)` region of
+    /// `img` to `population`.
+    pub(crate) fn count_population(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        img: Arc<StorageImage<Format>>,
+        region: (usize, usize, usize, usize),
+        population: Arc<CpuAccessibleBuffer<u32>>,
+    ) -> AutoCommandBufferBuilder {
+        let (x, y, width, height) = region;
+        let region = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            [x as i32, y as i32, width as i32, height as i32],
+        )
+        .expect("failed to create buffer");
+
+        let shader =
+            pcs::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                .add_image(img)
+                .unwrap()
+                .add_buffer(region)
+                .unwrap()
+                .add_buffer(population)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        builder
+            .dispatch(
+                [width.div_ceil(8) as u32, height.div_ceil(8) as u32, 1],
+                compute_pipeline,
+                set,
+                (),
+            )
+            .unwrap()
+    }
+
+    /// Returns the population of the current `Grid` and a hash of its
+    /// pattern which doesn't depend on its position. They are computed
+    /// on the GPU when possible.
+    pub(crate) fn summarise_pattern(&self) -> (u64, u64) {
+        if self.is_tiled() || self.is_bit_packed() {
+            return summarise_hash(hash_cells(&self.read_cells(), self.width));
+        }
+
+        let cells_img = StorageImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d {
+                width: self.width as u32,
+                height: self.height as u32,
+            },
+            Format::R8Unorm,
+            Some(self.queue.family()),
+        )
+        .expect("failed to create image");
+
+        let hash = self.new_hash_buffer();
+        let builder = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .copy_buffer_to_image(self.byte_cells(), cells_img.clone())
+            .unwrap();
+        let command_buffer = self
+            .reduce(
+                builder,
+                cells_img.clone(),
+                (self.width, self.height),
+                Reduction::Hash(hash.clone()),
+            )
+            .build()
+            .unwrap();
+
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        read_hash_buffer(&hash)
+    }
+
+    /// Returns a buffer in which the `chs` shader reduces the cells, see
+    /// `read_hash_buffer`.
+    pub(crate) fn new_hash_buffer(&self) -> Arc<CpuAccessibleBuffer<[u32]>> {
+        let mut data = HASH_FACTORS.to_vec();
+        data.extend_from_slice(&[0, u32::MAX, u32::MAX, 0, 0]);
+
+        CpuAccessibleBuffer::from_iter(self.device.clone(), BufferUsage::all(), data.into_iter())
+            .expect("failed to create buffer")
+    }
+
+    /// Adds to `builder` the dispatch of the shader computing `reduction`
+    /// on the whole `size` image `img`.
+    pub(crate) fn reduce(
+        &self,
+        builder: AutoCommandBufferBuilder,
+        img: Arc<StorageImage<Format>>,
+        size: (usize, usize),
+        reduction: Reduction,
+    ) -> AutoCommandBufferBuilder {
+        let hash = match reduction {
+            Reduction::Population(population) => {
+                return self.count_population(builder, img, (0, 0, size.0, size.1), population)
+            }
+            Reduction::Hash(hash) => hash,
+        };
+
+        let shader =
+            chs::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                .add_image(img)
+                .unwrap()
+                .add_buffer(hash)
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        builder
+            .dispatch(
+                [size.0.div_ceil(8) as u32, size.1.div_ceil(8) as u32, 1],
+                compute_pipeline,
+                set,
+                (),
+            )
+            .unwrap()
+    }
+
     /// Returns the coordinates of the cell at the upper left corner of
     /// the current `Grid`.
     pub fn guess_pattern_origin(&self) -> (usize, usize) {
@@ -31,16 +264,12 @@ impl Grid {
         let mut orig_x = width;
         let mut orig_y = height;
 
-        for y in 0..height {
-            for x in 0..width {
-                if self.get_cell_state(x as i64, y as i64) == 255 {
-                    if x < orig_x {
-                        orig_x = x;
-                    }
-                    if y < orig_y {
-                        orig_y = y;
-                    }
-                }
+        for (x, y) in self.live_cells() {
+            if x < orig_x {
+                orig_x = x;
+            }
+            if y < orig_y {
+                orig_y = y;
             }
         }
 
@@ -59,16 +288,12 @@ impl Grid {
         let (orig_x, orig_y) = self.guess_pattern_origin();
         let (mut lim_x, mut lim_y) = (orig_x, orig_y);
 
-        for y in 0..height {
-            for x in 0..width {
-                if self.get_cell_state(x as i64, y as i64) == 255 {
-                    if x > lim_x {
-                        lim_x = x;
-                    }
-                    if y > lim_y {
-                        lim_y = y;
-                    }
-                }
+        for (x, y) in self.live_cells() {
+            if x > lim_x {
+                lim_x = x;
+            }
+            if y > lim_y {
+                lim_y = y;
             }
         }
 
@@ -78,6 +303,10 @@ impl Grid {
     pub fn compute_pattern_boundaries(
         &self,
     ) -> (Option<usize>, Option<usize>, Option<usize>, Option<usize>) {
+        if self.is_tiled() {
+            return self.compute_pattern_boundaries_cpu();
+        }
+
         let cells_img = StorageImage::new(
             self.device.clone(),
             Dimensions::Dim2d {
@@ -125,7 +354,7 @@ impl Grid {
         let command_buffer =
             AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
                 .unwrap()
-                .copy_buffer_to_image(self.cells.clone(), cells_img.clone())
+                .copy_buffer_to_image(self.byte_cells(), cells_img.clone())
                 .unwrap()
                 .dispatch(
                     [
@@ -156,4 +385,121 @@ impl Grid {
 
         (min_x, max_x, min_y, max_y)
     }
+
+    /// Works like `compute_pattern_boundaries`, on the CPU, for grids too
+    /// large to be stored in a single image.
+    fn compute_pattern_boundaries_cpu(
+        &self,
+    ) -> (Option<usize>, Option<usize>, Option<usize>, Option<usize>) {
+        let mut flat_map_x = vec![false; self.width];
+        let mut flat_map_y = vec![false; self.height];
+
+        for (i, &cell) in self.read_cells().iter().enumerate() {
+            if cell == 255 {
+                flat_map_x[i % self.width] = true;
+                flat_map_y[i / self.width] = true;
+            }
+        }
+
+        (
+            flat_map_x.iter().position(|&n| n),
+            flat_map_x.iter().rposition(|&n| n),
+            flat_map_y.iter().position(|&n| n),
+            flat_map_y.iter().rposition(|&n| n),
+        )
+    }
+}
+
+/// Returns the population and the hash of the pattern reduced into
+/// `buffer` by the `chs` shader.
+pub(crate) fn read_hash_buffer(buffer: &CpuAccessibleBuffer<[u32]>) -> (u64, u64) {
+    let data = buffer.read().unwrap();
+    let mut reduction = [0; 5];
+    reduction.copy_from_slice(&data[HASH_FACTORS.len()..]);
+
+    summarise_hash(reduction)
+}
+
+// Works like the `chs` shader on the CPU: returns the population, the
+// coordinates of the north west corner of the pattern and the sums of
+// the powers of the factors for each living cell
+fn hash_cells(cells: &[u8], width: usize) -> [u32; 5] {
+    let mut reduction = [0, u32::MAX, u32::MAX, 0, 0];
+
+    for (i, _) in cells.iter().enumerate().filter(|&(_, &cell)| cell == 255) {
+        let (x, y) = ((i % width) as u32, (i / width) as u32);
+        reduction[0] += 1;
+        reduction[1] = reduction[1].min(x);
+        reduction[2] = reduction[2].min(y);
+        for k in 0..2 {
+            let term = HASH_FACTORS[2 * k]
+                .wrapping_pow(x)
+                .wrapping_mul(HASH_FACTORS[2 * k + 1].wrapping_pow(y));
+            reduction[3 + k] = reduction[3 + k].wrapping_add(term);
+        }
+    }
+
+    reduction
+}
+
+// Returns the population and the hash of a reduction of the cells: the
+// sums are divided by the powers of the factors for the north west
+// corner of the pattern, as if it was at (0, 0)
+fn summarise_hash(reduction: [u32; 5]) -> (u64, u64) {
+    let [population, min_x, min_y, first, second] = reduction;
+    if population == 0 {
+        return (0, 0);
+    }
+
+    let mut sums = [first, second];
+    for (k, sum) in sums.iter_mut().enumerate() {
+        let inverse_x = inverse(HASH_FACTORS[2 * k]).wrapping_pow(min_x);
+        let inverse_y = inverse(HASH_FACTORS[2 * k + 1]).wrapping_pow(min_y);
+        *sum = sum.wrapping_mul(inverse_x).wrapping_mul(inverse_y);
+    }
+
+    (
+        u64::from(population),
+        u64::from(sums[0]) << 32 | u64::from(sums[1]),
+    )
+}
+
+// Returns the inverse of an odd number modulo 2^32, with Newton's method
+fn inverse(odd: u32) -> u32 {
+    let mut inverse = odd;
+    for _ in 0..5 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(odd.wrapping_mul(inverse)));
+    }
+
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash_cells, inverse, summarise_hash, HASH_FACTORS};
+
+    #[test]
+    fn test_inverse() {
+        for &factor in &HASH_FACTORS {
+            assert_eq!(1, factor.wrapping_mul(inverse(factor)));
+        }
+    }
+
+    #[test]
+    fn test_translation_invariant_hash() {
+        let glider = [0, 255, 0, 0, 0, 255, 255, 255, 255];
+        let mut moved = vec![0; 25];
+        for (i, &cell) in glider.iter().enumerate() {
+            moved[(i / 3 + 2) * 5 + i % 3 + 1] = cell;
+        }
+
+        let (population, hash) = summarise_hash(hash_cells(&glider, 3));
+        assert_eq!(5, population);
+        assert_eq!(hash, summarise_hash(hash_cells(&moved, 5)).1);
+
+        // A different pattern, or the same one reflected
+        let flipped = [0, 255, 0, 255, 0, 0, 255, 255, 255];
+        assert_ne!(hash, summarise_hash(hash_cells(&flipped, 3)).1);
+        assert_eq!((0, 0), summarise_hash(hash_cells(&[0; 9], 3)));
+    }
 }
@@ -0,0 +1,489 @@
+//! This module contains a batch of independent grids of the same size,
+//! whose generations are all computed by a single dispatch.
+//!
+//! It is meant for searches running many small grids, like soup searches:
+//! the grids are bit-packed and stored one after the other in the same
+//! buffer, and every generation also gives the population of each grid
+//! and whether it stabilised.
+
+extern crate rand;
+
+use std::sync::Arc;
+
+use rand::Rng;
+
+use super::vulkano::buffer::BufferUsage;
+use super::vulkano::buffer::CpuAccessibleBuffer;
+use super::vulkano::command_buffer::AutoCommandBufferBuilder;
+use super::vulkano::command_buffer::CommandBuffer;
+use super::vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use super::vulkano::device::Device;
+use super::vulkano::device::Queue;
+use super::vulkano::pipeline::ComputePipeline;
+use super::vulkano::sync::GpuFuture;
+
+use super::cpu;
+use super::vulkan::ngps;
+use context::Context;
+use error::GridErrorKind;
+use packed::{pack_u32, unpack_u32};
+use processing::{STATS_CHANGES, STATS_POPULATION};
+use topology::Topology;
+use Grid;
+
+/// This struct contains `count` life cellular automaton grids of the
+/// same size, topology and rules, computed together on the GPU.
+///
+/// The grids keep their size: a resizable topology behaves like a plane.
+/// The stochastic rules aren't supported.
+pub struct GridBatch {
+    topology: Topology,
+    survival: Arc<CpuAccessibleBuffer<[u32]>>,
+    birth: Arc<CpuAccessibleBuffer<[u32]>>,
+
+    width: usize,
+    height: usize,
+    count: usize,
+    generation: u64,
+
+    // The current generation, and the previous one once computed
+    cells: Arc<CpuAccessibleBuffer<[u32]>>,
+    previous_cells: Arc<CpuAccessibleBuffer<[u32]>>,
+    // Number of generations computed since the last modification
+    // of each grid
+    histories: Vec<u64>,
+
+    populations: Vec<u64>,
+    periods: Vec<Option<u32>>,
+
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl GridBatch {
+    /// Returns a new `GridBatch` of `count` empty `width` x `height` grids
+    /// whose edges are joined according to `topology`, containing the
+    /// rules given by `srvl` and `brth`.
+    ///
+    /// Their generations are computed on the first discrete GPU.
+    pub fn new(
+        topology: Topology,
+        srvl: &[u32],
+        brth: &[u32],
+        width: usize,
+        height: usize,
+        count: usize,
+    ) -> GridBatch {
+        GridBatch::with_context(&Context::new(), topology, srvl, brth, width, height, count)
+    }
+
+    /// Returns a new `GridBatch` like `new`, whose generations are
+    /// computed on the device of `context`.
+    pub fn with_context(
+        context: &Context,
+        topology: Topology,
+        srvl: &[u32],
+        brth: &[u32],
+        width: usize,
+        height: usize,
+        count: usize,
+    ) -> GridBatch {
+        let (device, queue) = (context.get_device(), context.get_queue());
+
+        let new_buffer = || {
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::all(),
+                (0..count * width.div_ceil(32) * height).map(|_| 0u32),
+            )
+            .expect("failed to create buffer")
+        };
+        let (cells, previous_cells) = (new_buffer(), new_buffer());
+
+        let survival = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            srvl.iter().cloned(),
+        )
+        .expect("failed to create buffer");
+
+        let birth = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            brth.iter().cloned(),
+        )
+        .expect("failed to create buffer");
+
+        GridBatch {
+            topology,
+            survival,
+            birth,
+            width,
+            height,
+            count,
+            generation: 0,
+            cells,
+            previous_cells,
+            histories: vec![0; count],
+            populations: vec![0; count],
+            periods: vec![None; count],
+            device,
+            queue,
+        }
+    }
+
+    /// Returns a new `GridBatch` containing the cells of `grids`.
+    ///
+    /// Returns an error if the grids don't all have the same size,
+    /// topology and rules, or if there is no grid.
+    pub fn from_grids(grids: &[Grid]) -> Result<GridBatch, GridErrorKind> {
+        let first = grids.first().ok_or(GridErrorKind::IncompatibleGrids)?;
+
+        let mut batch = GridBatch::new(
+            first.get_topology(),
+            &first.get_survival(),
+            &first.get_birth(),
+            first.get_width(),
+            first.get_height(),
+            grids.len(),
+        );
+        for (i, grid) in grids.iter().enumerate() {
+            batch.set_grid(i, grid)?;
+        }
+
+        Ok(batch)
+    }
+
+    /// Returns the topology of the grids.
+    pub fn get_topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Returns the survival conditions of the cellular automaton.
+    pub fn get_survival(&self) -> Vec<u32> {
+        self.survival.read().unwrap().to_vec()
+    }
+
+    /// Returns the birth conditions of the cellular automaton.
+    pub fn get_birth(&self) -> Vec<u32> {
+        self.birth.read().unwrap().to_vec()
+    }
+
+    /// Returns the width of the grids.
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the grids.
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of grids.
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the number of generations computed.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns a new `Grid` containing the cells of the grid `i`,
+    /// using the file format `frmt`.
+    pub fn get_grid(&self, i: usize, frmt: &String) -> Result<Grid, GridErrorKind> {
+        let words = self.grid_words(i)?;
+
+        let mut grid = Grid::new(
+            frmt,
+            self.topology,
+            &self.get_survival(),
+            &self.get_birth(),
+            self.width,
+            self.height,
+        );
+        grid.write_cells(&unpack_u32(
+            &self.cells.read().unwrap()[words],
+            self.width,
+            self.height,
+        ));
+
+        Ok(grid)
+    }
+
+    /// Replaces the cells of the grid `i` with the cells of `grid`.
+    ///
+    /// Returns an error if `grid` doesn't have the same size, topology
+    /// and rules as the batch.
+    pub fn set_grid(&mut self, i: usize, grid: &Grid) -> Result<(), GridErrorKind> {
+        let compatible = grid.get_width() == self.width
+            && grid.get_height() == self.height
+            && grid.get_topology() == self.topology
+            && grid.get_survival() == self.get_survival()
+            && grid.get_birth() == self.get_birth();
+        if !compatible {
+            return Err(GridErrorKind::IncompatibleGrids);
+        }
+
+        let words = self.grid_words(i)?;
+        self.cells.write().unwrap()[words]
+            .copy_from_slice(&pack_u32(&grid.read_cells(), self.width));
+        self.modified(i);
+
+        Ok(())
+    }
+
+    /// Returns the state of the cell at the coordinates (`x`, `y`) of
+    /// the grid `i`, or `0` if they are out of bounds.
+    pub fn get_cell_state(&self, i: usize, x: usize, y: usize) -> u8 {
+        match self.word_index(i, x, y) {
+            Some(index) if self.cells.read().unwrap()[index] & (1 << (x % 32)) != 0 => 255,
+            _ => 0,
+        }
+    }
+
+    /// Modifies the state of the cell at the coordinates (`x`, `y`) of
+    /// the grid `i` with `state`. Any state other than `0` is a living cell.
+    pub fn set_cell_state(
+        &mut self,
+        i: usize,
+        x: usize,
+        y: usize,
+        state: u8,
+    ) -> Result<(), GridErrorKind> {
+        let index = self
+            .word_index(i, x, y)
+            .ok_or(GridErrorKind::OutOfBoundCoords)?;
+
+        {
+            let mut cells = self.cells.write().unwrap();
+            if state != 0 {
+                cells[index] |= 1 << (x % 32);
+            } else {
+                cells[index] &= !(1 << (x % 32));
+            }
+        }
+        self.modified(i);
+
+        Ok(())
+    }
+
+    /// Fills the `soup_width` x `soup_height` region at the center of
+    /// every grid with random cells, and kills the cells outside of it.
+    pub fn randomize_soups(&mut self, soup_width: usize, soup_height: usize) {
+        let mut rng = rand::thread_rng();
+
+        let (soup_width, soup_height) = (soup_width.min(self.width), soup_height.min(self.height));
+        let x0 = (self.width - soup_width) / 2;
+        let y0 = (self.height - soup_height) / 2;
+
+        let mut cells = vec![0; self.width * self.height];
+        let mut words = Vec::with_capacity(self.count * self.width.div_ceil(32) * self.height);
+        for _ in 0..self.count {
+            for y in y0..y0 + soup_height {
+                for x in x0..x0 + soup_width {
+                    cells[y * self.width + x] = if rng.gen::<bool>() { 255 } else { 0 };
+                }
+            }
+            words.extend(pack_u32(&cells, self.width));
+        }
+
+        self.cells.write().unwrap().copy_from_slice(&words);
+        for i in 0..self.count {
+            self.modified(i);
+        }
+    }
+
+    /// Returns the number of living cells of the grid `i`.
+    pub fn get_population(&self, i: usize) -> u64 {
+        self.populations[i]
+    }
+
+    /// Returns the period of the grid `i` if it stabilised during the
+    /// last generation: `Some(1)` if no cell changed, `Some(2)` if it came
+    /// back to the state it had two generations ago.
+    ///
+    /// Longer periods aren't detected.
+    pub fn get_period(&self, i: usize) -> Option<u32> {
+        self.periods[i]
+    }
+
+    /// Returns `true` if the grid `i` stabilised (see `get_period`).
+    pub fn is_stable(&self, i: usize) -> bool {
+        self.periods[i].is_some()
+    }
+
+    /// Computes the `n` next generations of every grid, with one dispatch
+    /// per generation, and updates them along with their populations and
+    /// periods.
+    pub fn step(&mut self, n: u64) {
+        if n == 0 || self.count == 0 {
+            return;
+        }
+
+        let words_per_row = self.width.div_ceil(32);
+
+        let topology = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            self.topology.as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let stochastic = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            cpu::Stochastic {
+                birth: u32::MAX,
+                survival: u32::MAX,
+                update: u32::MAX,
+                seed: 0,
+                generation: 0,
+            }
+            .as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        // Only the statistics of the last generation are computed
+        let sizes: Vec<_> = [0, STATS_POPULATION | STATS_CHANGES]
+            .iter()
+            .map(|&stats| {
+                CpuAccessibleBuffer::from_data(
+                    self.device.clone(),
+                    BufferUsage::all(),
+                    [
+                        self.width as i32,
+                        self.height as i32,
+                        words_per_row as i32,
+                        stats,
+                    ],
+                )
+                .expect("failed to create buffer")
+            })
+            .collect();
+
+        let stats = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..3 * self.count).map(|_| 0u32),
+        )
+        .expect("failed to create buffer");
+
+        let shader =
+            ngps::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let mut builder =
+            AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family()).unwrap();
+
+        let mut buffers = [self.cells.clone(), self.previous_cells.clone()];
+        for i in 0..n {
+            let last = i == n - 1;
+            if last {
+                builder = builder.fill_buffer(stats.clone(), 0).unwrap();
+            }
+
+            let set = Arc::new(
+                PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                    .add_buffer(buffers[0].clone())
+                    .unwrap()
+                    .add_buffer(buffers[1].clone())
+                    .unwrap()
+                    .add_buffer(topology.clone())
+                    .unwrap()
+                    .add_buffer(self.survival.clone())
+                    .unwrap()
+                    .add_buffer(self.birth.clone())
+                    .unwrap()
+                    .add_buffer(stochastic.clone())
+                    .unwrap()
+                    .add_buffer(sizes[last as usize].clone())
+                    .unwrap()
+                    .add_buffer(stats.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
+
+            builder = builder
+                .dispatch(
+                    [
+                        words_per_row.div_ceil(8) as u32,
+                        self.height.div_ceil(8) as u32,
+                        self.count as u32,
+                    ],
+                    compute_pipeline.clone(),
+                    set,
+                    (),
+                )
+                .unwrap();
+
+            buffers.swap(0, 1);
+        }
+
+        let finished = builder
+            .build()
+            .unwrap()
+            .execute(self.queue.clone())
+            .unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let [cells, previous_cells] = buffers;
+        self.cells = cells;
+        self.previous_cells = previous_cells;
+        self.generation += n;
+
+        let stats = stats.read().unwrap();
+        for (i, grid_stats) in stats.chunks(3).enumerate() {
+            self.histories[i] += n;
+            self.populations[i] = u64::from(grid_stats[0]);
+            self.periods[i] = if grid_stats[1] == 0 {
+                Some(1)
+            } else if grid_stats[2] == 0 && self.histories[i] >= 2 {
+                Some(2)
+            } else {
+                None
+            };
+        }
+    }
+
+    // Returns the range of the words of the grid `i`
+    fn grid_words(&self, i: usize) -> Result<::std::ops::Range<usize>, GridErrorKind> {
+        if i >= self.count {
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+
+        let len = self.width.div_ceil(32) * self.height;
+        Ok(i * len..(i + 1) * len)
+    }
+
+    // Returns the index of the word containing the cell at (`x`, `y`)
+    // of the grid `i`
+    fn word_index(&self, i: usize, x: usize, y: usize) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        self.grid_words(i)
+            .ok()
+            .map(|words| words.start + y * self.width.div_ceil(32) + x / 32)
+    }
+
+    // Updates the statistics of the grid `i` after a modification
+    fn modified(&mut self, i: usize) {
+        let words = self.grid_words(i).unwrap(); // Shouldn't fail
+        self.populations[i] = self.cells.read().unwrap()[words]
+            .iter()
+            .map(|word| u64::from(word.count_ones()))
+            .sum();
+        self.periods[i] = None;
+        self.histories[i] = 0;
+    }
+}
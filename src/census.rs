@@ -0,0 +1,413 @@
+//! This module contains the census of the objects of a grid: its pattern
+//! is split into connected objects, which are identified by their
+//! apgcodes (like `xs4_33` for a block, `xp2_7` for a blinker or `xq4_153`
+//! for a glider).
+//!
+//! An apgcode is made of a prefix giving the kind of the object (`xs`
+//! followed by the population for a still life, `xp` or `xq` followed by
+//! the period for an oscillator or a spaceship) and of the canonical
+//! extended Wechsler format of the object: the shortest, then
+//! lexicographically smallest encoding among its phases and its 8
+//! orientations.
+
+use std::collections::{HashMap, VecDeque};
+
+use classification::{BoxCells, Classification};
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// The apgcode given to the objects which don't repeat within the maximum
+/// period.
+pub const PATHOLOGICAL: &str = "PATHOLOGICAL";
+
+/// The characters of the extended Wechsler format: a column of 5 cells is
+/// encoded by one of the first 32 characters, and the number of dead
+/// columns after a `y` by one of the first 36 characters.
+const WECHSLER_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// A connected object of a grid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Object {
+    x: i64,
+    y: i64,
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+}
+
+impl Object {
+    /// Returns the coordinates of the north west corner of the bounding
+    /// box of the object. They can be negative if the object crosses the
+    /// edges of a grid which wraps around.
+    pub fn get_position(&self) -> (i64, i64) {
+        (self.x, self.y)
+    }
+
+    /// Returns the width of the bounding box of the object.
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the bounding box of the object.
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the cells of the bounding box of the object, the cells of
+    /// the other objects being dead.
+    pub fn get_cells(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// Returns the number of living cells of the object.
+    pub fn get_population(&self) -> u64 {
+        self.cells.iter().filter(|&&cell| cell != 0).count() as u64
+    }
+
+    /// Returns a hash of the object which doesn't depend on its position
+    /// nor on its orientation. It is the same across runs and platforms,
+    /// so that it can be stored.
+    pub fn get_canonical_hash(&self) -> u64 {
+        canonical_hash(self.width, self.height, &self.cells)
+    }
+}
+
+impl Grid {
+    /// Splits the pattern of the current `Grid` into connected objects.
+    /// Two living cells belong to the same object if they are at most
+    /// `distance` cells apart horizontally and vertically: `1` gives the
+    /// usual 8 neighbours, and a greater distance keeps together the
+    /// objects of a pseudo-object.
+    ///
+    /// The objects crossing the edges of a grid which wraps around aren't
+    /// split.
+    pub fn split_objects(&self, distance: usize) -> Vec<Object> {
+        split(
+            &self.read_cells(),
+            self.width,
+            self.height,
+            self.topology,
+            distance,
+        )
+    }
+
+    /// Returns a new resizable `Grid` with the rules of the current
+    /// `Grid`, only containing `object`.
+    pub fn isolate(&self, object: &Object) -> Grid {
+        let mut isolated = Grid::with_context(
+            &self.get_context(),
+            &self.get_format(),
+            Topology::Resizable,
+            &self.get_survival(),
+            &self.get_birth(),
+            object.width,
+            object.height,
+        );
+        isolated.write_cells(&object.cells);
+
+        isolated
+    }
+
+    /// Splits the pattern of the current `Grid` into objects (see
+    /// `split_objects`), runs each of them alone for up to `max_period`
+    /// generations, and counts them by apgcode. The objects which don't
+    /// repeat are counted as `PATHOLOGICAL`, and the ones which die out
+    /// aren't counted.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedRule)` if the rules are
+    /// stochastic.
+    pub fn census(
+        &self,
+        distance: usize,
+        max_period: u64,
+    ) -> Result<HashMap<String, u64>, GridErrorKind> {
+        if !self.is_deterministic() {
+            return Err(GridErrorKind::UnsupportedRule);
+        }
+
+        let mut census = HashMap::new();
+        for object in self.split_objects(distance) {
+            let (classification, phases) = self.isolate(&object).find_cycle(max_period)?;
+            if classification == Classification::Extinct {
+                continue;
+            }
+
+            *census.entry(apgcode(classification, &phases)).or_insert(0) += 1;
+        }
+
+        Ok(census)
+    }
+}
+
+/// Returns the apgcode of a pattern, given its classification and the
+/// bounding boxes of the generations of its cycle (see `find_cycle`).
+pub(crate) fn apgcode(classification: Classification, phases: &[BoxCells]) -> String {
+    let prefix = match classification {
+        Classification::Extinct => return String::from("xs0_0"),
+        Classification::Unknown => return String::from(PATHOLOGICAL),
+        Classification::StillLife => {
+            let (_, _, ref cells) = phases[0];
+            format!("xs{}", cells.iter().filter(|&&cell| cell != 0).count())
+        }
+        Classification::Oscillator { period } => format!("xp{}", period),
+        Classification::Spaceship { period, .. } => format!("xq{}", period),
+    };
+
+    let code = phases
+        .iter()
+        .map(|&(width, height, ref cells)| canonical_wechsler(width, height, cells))
+        .min_by(|a, b| (a.len(), a).cmp(&(b.len(), b)))
+        .unwrap_or_default();
+
+    format!("{}_{}", prefix, code)
+}
+
+/// Returns a hash of the canonical extended Wechsler format of a `width`
+/// x `height` bounding box. The 64-bit FNV-1a hash is used so that it
+/// can be stored: it doesn't change between Rust releases.
+pub(crate) fn canonical_hash(width: usize, height: usize, cells: &[u8]) -> u64 {
+    canonical_wechsler(width, height, cells)
+        .bytes()
+        .fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+        })
+}
+
+/// Returns the canonical extended Wechsler format of a `width` x `height`
+/// bounding box: the shortest, then lexicographically smallest encoding
+/// among its 8 orientations.
+pub(crate) fn canonical_wechsler(width: usize, height: usize, cells: &[u8]) -> String {
+    canonical_orientation(width, height, cells).0
+}
+
+/// Returns the canonical extended Wechsler format of a `width` x `height`
+/// bounding box, and the bounding box in the orientation it encodes.
+pub(crate) fn canonical_orientation(
+    width: usize,
+    height: usize,
+    cells: &[u8],
+) -> (String, BoxCells) {
+    let mut canonical: Option<(String, BoxCells)> = None;
+
+    for &(transposed, flip_x, flip_y) in &[
+        (false, false, false),
+        (false, true, false),
+        (false, false, true),
+        (false, true, true),
+        (true, false, false),
+        (true, true, false),
+        (true, false, true),
+        (true, true, true),
+    ] {
+        let (new_width, new_height) = if transposed {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        let oriented: Vec<u8> = (0..new_width * new_height)
+            .map(|i| {
+                let (x, y) = (i % new_width, i / new_width);
+                let (mut x, mut y) = if transposed { (y, x) } else { (x, y) };
+                if flip_x {
+                    x = width - 1 - x;
+                }
+                if flip_y {
+                    y = height - 1 - y;
+                }
+                cells[y * width + x]
+            })
+            .collect();
+
+        let code = wechsler(new_width, new_height, &oriented);
+        canonical = match canonical {
+            Some((ref best, _)) if (best.len(), best) <= (code.len(), &code) => canonical,
+            _ => Some((code, (new_width, new_height, oriented))),
+        };
+    }
+
+    canonical.unwrap()
+}
+
+/// Returns the extended Wechsler format of a `width` x `height` bounding
+/// box: the rows are grouped by strips of 5, separated by `z`, and each
+/// strip is encoded column by column. The runs of dead columns are
+/// abbreviated by `w` (2 columns), `x` (3 columns) or `y` followed by a
+/// character (4 to 39 columns), and the ones ending a strip are omitted.
+pub(crate) fn wechsler(width: usize, height: usize, cells: &[u8]) -> String {
+    let mut code = String::new();
+
+    for strip in 0..height.div_ceil(5) {
+        if strip > 0 {
+            code.push('z');
+        }
+
+        let mut dead_columns = 0;
+        for x in 0..width {
+            let column = (0..5)
+                .filter(|&row| {
+                    let y = 5 * strip + row;
+                    y < height && cells[y * width + x] != 0
+                })
+                .fold(0, |column, row| column | 1 << row);
+
+            if column == 0 {
+                dead_columns += 1;
+                if dead_columns == 39 {
+                    code.push_str("yz");
+                    dead_columns = 0;
+                }
+                continue;
+            }
+
+            match dead_columns {
+                0 => {}
+                1 => code.push('0'),
+                2 => code.push('w'),
+                3 => code.push('x'),
+                n => {
+                    code.push('y');
+                    code.push(WECHSLER_CHARS[n - 4] as char);
+                }
+            }
+            dead_columns = 0;
+            code.push(WECHSLER_CHARS[column] as char);
+        }
+    }
+
+    code
+}
+
+// Returns the objects of `cells`, in which the living cells at most
+// `distance` cells apart are connected
+fn split(
+    cells: &[u8],
+    width: usize,
+    height: usize,
+    topology: Topology,
+    distance: usize,
+) -> Vec<Object> {
+    let distance = distance as i64;
+    let mut visited = vec![false; cells.len()];
+    let mut objects = Vec::new();
+
+    for start in 0..cells.len() {
+        if cells[start] == 0 || visited[start] {
+            continue;
+        }
+
+        // The coordinates of the cells crossing the edges are unwrapped
+        // from the first cell, so that the object stays in one piece
+        let start_coords = ((start % width) as i64, (start / width) as i64);
+        let mut queue = VecDeque::new();
+        let mut members = Vec::new();
+        visited[start] = true;
+        queue.push_back((start_coords, start_coords));
+
+        while let Some(((x, y), (ux, uy))) = queue.pop_front() {
+            members.push((ux, uy));
+
+            for dy in -distance..=distance {
+                for dx in -distance..=distance {
+                    let (nx, ny) = match topology.map(x + dx, y + dy, width, height) {
+                        Some(coords) => coords,
+                        None => continue,
+                    };
+                    let i = ny * width + nx;
+                    if cells[i] != 0 && !visited[i] {
+                        visited[i] = true;
+                        queue.push_back(((nx as i64, ny as i64), (ux + dx, uy + dy)));
+                    }
+                }
+            }
+        }
+
+        let min_x = members.iter().map(|&(x, _)| x).min().unwrap();
+        let max_x = members.iter().map(|&(x, _)| x).max().unwrap();
+        let min_y = members.iter().map(|&(_, y)| y).min().unwrap();
+        let max_y = members.iter().map(|&(_, y)| y).max().unwrap();
+        let (object_width, object_height) =
+            ((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize);
+
+        let mut object_cells = vec![0; object_width * object_height];
+        for (x, y) in members {
+            object_cells[(y - min_y) as usize * object_width + (x - min_x) as usize] = 255;
+        }
+
+        objects.push(Object {
+            x: min_x,
+            y: min_y,
+            width: object_width,
+            height: object_height,
+            cells: object_cells,
+        });
+    }
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_hash, canonical_wechsler, split, wechsler};
+
+    use topology::Topology;
+
+    fn pattern(rows: &[&str]) -> (usize, usize, Vec<u8>) {
+        let cells = rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| if c == '*' { 255 } else { 0 }))
+            .collect();
+        (rows[0].len(), rows.len(), cells)
+    }
+
+    #[test]
+    fn test_wechsler() {
+        let (width, height, cells) = pattern(&["**", "**"]);
+        assert_eq!("33", wechsler(width, height, &cells));
+
+        let (width, height, cells) = pattern(&[
+            "*......*", "........", "........", "........", "........", "..*.....",
+        ]);
+        assert_eq!("1y21zw1", wechsler(width, height, &cells));
+
+        let (width, height, cells) = pattern(&["***"]);
+        assert_eq!("7", canonical_wechsler(width, height, &cells));
+
+        let (width, height, cells) = pattern(&[".*.", "..*", "***"]);
+        assert_eq!("153", canonical_wechsler(width, height, &cells));
+    }
+
+    #[test]
+    fn test_canonical_hash() {
+        let (width, height, cells) = pattern(&["**", "**"]);
+        assert_eq!(0x07FF_7D07_B4C0_03A3, canonical_hash(width, height, &cells));
+
+        // The hash doesn't depend on the orientation, here a reflection
+        let (width, height, cells) = pattern(&["*..", ".**", "**."]);
+        let (reflected_width, reflected_height, reflected_cells) = pattern(&["*.*", ".**", ".*."]);
+        assert_eq!(
+            canonical_hash(width, height, &cells),
+            canonical_hash(reflected_width, reflected_height, &reflected_cells)
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let (width, height, cells) = pattern(&["**..*", "**...", ".....", "*...*"]);
+
+        let objects = split(&cells, width, height, Topology::Plane, 1);
+        assert_eq!(4, objects.len());
+        assert_eq!((0, 0), objects[0].get_position());
+        assert_eq!(4, objects[0].get_population());
+
+        // A greater distance merges the close objects
+        assert_eq!(3, split(&cells, width, height, Topology::Plane, 2).len());
+
+        // The objects crossing the edges of a torus aren't split
+        let objects = split(&cells, width, height, Topology::torus(), 1);
+        assert_eq!(1, objects.len());
+        assert_eq!((-1, -1), objects[0].get_position());
+        assert_eq!((3, 3), (objects[0].get_width(), objects[0].get_height()));
+        assert_eq!(7, objects[0].get_population());
+    }
+}
@@ -0,0 +1,207 @@
+//! This module contains the detection of still lifes, oscillators and
+//! spaceships.
+//!
+//! The pattern is run forward, and each generation is identified by the
+//! cells of its bounding box: the pattern is periodic as soon as the same
+//! cells appear again, and it moved if the bounding box moved.
+
+use std::collections::HashMap;
+
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// The size and the cells of the bounding box of a generation.
+pub(crate) type BoxCells = (usize, usize, Vec<u8>);
+
+/// The kind of pattern found by `Grid::classify`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Classification {
+    /// The pattern dies out.
+    Extinct,
+    /// The pattern doesn't change.
+    StillLife,
+    /// The pattern comes back to the same state after `period`
+    /// generations.
+    Oscillator { period: u64 },
+    /// The pattern comes back to the same state after `period`
+    /// generations, moved by `dx` cells to the right and `dy` cells
+    /// downwards.
+    Spaceship { period: u64, dx: i64, dy: i64 },
+    /// The pattern didn't repeat within the maximum period.
+    Unknown,
+}
+
+impl Classification {
+    /// Returns the period of the pattern, 1 for a still life.
+    pub fn get_period(&self) -> Option<u64> {
+        match *self {
+            Classification::StillLife => Some(1),
+            Classification::Oscillator { period } | Classification::Spaceship { period, .. } => {
+                Some(period)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the speed of a spaceship, in the usual notation: `"c/4
+    /// diagonal"`, `"c/2 orthogonal"`, or `"(2,1)c/6"` for an oblique
+    /// spaceship.
+    pub fn get_speed(&self) -> Option<String> {
+        let (period, dx, dy) = match *self {
+            Classification::Spaceship { period, dx, dy } => {
+                (period, dx.unsigned_abs(), dy.unsigned_abs())
+            }
+            _ => return None,
+        };
+
+        if dx != 0 && dy != 0 && dx != dy {
+            return Some(format!("({},{})c/{}", dx.max(dy), dx.min(dy), period));
+        }
+
+        let distance = dx.max(dy);
+        let divisor = gcd(distance, period);
+        let (distance, period) = (distance / divisor, period / divisor);
+        let direction = if dx == dy { "diagonal" } else { "orthogonal" };
+
+        if distance == 1 {
+            Some(format!("c/{} {}", period, direction))
+        } else {
+            Some(format!("{}c/{} {}", distance, period, direction))
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Grid {
+    /// Runs a copy of the current `Grid` forward for up to `max_period`
+    /// generations, and tells whether its pattern is a still life, an
+    /// oscillator or a spaceship. The pattern may reach its cycle after a
+    /// few generations.
+    ///
+    /// On the topologies which wrap around, the displacement of a spaceship
+    /// is the shortest one.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedRule)` if the rules are
+    /// stochastic.
+    pub fn classify(&self, max_period: u64) -> Result<Classification, GridErrorKind> {
+        self.find_cycle(max_period)
+            .map(|(classification, _)| classification)
+    }
+
+    /// Works like `classify`, and also returns the bounding boxes of the
+    /// generations of the cycle, from the first one.
+    pub(crate) fn find_cycle(
+        &self,
+        max_period: u64,
+    ) -> Result<(Classification, Vec<BoxCells>), GridErrorKind> {
+        if !self.is_deterministic() {
+            return Err(GridErrorKind::UnsupportedRule);
+        }
+
+        let mut grid = self.clone();
+        let (width, height) = (grid.width as i64, grid.height as i64);
+        let wraps = (
+            grid.topology.map(-1, 0, grid.width, grid.height).is_some(),
+            grid.topology.map(0, -1, grid.width, grid.height).is_some(),
+        );
+
+        // Bounding box of each generation, with the generation and the
+        // position of the box
+        let mut seen: HashMap<BoxCells, (u64, i64, i64)> = HashMap::new();
+        let mut boxes: Vec<BoxCells> = Vec::new();
+        // Translation of the cells by the resizing of the grid
+        let mut translation = (0, 0);
+
+        for generation in 0..=max_period {
+            let (min_x, max_x, min_y, max_y) = match grid.compute_pattern_boundaries() {
+                (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) => {
+                    (min_x, max_x, min_y, max_y)
+                }
+                _ => return Ok((Classification::Extinct, Vec::new())),
+            };
+            let (box_width, box_height) = (max_x - min_x + 1, max_y - min_y + 1);
+            let cells = grid.read_region(min_x, min_y, box_width, box_height);
+            let (x, y) = (min_x as i64 - translation.0, min_y as i64 - translation.1);
+
+            if let Some(&(first, first_x, first_y)) =
+                seen.get(&(box_width, box_height, cells.clone()))
+            {
+                let period = generation - first;
+                let dx = shortest(x - first_x, width, wraps.0);
+                let dy = shortest(y - first_y, height, wraps.1);
+
+                let classification = match (period, dx, dy) {
+                    (1, 0, 0) => Classification::StillLife,
+                    (period, 0, 0) => Classification::Oscillator { period },
+                    (period, dx, dy) => Classification::Spaceship { period, dx, dy },
+                };
+                return Ok((classification, boxes.split_off(first as usize)));
+            }
+            boxes.push((box_width, box_height, cells.clone()));
+            seen.insert((box_width, box_height, cells), (generation, x, y));
+
+            // The next generation starts by moving the pattern of unbounded
+            // grids next to their north west corner (see `recenter_pattern`)
+            match grid.topology {
+                Topology::Resizable => {
+                    translation.0 += 1 - min_x as i64;
+                    translation.1 += 1 - min_y as i64;
+                }
+                Topology::Cylinder => translation.1 += 1 - min_y as i64,
+                _ => {}
+            }
+            if generation < max_period {
+                grid.next_gen();
+            }
+        }
+
+        Ok((Classification::Unknown, Vec::new()))
+    }
+}
+
+// Returns the shortest displacement equivalent to `d` on an axis of
+// length `length`, if it wraps around
+fn shortest(d: i64, length: i64, wraps: bool) -> i64 {
+    if !wraps {
+        return d;
+    }
+
+    let d = d.rem_euclid(length);
+    if d > length / 2 {
+        d - length
+    } else {
+        d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shortest, Classification};
+
+    #[test]
+    fn test_speeds() {
+        let speed = |period, dx, dy| Classification::Spaceship { period, dx, dy }.get_speed();
+
+        assert_eq!(Some("c/4 diagonal".to_string()), speed(4, 1, -1));
+        assert_eq!(Some("c/2 orthogonal".to_string()), speed(4, -2, 0));
+        assert_eq!(Some("2c/5 orthogonal".to_string()), speed(5, 0, 2));
+        assert_eq!(Some("(2,1)c/6".to_string()), speed(6, 1, 2));
+        assert_eq!(None, Classification::StillLife.get_speed());
+        assert_eq!(Some(1), Classification::StillLife.get_period());
+    }
+
+    #[test]
+    fn test_shortest_displacement() {
+        assert_eq!(-1, shortest(15, 16, true));
+        assert_eq!(15, shortest(15, 16, false));
+        assert_eq!(3, shortest(-13, 16, true));
+    }
+}
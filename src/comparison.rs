@@ -0,0 +1,78 @@
+//! This module contains some methods to compare grids by their cells, and
+//! patterns regardless of their position and orientation, so that
+//! collections of patterns can be deduplicated.
+
+use census;
+use topology::Topology;
+use Grid;
+
+impl Grid {
+    /// Returns `true` if `other` has the same size and the same cells as
+    /// the current `Grid`, whatever their topologies and rules.
+    pub fn same_cells(&self, other: &Grid) -> bool {
+        (self.width, self.height) == (other.width, other.height)
+            && self.read_cells() == other.read_cells()
+    }
+
+    /// Returns `true` if the pattern of `other` is the pattern of the
+    /// current `Grid`, moved, rotated or reflected.
+    pub fn same_pattern(&self, other: &Grid) -> bool {
+        self.canonical_pattern().0 == other.canonical_pattern().0
+    }
+
+    /// Returns a hash of the pattern of the current `Grid` which doesn't
+    /// depend on its position. It is computed on the GPU when possible.
+    pub fn get_pattern_hash(&self) -> u64 {
+        self.summarise_pattern().1
+    }
+
+    /// Returns a hash of the pattern of the current `Grid` which depends
+    /// neither on its position nor on its orientation. It is the same
+    /// across runs and platforms, so that it can be stored.
+    pub fn get_canonical_hash(&self) -> u64 {
+        let (width, height, cells) = self.pattern_box();
+        census::canonical_hash(width, height, &cells)
+    }
+
+    /// Returns a new resizable `Grid` with the rules of the current `Grid`,
+    /// containing its pattern in a canonical orientation among the 8
+    /// symmetries of the square: the one giving the smallest extended
+    /// Wechsler format (see the `census` module).
+    pub fn get_canonical_form(&self) -> Grid {
+        let (_, (width, height, cells)) = self.canonical_pattern();
+
+        let mut canonical = Grid::with_context(
+            &self.get_context(),
+            &self.get_format(),
+            Topology::Resizable,
+            &self.get_survival(),
+            &self.get_birth(),
+            width.max(1),
+            height.max(1),
+        );
+        if !cells.is_empty() {
+            canonical.write_cells(&cells);
+        }
+
+        canonical
+    }
+
+    // Returns the canonical extended Wechsler format of the pattern, and
+    // its bounding box in the orientation it encodes
+    fn canonical_pattern(&self) -> (String, (usize, usize, Vec<u8>)) {
+        let (width, height, cells) = self.pattern_box();
+        census::canonical_orientation(width, height, &cells)
+    }
+
+    // Returns the size and the cells of the bounding box of the pattern,
+    // which is empty without any living cell
+    fn pattern_box(&self) -> (usize, usize, Vec<u8>) {
+        match self.compute_pattern_boundaries() {
+            (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) => {
+                let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+                (width, height, self.read_region(min_x, min_y, width, height))
+            }
+            _ => (0, 0, Vec::new()),
+        }
+    }
+}
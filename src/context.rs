@@ -0,0 +1,60 @@
+//! This module contains the Vulkan device used to compute the
+//! generations of a grid, so that several grids can share a device or
+//! be spread over several devices.
+
+use std::sync::Arc;
+
+use super::vulkano::device::Device;
+use super::vulkano::device::Queue;
+
+use super::vulkan;
+
+/// A Vulkan device and the queue the computations are submitted to.
+///
+/// Cloning a `Context` gives another handle on the same device.
+#[derive(Clone)]
+pub struct Context {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl Context {
+    /// Returns a new `Context` on the first discrete GPU.
+    pub fn new() -> Context {
+        let (device, queue) = vulkan::vk_init();
+
+        Context { device, queue }
+    }
+
+    /// Returns a new `Context` for every device which can compute the
+    /// generations, including integrated and software devices.
+    pub fn enumerate() -> Vec<Context> {
+        vulkan::vk_init_all()
+            .into_iter()
+            .map(|(device, queue)| Context { device, queue })
+            .collect()
+    }
+
+    /// Returns the name of the device.
+    pub fn get_device_name(&self) -> String {
+        self.device.physical_device().name()
+    }
+
+    pub(crate) fn from_parts(device: Arc<Device>, queue: Arc<Queue>) -> Context {
+        Context { device, queue }
+    }
+
+    pub(crate) fn get_device(&self) -> Arc<Device> {
+        self.device.clone()
+    }
+
+    pub(crate) fn get_queue(&self) -> Arc<Queue> {
+        self.queue.clone()
+    }
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        Context::new()
+    }
+}
@@ -0,0 +1,321 @@
+//! This module contains a CPU implementation of the rules applied by
+//! the `ngs` shader, so that the same generations can be computed
+//! without a GPU.
+//!
+//! Stochastic rules draw their random numbers from a counter-based
+//! generator: the random number of a cell only depends on the seed,
+//! the generation and the coordinates of the cell. This is what makes
+//! the CPU and the GPU produce exactly the same generations from a seed.
+
+use topology::Topology;
+
+/// Stream used to decide whether a cell is born or survives.
+pub const RULE_STREAM: u32 = 0;
+/// Stream used to decide whether a cell is updated at all.
+pub const UPDATE_STREAM: u32 = 1;
+
+/// Parameters of the stochastic rules, as they are given to the shader.
+#[derive(Clone, Copy, Debug)]
+pub struct Stochastic {
+    pub birth: u32,
+    pub survival: u32,
+    pub update: u32,
+    pub seed: u64,
+    pub generation: u64,
+}
+
+impl Stochastic {
+    /// Returns the content of the `Stochastic` buffer of the `ngs` shader.
+    pub fn as_shader_data(&self) -> [u32; 7] {
+        [
+            self.birth,
+            self.survival,
+            self.update,
+            self.seed as u32,
+            (self.seed >> 32) as u32,
+            self.generation as u32,
+            (self.generation >> 32) as u32,
+        ]
+    }
+}
+
+/// Converts a probability into a threshold a random `u32` is compared to.
+///
+/// A probability of 1 is mapped to `u32::MAX`, which `chance`
+/// treats as "always".
+pub fn probability_to_threshold(p: f64) -> u32 {
+    if p >= 1.0 {
+        u32::MAX
+    } else if p <= 0.0 {
+        0
+    } else {
+        (p * 4_294_967_296.0) as u32
+    }
+}
+
+/// Returns `true` if an event with the given threshold happens for
+/// the random number `r`.
+pub fn chance(r: u32, threshold: u32) -> bool {
+    threshold == u32::MAX || r < threshold
+}
+
+/// Integer hash function with a good avalanche effect.
+pub fn hash(x: u32) -> u32 {
+    let mut x = x;
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+/// Returns the random number drawn by the cell at (`x`, `y`) for the
+/// given stream during the generation `generation`.
+pub fn random(seed: u64, generation: u64, x: u32, y: u32, stream: u32) -> u32 {
+    let mut h = hash(stream);
+    h = hash(y ^ h);
+    h = hash(x ^ h);
+    h = hash((generation >> 32) as u32 ^ h);
+    h = hash(generation as u32 ^ h);
+    h = hash((seed >> 32) as u32 ^ h);
+    hash(seed as u32 ^ h)
+}
+
+/// Computes the next generation of `cells`, a `width` x `height` grid
+/// whose living cells are set to `255`.
+pub fn next_gen(
+    cells: &[u8],
+    width: usize,
+    height: usize,
+    topology: &Topology,
+    survival: &[u32],
+    birth: &[u32],
+    stochastic: &Stochastic,
+) -> Vec<u8> {
+    let get = |x: i64, y: i64| -> bool {
+        match topology.map(x, y, width, height) {
+            Some((x, y)) => cells[y * width + x] == 255,
+            None => false,
+        }
+    };
+
+    let mut new_cells = vec![0u8; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as i64, y as i64);
+            let mut living_neighbors = 0;
+            for dy in -1..2 {
+                for dx in -1..2 {
+                    if (dx != 0 || dy != 0) && get(xi + dx, yi + dy) {
+                        living_neighbors += 1;
+                    }
+                }
+            }
+
+            let alive = get(xi, yi);
+            let random = |stream| {
+                random(
+                    stochastic.seed,
+                    stochastic.generation,
+                    x as u32,
+                    y as u32,
+                    stream,
+                )
+            };
+
+            let next_state = if !chance(random(UPDATE_STREAM), stochastic.update) {
+                alive
+            } else if alive {
+                survival.contains(&living_neighbors)
+                    && chance(random(RULE_STREAM), stochastic.survival)
+            } else {
+                birth.contains(&living_neighbors) && chance(random(RULE_STREAM), stochastic.birth)
+            };
+
+            if next_state {
+                new_cells[y * width + x] = 255;
+            }
+        }
+    }
+
+    new_cells
+}
+
+/// Returns a bit mask whose `n`th bit is set if `n` is in `rules`.
+pub fn rule_mask(rules: &[u32]) -> u16 {
+    rules
+        .iter()
+        .filter(|&&n| n <= 8)
+        .fold(0, |mask, &n| mask | (1 << n))
+}
+
+fn full_adder(a: u64, b: u64, c: u64) -> (u64, u64) {
+    (a ^ b ^ c, (a & b) | (c & (a ^ b)))
+}
+
+/// Computes the next state of 64 cells at once.
+///
+/// Each argument holds a row of 64 cells, one per bit: `row` contains the
+/// cells themselves, `above` and `below` the cells of the rows above and
+/// below them. Each row is given as `[west, center, east]` where `west`
+/// (resp. `east`) holds for each bit the state of the cell on the left
+/// (resp. right) of the cell of `center`.
+/// `survival` and `birth` are rule masks returned by `rule_mask`.
+pub fn next_word(
+    above: [u64; 3],
+    row: [u64; 3],
+    below: [u64; 3],
+    survival: u16,
+    birth: u16,
+) -> u64 {
+    // Add the 8 neighbors of every cell with full adders, each bit of
+    // the count being stored in a different word
+    let (sum_a, carry_a) = full_adder(above[0], above[1], above[2]);
+    let (sum_b, carry_b) = full_adder(below[0], below[1], below[2]);
+    let (sum_c, carry_c) = (row[0] ^ row[2], row[0] & row[2]);
+
+    let (bit0, carry_0) = full_adder(sum_a, sum_b, sum_c);
+    let (twos, carry_1) = full_adder(carry_a, carry_b, carry_c);
+    let (bit1, carry_2) = (twos ^ carry_0, twos & carry_0);
+    let (bit2, bit3) = (carry_1 ^ carry_2, carry_1 & carry_2);
+
+    let count_is = |n: u16| -> u64 {
+        let pick = |bit: u64, set: bool| if set { bit } else { !bit };
+        pick(bit0, n & 1 != 0)
+            & pick(bit1, n & 2 != 0)
+            & pick(bit2, n & 4 != 0)
+            & pick(bit3, n & 8 != 0)
+    };
+
+    let (mut survives, mut born) = (0, 0);
+    for n in 0..9 {
+        if survival & (1 << n) != 0 {
+            survives |= count_is(n);
+        }
+        if birth & (1 << n) != 0 {
+            born |= count_is(n);
+        }
+    }
+
+    (row[1] & survives) | (!row[1] & born)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use topology::Topology;
+
+    fn deterministic() -> Stochastic {
+        Stochastic {
+            birth: u32::MAX,
+            survival: u32::MAX,
+            update: u32::MAX,
+            seed: 0,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn test_probability_to_threshold() {
+        assert_eq!(0, probability_to_threshold(0.0));
+        assert_eq!(1 << 31, probability_to_threshold(0.5));
+        assert_eq!(u32::MAX, probability_to_threshold(1.0));
+
+        assert!(chance(u32::MAX, probability_to_threshold(1.0)));
+        assert!(!chance(0, probability_to_threshold(0.0)));
+    }
+
+    #[test]
+    fn test_random_is_reproducible() {
+        assert_eq!(random(42, 7, 3, 5, 0), random(42, 7, 3, 5, 0));
+        assert_ne!(random(42, 7, 3, 5, 0), random(43, 7, 3, 5, 0));
+        assert_ne!(random(42, 7, 3, 5, 0), random(42, 8, 3, 5, 0));
+        assert_ne!(random(42, 7, 3, 5, 0), random(42, 7, 5, 3, 0));
+        assert_ne!(random(42, 7, 3, 5, 0), random(42, 7, 3, 5, 1));
+    }
+
+    #[test]
+    fn test_deterministic_blinker() {
+        let cells = vec![0, 0, 0, 255, 255, 255, 0, 0, 0];
+        let new_cells = next_gen(
+            &cells,
+            3,
+            3,
+            &Topology::Resizable,
+            &[2, 3],
+            &[3],
+            &deterministic(),
+        );
+
+        assert_eq!(vec![0, 255, 0, 0, 255, 0, 0, 255, 0], new_cells);
+    }
+
+    #[test]
+    fn test_next_word() {
+        let (survival, birth) = (rule_mask(&[2, 3]), rule_mask(&[3]));
+        let row = |word: u64| [word << 1, word, word >> 1];
+
+        // A horizontal blinker becomes vertical
+        let blinker = 0b1110;
+        assert_eq!(
+            0b0100,
+            next_word(row(0), row(blinker), row(0), survival, birth)
+        );
+        assert_eq!(
+            0b0100,
+            next_word(row(blinker), row(0), row(0), survival, birth)
+        );
+
+        // A block is a still life
+        let block = 0b0110;
+        assert_eq!(
+            block,
+            next_word(row(0), row(block), row(block), survival, birth)
+        );
+    }
+
+    #[test]
+    fn test_stochastic_rules() {
+        let cells = vec![0, 0, 0, 255, 255, 255, 0, 0, 0];
+
+        // Nothing is born nor survives
+        let never = Stochastic {
+            birth: 0,
+            survival: 0,
+            ..deterministic()
+        };
+        assert_eq!(
+            vec![0; 9],
+            next_gen(&cells, 3, 3, &Topology::Resizable, &[2, 3], &[3], &never)
+        );
+
+        // No cell is ever updated
+        let frozen = Stochastic {
+            update: 0,
+            ..deterministic()
+        };
+        assert_eq!(
+            cells,
+            next_gen(&cells, 3, 3, &Topology::Resizable, &[2, 3], &[3], &frozen)
+        );
+
+        // The same seed and generation give the same result
+        let noisy = Stochastic {
+            birth: probability_to_threshold(0.5),
+            survival: probability_to_threshold(0.5),
+            update: probability_to_threshold(0.5),
+            seed: 1234,
+            generation: 9,
+        };
+        let soup: Vec<u8> = (0..64u32)
+            .map(|i| if hash(i) & 1 == 0 { 255 } else { 0 })
+            .collect();
+        assert_eq!(
+            next_gen(&soup, 8, 8, &Topology::torus(), &[2, 3], &[3], &noisy),
+            next_gen(&soup, 8, 8, &Topology::torus(), &[2, 3], &[3], &noisy)
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! This module contains some methods to combine the patterns of
+//! several grids.
+
+use topology::Topology;
+use Grid;
+
+/// Represents how the cells of a pasted pattern are combined with the
+/// cells they cover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The covered cells are replaced, dead cells included.
+    Copy,
+    /// A cell is alive if it is alive in either pattern.
+    Or,
+    /// A cell is alive if it is alive in both patterns.
+    And,
+    /// A cell is alive if it is alive in only one of the patterns.
+    Xor,
+    /// Only the living cells of the pasted pattern are copied.
+    CopyLive,
+}
+
+impl BlendMode {
+    fn blend(self, below: u8, above: u8) -> u8 {
+        let (below, above) = (below == 255, above == 255);
+        let alive = match self {
+            BlendMode::Copy => above,
+            BlendMode::Or | BlendMode::CopyLive => below || above,
+            BlendMode::And => below && above,
+            BlendMode::Xor => below != above,
+        };
+
+        if alive {
+            255
+        } else {
+            0
+        }
+    }
+}
+
+impl Grid {
+    /// Pastes the cells of `other` so that its north west corner is at
+    /// the coordinates (`x`, `y`), combining them with the cells of the
+    /// current `Grid` according to `mode`. The rules of `other` are
+    /// ignored.
+    ///
+    /// The cells crossing the edges are moved according to the topology.
+    /// A resizable grid grows to contain the whole pasted pattern, and a
+    /// cylindrical grid grows vertically. On the other topologies, the
+    /// cells which have no place in the grid are lost.
+    pub fn paste(&mut self, other: &Grid, x: i64, y: i64, mode: BlendMode) {
+        self.journaled(|grid| grid.paste_cells(other, x, y, mode));
+    }
+
+    fn paste_cells(&mut self, other: &Grid, x: i64, y: i64, mode: BlendMode) {
+        let (x, y) = self.make_room_for(x, y, other.get_width(), other.get_height());
+
+        let (width, height) = (self.get_width(), self.get_height());
+        let mut cells = self.read_cells();
+        for (i, &cell) in other.cells().iter().enumerate() {
+            let (ox, oy) = (
+                (i % other.get_width()) as i64,
+                (i / other.get_width()) as i64,
+            );
+            if let Some((dx, dy)) = self.topology.map(x + ox, y + oy, width, height) {
+                let index = dy * width + dx;
+                cells[index] = mode.blend(cells[index], cell);
+            }
+        }
+
+        self.write_cells(&cells);
+    }
+
+    /// Returns a new resizable `Grid` with the rules of the current
+    /// `Grid`, containing a copy of its `width` x `height` region whose
+    /// north west corner is at (`x`, `y`).
+    ///
+    /// The region can cross the edges of the grid: its cells are found
+    /// like `get_cell_state` does.
+    pub fn extract(&self, x: i64, y: i64, width: usize, height: usize) -> Grid {
+        let mut extracted = Grid::with_context(
+            &self.get_context(),
+            &self.get_format(),
+            Topology::Resizable,
+            &self.get_survival(),
+            &self.get_birth(),
+            width,
+            height,
+        );
+
+        let cells = self.cells();
+        let region: Vec<u8> = (0..width * height)
+            .map(|i| {
+                let (rx, ry) = (x + (i % width) as i64, y + (i / width) as i64);
+                match self.topology.map(rx, ry, self.width, self.height) {
+                    Some((cx, cy)) => cells[cy * self.width + cx],
+                    None => 0,
+                }
+            })
+            .collect();
+        extracted.write_cells(&region);
+
+        extracted
+    }
+
+    /// Works like `extract`, and kills the cells of the region in the
+    /// current `Grid`.
+    pub fn cut(&mut self, x: i64, y: i64, width: usize, height: usize) -> Grid {
+        let extracted = self.extract(x, y, width, height);
+
+        let cells: Vec<(usize, usize, u8)> = (0..width * height)
+            .filter_map(|i| {
+                let (rx, ry) = (x + (i % width) as i64, y + (i / width) as i64);
+                self.topology.map(rx, ry, self.width, self.height)
+            })
+            .map(|(cx, cy)| (cx, cy, 0))
+            .collect();
+        self.set_cells(cells).unwrap(); // Shouldn't fail
+
+        extracted
+    }
+
+    // Grows a resizable or cylindrical grid so that it contains the
+    // `width` x `height` region whose north west corner is at (`x`, `y`).
+    // Returns the new coordinates of this corner.
+    fn make_room_for(&mut self, x: i64, y: i64, width: usize, height: usize) -> (i64, i64) {
+        let grows_horizontally = match self.topology {
+            Topology::Resizable => true,
+            Topology::Cylinder => false,
+            _ => return (x, y),
+        };
+
+        let (old_width, old_height) = (self.width as i64, self.height as i64);
+        let (min_x, max_x) = if grows_horizontally {
+            (x.min(0), (x + width as i64).max(old_width))
+        } else {
+            (0, old_width)
+        };
+        let (min_y, max_y) = (y.min(0), (y + height as i64).max(old_height));
+
+        self.reframe(
+            (0, 0),
+            (self.width, self.height),
+            ((max_x - min_x) as usize, (max_y - min_y) as usize),
+            ((-min_x) as usize, (-min_y) as usize),
+        );
+
+        (x - min_x, y - min_y)
+    }
+}
@@ -10,12 +10,28 @@ use std::num;
 #[derive(Debug)]
 pub enum GridErrorKind {
     OutOfBoundCoords,
+    InvalidProbability,
+    UnsupportedRule,
+    IncompatibleGrids,
+    UnsupportedTopology,
+    UnavailableGeneration,
+    AperiodicPattern,
 }
 
 impl fmt::Display for GridErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             GridErrorKind::OutOfBoundCoords => write!(f, "Error: out of bound index"),
+            GridErrorKind::InvalidProbability => {
+                write!(f, "Error: probability not between 0 and 1")
+            }
+            GridErrorKind::UnsupportedRule => write!(f, "Error: rule not supported"),
+            GridErrorKind::IncompatibleGrids => write!(f, "Error: incompatible grids"),
+            GridErrorKind::UnsupportedTopology => write!(f, "Error: topology not supported"),
+            GridErrorKind::UnavailableGeneration => {
+                write!(f, "Error: generation not in the history")
+            }
+            GridErrorKind::AperiodicPattern => write!(f, "Error: pattern not periodic"),
         }
     }
 }
@@ -24,6 +40,12 @@ impl error::Error for GridErrorKind {
     fn description(&self) -> &str {
         match *self {
             GridErrorKind::OutOfBoundCoords => "out of bound index",
+            GridErrorKind::InvalidProbability => "probability not between 0 and 1",
+            GridErrorKind::UnsupportedRule => "rule not supported",
+            GridErrorKind::IncompatibleGrids => "incompatible grids",
+            GridErrorKind::UnsupportedTopology => "topology not supported",
+            GridErrorKind::UnavailableGeneration => "generation not in the history",
+            GridErrorKind::AperiodicPattern => "pattern not periodic",
         }
     }
 
@@ -42,6 +64,7 @@ pub enum FileParsingErrorKind {
     IncompleteFile,
     RuleParsingError,
     CoordParsingError,
+    ApgcodeParsingError,
     OutOfBoundCoords(GridErrorKind),
 }
 
@@ -53,6 +76,7 @@ impl fmt::Display for FileParsingErrorKind {
             FileParsingErrorKind::IncompleteFile => write!(f, "Incomplete or empty file"), // TODO: separately handle the case where the file is empty
             FileParsingErrorKind::RuleParsingError => write!(f, "Invalid ruleset"),
             FileParsingErrorKind::CoordParsingError => write!(f, "Invalid coordinates"),
+            FileParsingErrorKind::ApgcodeParsingError => write!(f, "Invalid apgcode"),
             FileParsingErrorKind::OutOfBoundCoords(ref err) => write!(f, "{}", err),
         }
     }
@@ -66,6 +90,7 @@ impl error::Error for FileParsingErrorKind {
             FileParsingErrorKind::IncompleteFile => "incomplete or empty file", // TODO: separately handle the case where the file is empty
             FileParsingErrorKind::RuleParsingError => "invalid ruleset",
             FileParsingErrorKind::CoordParsingError => "invalid coordinates",
+            FileParsingErrorKind::ApgcodeParsingError => "invalid apgcode",
             FileParsingErrorKind::OutOfBoundCoords(ref err) => err.description(),
         }
     }
@@ -2,6 +2,9 @@
 //! save files containing grid data.
 //! For now it supports two internal file formats : "Resizable Life"
 //! and "Toroidal Life".
+//! The "Toroidal Life" format is used for every bounded topology, which
+//! is then given as a suffix of the ruleset (e.g. "#R 23/3:K30*,20").
+//! Patterns can also be imported and exported as apgcodes (e.g. "xq4_153").
 
 use std::collections::LinkedList;
 use std::fs::File;
@@ -9,9 +12,15 @@ use std::io;
 use std::io::prelude::*;
 use std::iter::FromIterator;
 
-use error::FileParsingErrorKind;
+use census;
+use classification::Classification;
+use error::{FileParsingErrorKind, GridErrorKind};
+use topology::Topology;
 use Grid;
 
+/// The maximum period of the patterns encoded by `Grid::to_apgcode`.
+const APGCODE_MAX_PERIOD: u64 = 1024;
+
 impl Grid {
     /// Returns a new `Grid` encoded within a file located at `path`.
     ///
@@ -57,15 +66,15 @@ impl Grid {
 
         // Recenter the `Grid`
         let mut grid = self.clone();
-        if !self.is_toroidal() {
+        if self.is_resizable() {
             grid.recenter_pattern(0);
         }
 
         // Put format
-        if grid.is_toroidal() {
-            lines.push_back("#Toroidal Life".to_string());
-        } else {
+        if grid.is_resizable() {
             lines.push_back("#Resizable Life".to_string());
+        } else {
+            lines.push_back("#Toroidal Life".to_string());
         }
 
         // Put ruleset
@@ -77,22 +86,32 @@ impl Grid {
         for n in grid.birth.read().unwrap().iter() {
             birth_ruleset.push_str(&n.to_string());
         }
-        lines.push_back(format!("#R {}/{}", survival_ruleset, birth_ruleset));
-
-        // Put grid size if toroidal
         let width = grid.get_width();
         let height = grid.get_height();
-        if grid.is_toroidal() {
+        // A torus without any shift is the default topology of the format
+        let topology_suffix = if grid.get_topology() == Topology::torus() {
+            String::new()
+        } else {
+            grid.get_topology().to_suffix(width, height)
+        };
+        lines.push_back(format!(
+            "#R {}/{}{}",
+            survival_ruleset, birth_ruleset, topology_suffix
+        ));
+
+        // Put generation if some were computed
+        if grid.get_generation() != 0 {
+            lines.push_back(format!("#G {}", grid.get_generation()));
+        }
+
+        // Put grid size if it is bounded
+        if !grid.is_resizable() {
             lines.push_back(format!("#S {} {}", width, height));
         }
 
         // Put living cells coords
-        for y in 0..height {
-            for x in 0..width {
-                if grid.get_cell_state(x as i64, y as i64) == 255 {
-                    lines.push_back(format!("{} {}", x, y));
-                }
-            }
+        for (x, y) in grid.live_cells() {
+            lines.push_back(format!("{} {}", x, y));
         }
 
         // Write lines to a file
@@ -103,6 +122,62 @@ impl Grid {
 
         Ok(())
     }
+
+    /// Returns a new resizable `Grid` following the rules of Conway's Game
+    /// of Life, containing the pattern described by an apgcode like
+    /// `xs6_696` or `xq4_153`.
+    ///
+    /// # Errors
+    ///
+    /// If the apgcode isn't made of a `xs`, `xp` or `xq` prefix followed
+    /// by a number, an underscore and a valid extended Wechsler format,
+    /// `FileParsingErrorKind::ApgcodeParsingError` will be returned.
+    pub fn from_apgcode(apgcode: &str) -> Result<Grid, FileParsingErrorKind> {
+        let (prefix, wechsler) = match apgcode.find('_') {
+            Some(idx) => (&apgcode[..idx], &apgcode[idx + 1..]),
+            None => return Err(FileParsingErrorKind::ApgcodeParsingError),
+        };
+        let valid_prefix = ["xs", "xp", "xq"]
+            .iter()
+            .any(|kind| prefix.starts_with(kind) && prefix[kind.len()..].parse::<u64>().is_ok());
+        if !valid_prefix {
+            return Err(FileParsingErrorKind::ApgcodeParsingError);
+        }
+
+        let coords = parse_wechsler(wechsler)?;
+        let (width, height) = guess_pattern_size(&coords);
+
+        let mut grid = Grid::new(
+            &"#Resizable Life".to_string(),
+            Topology::Resizable,
+            &vec![2, 3],
+            &vec![3],
+            width,
+            height,
+        );
+        grid.set_cells(coords.into_iter().map(|(x, y)| (x, y, 255)))?;
+
+        Ok(grid)
+    }
+
+    /// Returns the apgcode of the pattern of the `Grid`: its kind and its
+    /// period, followed by the canonical extended Wechsler format of its
+    /// phases (see the `census` module). The pattern is run for up to
+    /// 1024 generations to find its period.
+    ///
+    /// # Errors
+    ///
+    /// If the rules are stochastic, `GridErrorKind::UnsupportedRule` will
+    /// be returned, and if the pattern doesn't repeat,
+    /// `GridErrorKind::AperiodicPattern` will be.
+    pub fn to_apgcode(&self) -> Result<String, GridErrorKind> {
+        let (classification, phases) = self.find_cycle(APGCODE_MAX_PERIOD)?;
+        if classification == Classification::Unknown {
+            return Err(GridErrorKind::AperiodicPattern);
+        }
+
+        Ok(census::apgcode(classification, &phases))
+    }
 }
 
 fn valid_life_file(lines_ref: &LinkedList<&str>) -> Result<(), FileParsingErrorKind> {
@@ -154,6 +229,11 @@ fn valid_resizable_life(lines: LinkedList<&str>) -> Result<(), FileParsingErrorK
             .filter(|s| *s != "#R")
             .next()
             .ok_or(FileParsingErrorKind::IncompleteFile)?; // Without .next() there is a type error with split method
+        let (ruleset, suffix) = split_topology_suffix(ruleset);
+        if suffix.is_some() {
+            // Resizable grids have no topology suffix
+            return Err(FileParsingErrorKind::RuleParsingError);
+        }
         if ruleset.split('/').count() != 2 {
             return Err(FileParsingErrorKind::RuleParsingError);
         }
@@ -180,6 +260,9 @@ fn valid_resizable_life(lines: LinkedList<&str>) -> Result<(), FileParsingErrorK
             .ok_or(FileParsingErrorKind::IncompleteFile)?;
     }
 
+    // If any, check generation
+    pop_generation(&mut lines)?;
+
     // If no "coords" lines return an error
     if lines.is_empty() {
         return Err(FileParsingErrorKind::IncompleteFile);
@@ -236,6 +319,10 @@ fn valid_toroidal_life(lines: LinkedList<&str>) -> Result<(), FileParsingErrorKi
             .filter(|s| *s != "#R")
             .next()
             .ok_or(FileParsingErrorKind::IncompleteFile)?; // Without .next() there is a type error with split method
+        let (ruleset, suffix) = split_topology_suffix(ruleset);
+        if let Some(suffix) = suffix {
+            Topology::from_suffix(suffix)?;
+        }
         if ruleset.split('/').count() != 2 {
             return Err(FileParsingErrorKind::RuleParsingError);
         }
@@ -262,6 +349,9 @@ fn valid_toroidal_life(lines: LinkedList<&str>) -> Result<(), FileParsingErrorKi
             .ok_or(FileParsingErrorKind::IncompleteFile)?;
     }
 
+    // If any, check generation
+    pop_generation(&mut lines)?;
+
     // Check grid size specification (#S <width> <height>)
     let grid_size_line = lines
         .pop_front()
@@ -346,6 +436,9 @@ fn load_resizable_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingError
     brth.sort();
     brth.dedup();
 
+    // Get generation
+    let generation = pop_generation(&mut lines)?;
+
     // Guess the "cells" size
     let mut file_coords: Vec<(usize, usize)> = Vec::new();
     while !lines.is_empty() {
@@ -359,12 +452,18 @@ fn load_resizable_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingError
     let (width, height) = guess_pattern_size(&file_coords);
 
     // Make CA grid
-    let mut grid = Grid::new(&frmt.to_string(), false, &srvl, &brth, width, height);
+    let mut grid = Grid::new(
+        &frmt.to_string(),
+        Topology::Resizable,
+        &srvl,
+        &brth,
+        width,
+        height,
+    );
 
     // Set to true the cells that are alive
-    for (x, y) in file_coords {
-        grid.set_cell_state(x, y, 255)?;
-    }
+    grid.set_cells(file_coords.into_iter().map(|(x, y)| (x, y, 255)))?;
+    grid.set_generation(generation);
 
     // Return CA grid
     Ok(grid)
@@ -382,9 +481,10 @@ fn load_toroidal_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingErrorK
         lines.pop_front().unwrap();
     }
 
-    // Get ruleset
+    // Get ruleset and topology
     let mut srvl: Vec<u32> = Vec::new();
     let mut brth: Vec<u32> = Vec::new();
+    let mut topology_suffix = None;
     if *lines.front().unwrap() == "#N" {
         lines.pop_front().unwrap();
         srvl = vec![2, 3];
@@ -396,6 +496,10 @@ fn load_toroidal_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingErrorK
             .filter(|s| *s != "#R")
             .next()
             .unwrap(); // Without .next().unwrap() there is a type error with split method
+        let (ruleset, suffix) = split_topology_suffix(ruleset);
+        if let Some(suffix) = suffix {
+            topology_suffix = Some(Topology::from_suffix(suffix)?);
+        }
         let ruleset: Vec<&str> = ruleset.split("/").collect();
         let survival_ruleset = ruleset[0].chars();
         let birth_ruleset = ruleset[1].chars();
@@ -412,6 +516,9 @@ fn load_toroidal_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingErrorK
     brth.sort();
     brth.dedup();
 
+    // Get generation
+    let generation = pop_generation(&mut lines)?;
+
     // Get the grid size
     let grid_size_line_terms: Vec<&str> = lines
         .pop_front()
@@ -424,8 +531,19 @@ fn load_toroidal_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingErrorK
         grid_size_line_terms[1].parse().unwrap(),
     );
 
+    // The size given by the topology suffix must match the grid size
+    let topology = match topology_suffix {
+        Some((topology, suffix_width, suffix_height)) => {
+            if suffix_width != width || (suffix_height != height && suffix_height != 0) {
+                return Err(FileParsingErrorKind::RuleParsingError);
+            }
+            topology
+        }
+        None => Topology::torus(),
+    };
+
     // Make CA grid
-    let mut grid = Grid::new(&frmt.to_string(), true, &srvl, &brth, width, height);
+    let mut grid = Grid::new(&frmt.to_string(), topology, &srvl, &brth, width, height);
 
     // Get the coordinates from the file
     let mut file_coords: Vec<(usize, usize)> = Vec::new();
@@ -439,14 +557,78 @@ fn load_toroidal_life(lines: LinkedList<&str>) -> Result<Grid, FileParsingErrorK
     }
 
     // Set to true the cells that are alive
-    for (x, y) in file_coords {
-        grid.set_cell_state(x, y, 255)?;
-    }
+    grid.set_cells(file_coords.into_iter().map(|(x, y)| (x, y, 255)))?;
+    grid.set_generation(generation);
 
     // Return CA grid
     Ok(grid)
 }
 
+// Removes the optional "#G <generation>" line and returns the generation,
+// which is 0 without this line
+fn pop_generation(lines: &mut LinkedList<&str>) -> Result<u64, FileParsingErrorKind> {
+    match lines.front() {
+        Some(line) if line.starts_with("#G") => {}
+        _ => return Ok(0),
+    }
+
+    let generation_line = lines.pop_front().unwrap();
+    if generation_line.split_whitespace().count() != 2 {
+        return Err(FileParsingErrorKind::IncompleteFile);
+    }
+    let generation = generation_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or(FileParsingErrorKind::IncompleteFile)?;
+
+    Ok(generation.parse::<u64>()?)
+}
+
+// Splits a ruleset like "23/3:T30,20" into "23/3" and "T30,20"
+fn split_topology_suffix(ruleset: &str) -> (&str, Option<&str>) {
+    match ruleset.find(':') {
+        Some(idx) => (&ruleset[..idx], Some(&ruleset[idx + 1..])),
+        None => (ruleset, None),
+    }
+}
+
+// Returns the coordinates of the living cells encoded by an extended
+// Wechsler format (see `census::wechsler`)
+fn parse_wechsler(wechsler: &str) -> Result<Vec<(usize, usize)>, FileParsingErrorKind> {
+    let mut coords = Vec::new();
+    let (mut x, mut strip) = (0, 0);
+    let mut chars = wechsler.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            'w' => x += 2,
+            'x' => x += 3,
+            'y' => {
+                let dead_columns = chars
+                    .next()
+                    .filter(|n| n.is_ascii_digit() || n.is_ascii_lowercase())
+                    .and_then(|n| n.to_digit(36))
+                    .ok_or(FileParsingErrorKind::ApgcodeParsingError)?;
+                x += 4 + dead_columns as usize;
+            }
+            'z' => {
+                x = 0;
+                strip += 1;
+            }
+            '0'..='9' | 'a'..='v' => {
+                let column = c.to_digit(32).unwrap();
+                for row in (0..5).filter(|row| column & 1 << row != 0) {
+                    coords.push((x, 5 * strip + row));
+                }
+                x += 1;
+            }
+            _ => return Err(FileParsingErrorKind::ApgcodeParsingError),
+        }
+    }
+
+    Ok(coords)
+}
+
 fn guess_pattern_size(coords: &[(usize, usize)]) -> (usize, usize) {
     let (mut lim_x, mut lim_y): (usize, usize) = (0, 0);
 
@@ -461,3 +643,31 @@ fn guess_pattern_size(coords: &[(usize, usize)]) -> (usize, usize) {
     // The "+ 1"s are here because because the "coords" start at 0
     (lim_x + 1, lim_y + 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_wechsler;
+
+    use census::wechsler;
+
+    #[test]
+    fn test_parse_wechsler() {
+        assert_eq!(
+            vec![(0, 0), (0, 1), (1, 0), (1, 1)],
+            parse_wechsler("33").unwrap()
+        );
+        assert_eq!(
+            vec![(0, 0), (7, 0), (2, 5)],
+            parse_wechsler("1y21zw1").unwrap()
+        );
+        assert!(parse_wechsler("3A").is_err());
+        assert!(parse_wechsler("3y").is_err());
+
+        // Decoding and encoding give back the same format
+        let mut cells = vec![0; 8 * 6];
+        for (x, y) in parse_wechsler("1y21zw1").unwrap() {
+            cells[y * 8 + x] = 255;
+        }
+        assert_eq!("1y21zw1", wechsler(8, 6, &cells));
+    }
+}
@@ -0,0 +1,690 @@
+//! This module contains a HashLife engine, which can compute
+//! astronomically distant generations of patterns with a lot of
+//! regularities, like breeders, guns or metacells.
+//!
+//! The universe is stored as a quadtree whose nodes are canonicalised:
+//! two identical regions of the universe are represented by the same node.
+//! The future of the center of each node is memoised, so that repeated
+//! regions are only simulated once.
+
+use std::collections::HashMap;
+use std::mem;
+
+use super::cpu;
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// Index of a node in `HashLife::nodes`.
+type NodeId = u32;
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+/// Maximum level of the root, which covers the coordinates from
+/// `-2^(MAX_LEVEL - 1)` to `2^(MAX_LEVEL - 1) - 1` on each axis.
+const MAX_LEVEL: u8 = 62;
+
+/// Default value of the memory limit, in bytes.
+pub const DEFAULT_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+
+/// A square of `2^level` cells of side.
+///
+/// The leaves (level 0) are the cells themselves. The other nodes have
+/// four children of the level below, stored as north west, north east,
+/// south west and south east.
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    level: u8,
+    children: [NodeId; 4],
+    population: u64,
+    // The step (as a power of 2) and the center of this node after
+    // this number of generations
+    result: Option<(u32, NodeId)>,
+}
+
+// Approximate memory used by a node, index included
+const NODE_BYTES: usize = mem::size_of::<Node>() + mem::size_of::<([NodeId; 4], NodeId)>() * 2;
+
+/// This struct contains a life cellular automaton computed with the
+/// HashLife algorithm.
+///
+/// It supports the outer-totalistic rules without birth on 0 neighbors,
+/// on an unbounded plane centered on the coordinates (0, 0).
+/// When the memory used by the nodes exceeds the memory limit, the nodes
+/// which aren't part of the current generation are garbage collected
+/// before the next step.
+#[derive(Clone, Debug)]
+pub struct HashLife {
+    survival: Vec<u32>,
+    birth: Vec<u32>,
+    survival_mask: u16,
+    birth_mask: u16,
+
+    nodes: Vec<Node>,
+    index: HashMap<[NodeId; 4], NodeId>,
+    root: NodeId,
+
+    generation: u64,
+    memory_limit: usize,
+}
+
+impl HashLife {
+    /// Returns a new empty `HashLife` universe containing the rules given
+    /// by `srvl` and `brth`.
+    ///
+    /// Returns an error if the rules make cells being born with 0 neighbors.
+    pub fn new(srvl: &[u32], brth: &[u32]) -> Result<HashLife, GridErrorKind> {
+        if brth.contains(&0) {
+            return Err(GridErrorKind::UnsupportedRule);
+        }
+
+        let leaf = |population| Node {
+            level: 0,
+            children: [DEAD; 4],
+            population,
+            result: None,
+        };
+
+        let mut hashlife = HashLife {
+            survival: srvl.to_vec(),
+            birth: brth.to_vec(),
+            survival_mask: cpu::rule_mask(srvl),
+            birth_mask: cpu::rule_mask(brth),
+            nodes: vec![leaf(0), leaf(1)],
+            index: HashMap::new(),
+            root: DEAD,
+            generation: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        };
+        hashlife.root = hashlife.empty(3);
+
+        Ok(hashlife)
+    }
+
+    /// Returns a new `HashLife` universe containing the rules and the
+    /// living cells of `grid`, at the same coordinates.
+    ///
+    /// The topology of `grid` is not kept: the pattern evolves on
+    /// an unbounded plane.
+    pub fn from_grid(grid: &Grid) -> Result<HashLife, GridErrorKind> {
+        let mut hashlife = HashLife::new(&grid.get_survival(), &grid.get_birth())?;
+
+        for y in 0..grid.get_height() {
+            for x in 0..grid.get_width() {
+                if grid.get_cell_state(x as i64, y as i64) == 255 {
+                    hashlife.set_cell_state(x as i64, y as i64, 255)?;
+                }
+            }
+        }
+
+        Ok(hashlife)
+    }
+
+    /// Returns a new resizable `Grid` containing the rules and the living
+    /// cells of this universe, using the file format `frmt`.
+    ///
+    /// The pattern is translated so that the north west corner of its
+    /// bounding box (see `bounding_box`) is at the coordinates (0, 0).
+    /// An empty universe gives a 1x1 grid.
+    pub fn to_grid(&self, frmt: &String) -> Grid {
+        let (min_x, max_x, min_y, max_y) = self.bounding_box().unwrap_or((0, 0, 0, 0));
+
+        let mut grid = Grid::new(
+            frmt,
+            Topology::Resizable,
+            &self.survival,
+            &self.birth,
+            (max_x - min_x + 1) as usize,
+            (max_y - min_y + 1) as usize,
+        );
+
+        for (x, y) in self.live_cells() {
+            grid.set_cell_state((x - min_x) as usize, (y - min_y) as usize, 255)
+                .unwrap(); // Shouldn't fail
+        }
+
+        grid
+    }
+
+    /// Returns the survival conditions of the cellular automaton.
+    pub fn get_survival(&self) -> Vec<u32> {
+        self.survival.clone()
+    }
+
+    /// Returns the birth conditions of the cellular automaton.
+    pub fn get_birth(&self) -> Vec<u32> {
+        self.birth.clone()
+    }
+
+    /// Returns the number of generations computed since the creation
+    /// of this universe.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the memory limit, in bytes.
+    pub fn get_memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Sets the memory limit, in bytes, above which the unused nodes are
+    /// garbage collected. It is only checked between two steps: the nodes
+    /// created during a single `step_pow2` are all kept until it returns.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit;
+    }
+
+    /// Returns the number of nodes currently stored.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the number of living cells.
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    /// Returns the state of the cell at the coordinates (`x`, `y`).
+    pub fn get_cell_state(&self, x: i64, y: i64) -> u8 {
+        if !self.contains(x, y) {
+            return 0;
+        }
+
+        let half = self.half_size();
+        let (mut x, mut y) = ((x + half) as u64, (y + half) as u64);
+        let mut id = self.root;
+
+        while self.nodes[id as usize].level > 0 {
+            let node = self.nodes[id as usize];
+            let half = 1 << (node.level - 1);
+            id = node.children[child_index(x, y, half)];
+            x %= half;
+            y %= half;
+        }
+
+        if id == ALIVE {
+            255
+        } else {
+            0
+        }
+    }
+
+    /// Modifies the state of the cell at the coordinates (`x`, `y`)
+    /// with `state`. Any state other than `0` is a living cell.
+    ///
+    /// Returns an error if the coordinates are further than
+    /// `2^(MAX_LEVEL - 1)` from the origin.
+    pub fn set_cell_state(&mut self, x: i64, y: i64, state: u8) -> Result<(), GridErrorKind> {
+        while !self.contains(x, y) {
+            if self.nodes[self.root as usize].level >= MAX_LEVEL {
+                return Err(GridErrorKind::OutOfBoundCoords);
+            }
+            self.root = self.expand(self.root);
+        }
+
+        let half = self.half_size();
+        let root = self.root;
+        self.root = self.set_cell(root, (x + half) as u64, (y + half) as u64, state != 0);
+
+        Ok(())
+    }
+
+    /// Returns the coordinates of the living cells.
+    pub fn live_cells(&self) -> Vec<(i64, i64)> {
+        let mut cells = Vec::new();
+        let half = self.half_size();
+
+        self.collect_cells(self.root, -half, -half, &mut cells);
+
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells
+    }
+
+    /// Returns the boundaries of the pattern as
+    /// `(min_x, max_x, min_y, max_y)`, or `None` if there is no living cell.
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let half = self.half_size();
+        let mut memo = HashMap::new();
+
+        self.local_bounds(self.root, &mut memo)
+            .map(|(min_x, max_x, min_y, max_y)| {
+                (
+                    min_x as i64 - half,
+                    max_x as i64 - half,
+                    min_y as i64 - half,
+                    max_y as i64 - half,
+                )
+            })
+    }
+
+    /// Computes the generation `2^k` generations after the current one
+    /// and updates the universe.
+    ///
+    /// The memory limit may be exceeded while computing a large `k`, in
+    /// which case several smaller steps use less memory.
+    ///
+    /// Returns an error if the pattern would grow beyond the coordinates
+    /// supported by the universe.
+    pub fn step_pow2(&mut self, k: u32) -> Result<(), GridErrorKind> {
+        if self.nodes.len() * NODE_BYTES > self.memory_limit {
+            self.collect_garbage();
+        }
+
+        // The pattern must fit in the center quarter of the root, so that
+        // it can't escape the center half of the root after 2^k generations
+        loop {
+            let level = self.nodes[self.root as usize].level;
+            if u32::from(level) >= k + 2 && self.fits_in_center() {
+                break;
+            }
+            if level >= MAX_LEVEL {
+                return Err(GridErrorKind::OutOfBoundCoords);
+            }
+            self.root = self.expand(self.root);
+        }
+
+        let expanded = self.expand(self.root);
+        self.root = self.advance(expanded, k);
+        self.generation += 1 << k;
+
+        Ok(())
+    }
+
+    /// Computes the next generation and updates the universe.
+    pub fn next_gen(&mut self) -> Result<(), GridErrorKind> {
+        self.step_pow2(0)
+    }
+
+    fn half_size(&self) -> i64 {
+        1 << (self.nodes[self.root as usize].level - 1)
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        let half = self.half_size();
+
+        x >= -half && x < half && y >= -half && y < half
+    }
+
+    // Returns the canonical node having these children
+    fn make(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.index.get(&children) {
+            return id;
+        }
+
+        let node = Node {
+            level: self.nodes[children[0] as usize].level + 1,
+            children,
+            population: children
+                .iter()
+                .map(|&c| self.nodes[c as usize].population)
+                .sum(),
+            result: None,
+        };
+
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(node);
+        self.index.insert(children, id);
+
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> NodeId {
+        if level == 0 {
+            DEAD
+        } else {
+            let child = self.empty(level - 1);
+            self.make([child; 4])
+        }
+    }
+
+    // Returns a node of the level above with `id` at its center
+    fn expand(&mut self, id: NodeId) -> NodeId {
+        let node = self.nodes[id as usize];
+        let e = self.empty(node.level - 1);
+        let [nw, ne, sw, se] = node.children;
+
+        let nw = self.make([e, e, e, nw]);
+        let ne = self.make([e, e, ne, e]);
+        let sw = self.make([e, sw, e, e]);
+        let se = self.make([se, e, e, e]);
+
+        self.make([nw, ne, sw, se])
+    }
+
+    fn children(&self, id: NodeId) -> [NodeId; 4] {
+        self.nodes[id as usize].children
+    }
+
+    // Returns the center of a node, one level below
+    fn center(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(id);
+
+        self.make([
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ])
+    }
+
+    // Returns the node between two horizontally adjacent nodes
+    fn center_horizontal(&mut self, w: NodeId, e: NodeId) -> NodeId {
+        let (w, e) = (self.children(w), self.children(e));
+
+        self.make([w[1], e[0], w[3], e[2]])
+    }
+
+    // Returns the node between two vertically adjacent nodes
+    fn center_vertical(&mut self, n: NodeId, s: NodeId) -> NodeId {
+        let (n, s) = (self.children(n), self.children(s));
+
+        self.make([n[2], n[3], s[0], s[1]])
+    }
+
+    fn fits_in_center(&mut self) -> bool {
+        let root = self.root;
+        let center = self.center(root);
+        let center = self.center(center);
+
+        self.nodes[center as usize].population == self.population()
+    }
+
+    // Returns the center of the node `id`, `2^k` generations later.
+    // `k` must be lower than or equal to the level of the node minus 2.
+    fn advance(&mut self, id: NodeId, k: u32) -> NodeId {
+        let node = self.nodes[id as usize];
+
+        if node.population == 0 {
+            return self.empty(node.level - 1);
+        }
+        if let Some((step, result)) = node.result {
+            if step == k {
+                return result;
+            }
+        }
+
+        let result = if node.level == 2 {
+            self.advance_leaves(id)
+        } else {
+            let [nw, ne, sw, se] = node.children;
+            let n = self.center_horizontal(nw, ne);
+            let s = self.center_horizontal(sw, se);
+            let w = self.center_vertical(nw, sw);
+            let e = self.center_vertical(ne, se);
+            let c = self.center(id);
+
+            // When computing 2^(level - 2) generations, the first half is
+            // computed on the 9 subnodes and the second half on their
+            // results. Otherwise, only the second half is computed.
+            let full_speed = k + 2 == u32::from(node.level);
+            let mut parts = [nw, n, ne, w, c, e, sw, s, se];
+            for part in parts.iter_mut() {
+                *part = if full_speed {
+                    self.advance(*part, k - 1)
+                } else {
+                    self.center(*part)
+                };
+            }
+
+            let k = if full_speed { k - 1 } else { k };
+            let quarters = [
+                [parts[0], parts[1], parts[3], parts[4]],
+                [parts[1], parts[2], parts[4], parts[5]],
+                [parts[3], parts[4], parts[6], parts[7]],
+                [parts[4], parts[5], parts[7], parts[8]],
+            ];
+            let mut results = [DEAD; 4];
+            for (result, &quarter) in results.iter_mut().zip(quarters.iter()) {
+                let quarter = self.make(quarter);
+                *result = self.advance(quarter, k);
+            }
+
+            self.make(results)
+        };
+
+        self.nodes[id as usize].result = Some((k, result));
+
+        result
+    }
+
+    // Computes the next generation of the 2x2 center of a 4x4 node
+    fn advance_leaves(&mut self, id: NodeId) -> NodeId {
+        let mut cells = [[false; 4]; 4];
+        for (i, &child) in self.children(id).iter().enumerate() {
+            for (j, &leaf) in self.children(child).iter().enumerate() {
+                let x = (i & 1) * 2 + (j & 1);
+                let y = (i >> 1) * 2 + (j >> 1);
+                cells[y][x] = leaf == ALIVE;
+            }
+        }
+
+        let mut results = [DEAD; 4];
+        for (j, result) in results.iter_mut().enumerate() {
+            let (x, y) = (1 + (j & 1), 1 + (j >> 1));
+            let living_neighbors = cells[y - 1..y + 2]
+                .iter()
+                .flat_map(|row| row[x - 1..x + 2].iter())
+                .filter(|&&cell| cell)
+                .count()
+                - cells[y][x] as usize;
+
+            let rules = if cells[y][x] {
+                self.survival_mask
+            } else {
+                self.birth_mask
+            };
+            if rules & (1 << living_neighbors) != 0 {
+                *result = ALIVE;
+            }
+        }
+
+        self.make(results)
+    }
+
+    fn set_cell(&mut self, id: NodeId, x: u64, y: u64, alive: bool) -> NodeId {
+        let level = self.nodes[id as usize].level;
+        if level == 0 {
+            return if alive { ALIVE } else { DEAD };
+        }
+
+        let half = 1 << (level - 1);
+        let mut children = self.children(id);
+        let i = child_index(x, y, half);
+        children[i] = self.set_cell(children[i], x % half, y % half, alive);
+
+        self.make(children)
+    }
+
+    fn collect_cells(&self, id: NodeId, x: i64, y: i64, cells: &mut Vec<(i64, i64)>) {
+        let node = self.nodes[id as usize];
+
+        if node.population == 0 {
+            return;
+        }
+        if node.level == 0 {
+            cells.push((x, y));
+            return;
+        }
+
+        let half = 1 << (node.level - 1);
+        for (i, &child) in node.children.iter().enumerate() {
+            let (dx, dy) = ((i & 1) as i64 * half, (i >> 1) as i64 * half);
+            self.collect_cells(child, x + dx, y + dy, cells);
+        }
+    }
+
+    // Returns the boundaries of the pattern of a node, relatively to its
+    // north west corner
+    fn local_bounds(
+        &self,
+        id: NodeId,
+        memo: &mut HashMap<NodeId, Option<(u64, u64, u64, u64)>>,
+    ) -> Option<(u64, u64, u64, u64)> {
+        let node = self.nodes[id as usize];
+
+        if node.population == 0 {
+            return None;
+        }
+        if node.level == 0 {
+            return Some((0, 0, 0, 0));
+        }
+        if let Some(&bounds) = memo.get(&id) {
+            return bounds;
+        }
+
+        let half = 1 << (node.level - 1);
+        let mut bounds: Option<(u64, u64, u64, u64)> = None;
+        for (i, &child) in node.children.iter().enumerate() {
+            let (dx, dy) = ((i & 1) as u64 * half, (i >> 1) as u64 * half);
+            if let Some((x0, x1, y0, y1)) = self.local_bounds(child, memo) {
+                let (x0, x1, y0, y1) = (x0 + dx, x1 + dx, y0 + dy, y1 + dy);
+                bounds = Some(match bounds {
+                    None => (x0, x1, y0, y1),
+                    Some((min_x, max_x, min_y, max_y)) => {
+                        (min_x.min(x0), max_x.max(x1), min_y.min(y0), max_y.max(y1))
+                    }
+                });
+            }
+        }
+
+        memo.insert(id, bounds);
+        bounds
+    }
+
+    // Removes the nodes which aren't part of the current generation,
+    // along with all the memoised results
+    fn collect_garbage(&mut self) {
+        let mut reachable = vec![false; self.nodes.len()];
+        reachable[DEAD as usize] = true;
+        reachable[ALIVE as usize] = true;
+
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            if !reachable[id as usize] {
+                reachable[id as usize] = true;
+                stack.extend_from_slice(&self.nodes[id as usize].children);
+            }
+        }
+
+        // The children of a node are always created before it, so
+        // keeping the order of the nodes keeps this property
+        let mut new_ids = vec![DEAD; self.nodes.len()];
+        let mut nodes = Vec::new();
+        self.index.clear();
+        for (id, node) in self.nodes.iter().enumerate() {
+            if !reachable[id] {
+                continue;
+            }
+
+            let mut node = *node;
+            node.result = None;
+            if node.level > 0 {
+                for child in node.children.iter_mut() {
+                    *child = new_ids[*child as usize];
+                }
+                self.index.insert(node.children, nodes.len() as NodeId);
+            }
+
+            new_ids[id] = nodes.len() as NodeId;
+            nodes.push(node);
+        }
+
+        self.root = new_ids[self.root as usize];
+        self.nodes = nodes;
+    }
+}
+
+// Returns the index of the child containing the coordinates (`x`, `y`)
+// of a node whose children have a side of `half`
+fn child_index(x: u64, y: u64, half: u64) -> usize {
+    (if y >= half { 2 } else { 0 }) + (if x >= half { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashLife;
+
+    use sparse::SparseGrid;
+
+    const R_PENTOMINO: [(i64, i64); 5] = [(1, 0), (2, 0), (0, 1), (1, 1), (1, 2)];
+
+    #[test]
+    fn test_set_cell_state() {
+        let mut hashlife = HashLife::new(&[2, 3], &[3]).unwrap();
+
+        hashlife.set_cell_state(-1000, 5, 255).unwrap();
+        hashlife.set_cell_state(7, -3, 255).unwrap();
+        hashlife.set_cell_state(7, -3, 0).unwrap();
+
+        assert_eq!(255, hashlife.get_cell_state(-1000, 5));
+        assert_eq!(0, hashlife.get_cell_state(7, -3));
+        assert_eq!(1, hashlife.population());
+        assert_eq!(Some((-1000, -1000, 5, 5)), hashlife.bounding_box());
+
+        assert!(HashLife::new(&[2, 3], &[0, 3]).is_err());
+    }
+
+    #[test]
+    fn test_glider() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut hashlife = HashLife::new(&[2, 3], &[3]).unwrap();
+        for &(x, y) in glider.iter() {
+            hashlife.set_cell_state(x, y, 255).unwrap();
+        }
+
+        // A glider moves by one cell diagonally every 4 generations
+        hashlife.step_pow2(10).unwrap();
+
+        let mut expected: Vec<(i64, i64)> =
+            glider.iter().map(|&(x, y)| (x + 256, y + 256)).collect();
+        expected.sort_by_key(|&(x, y)| (y, x));
+
+        assert_eq!(1024, hashlife.get_generation());
+        assert_eq!(5, hashlife.population());
+        assert_eq!(expected, hashlife.live_cells());
+    }
+
+    #[test]
+    fn test_same_as_sparse_grid() {
+        let mut hashlife = HashLife::new(&[2, 3], &[3]).unwrap();
+        let mut sparse = SparseGrid::new(&[2, 3], &[3]);
+        for &(x, y) in R_PENTOMINO.iter() {
+            hashlife.set_cell_state(x, y, 255).unwrap();
+            sparse.set_cell_state(x, y, 255);
+        }
+
+        hashlife.next_gen().unwrap();
+        hashlife.step_pow2(1).unwrap();
+        hashlife.step_pow2(6).unwrap();
+        for _ in 0..1 + 2 + 64 {
+            sparse.next_gen();
+        }
+
+        assert_eq!(67, hashlife.get_generation());
+        assert_eq!(sparse.population(), hashlife.population());
+        assert_eq!(sparse.live_cells(), hashlife.live_cells());
+    }
+
+    #[test]
+    fn test_garbage_collection() {
+        let mut hashlife = HashLife::new(&[2, 3], &[3]).unwrap();
+        for &(x, y) in R_PENTOMINO.iter() {
+            hashlife.set_cell_state(x, y, 255).unwrap();
+        }
+        let mut reference = hashlife.clone();
+
+        hashlife.set_memory_limit(0);
+        for _ in 0..4 {
+            hashlife.step_pow2(5).unwrap();
+        }
+        reference.step_pow2(7).unwrap();
+
+        assert_eq!(reference.live_cells(), hashlife.live_cells());
+
+        let node_count = reference.node_count();
+        reference.collect_garbage();
+        assert!(reference.node_count() < node_count);
+        assert_eq!(hashlife.live_cells(), reference.live_cells());
+    }
+}
@@ -0,0 +1,308 @@
+//! This module contains an optional history of the last generations of a
+//! grid, so that a simulation can be rewound.
+//!
+//! The state of the grid is recorded before each step. Each recorded
+//! generation only stores the cells which changed since the previous one,
+//! unless the size or the topology of the grid changed. The generations
+//! computed in a single step (see `step_async`) aren't recorded: they are
+//! simulated again from the nearest recorded generation.
+
+use std::collections::VecDeque;
+
+use super::vulkano::buffer::BufferUsage;
+use super::vulkano::buffer::CpuAccessibleBuffer;
+
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// The cells of a recorded generation.
+enum Record {
+    /// All the cells, one byte per cell.
+    Full(Vec<u8>),
+    /// The cells which changed since the previous recorded generation,
+    /// as `(index, state)`.
+    Delta(Vec<(u32, u8)>),
+}
+
+/// A recorded generation.
+struct Snapshot {
+    generation: u64,
+    width: usize,
+    height: usize,
+    topology: Topology,
+    cells: Record,
+}
+
+/// The last recorded generations of a grid, from the oldest to the most
+/// recent one. The oldest generation is always fully stored.
+pub(crate) struct History {
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+    latest: Vec<u8>, // Cells of the most recent snapshot
+}
+
+impl History {
+    fn new(capacity: usize) -> History {
+        History {
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+            latest: Vec::new(),
+        }
+    }
+
+    // Records the cells of `generation`, replacing the more recent ones
+    fn push(
+        &mut self,
+        generation: u64,
+        (width, height): (usize, usize),
+        topology: Topology,
+        cells: Vec<u8>,
+    ) {
+        self.truncate(generation);
+
+        let record = match self.snapshots.back() {
+            Some(last) if (last.width, last.height, last.topology) == (width, height, topology) => {
+                Record::Delta(
+                    self.latest
+                        .iter()
+                        .zip(cells.iter())
+                        .enumerate()
+                        .filter(|&(_, (old, new))| old != new)
+                        .map(|(i, (_, &new))| (i as u32, new))
+                        .collect(),
+                )
+            }
+            _ => Record::Full(cells.clone()),
+        };
+
+        self.snapshots.push_back(Snapshot {
+            generation,
+            width,
+            height,
+            topology,
+            cells: record,
+        });
+        self.latest = cells;
+
+        while self.snapshots.len() > self.capacity {
+            self.pop_oldest();
+        }
+    }
+
+    // Forgets the recorded generations from `generation` onwards
+    fn truncate(&mut self, generation: u64) {
+        let kept = self
+            .snapshots
+            .iter()
+            .take_while(|snapshot| snapshot.generation < generation)
+            .count();
+        if kept == self.snapshots.len() {
+            return;
+        }
+
+        self.snapshots.truncate(kept);
+        self.latest = match kept {
+            0 => Vec::new(),
+            _ => self.cells(kept - 1),
+        };
+    }
+
+    // Forgets the oldest generation, the next one being fully stored
+    fn pop_oldest(&mut self) {
+        let oldest = match self.snapshots.pop_front() {
+            Some(Snapshot {
+                cells: Record::Full(cells),
+                ..
+            }) => cells,
+            _ => return,
+        };
+
+        if let Some(next) = self.snapshots.front_mut() {
+            if let Record::Delta(ref delta) = next.cells {
+                next.cells = Record::Full(apply_delta(oldest, delta));
+            }
+        }
+    }
+
+    // Returns the index of the most recent snapshot which isn't more
+    // recent than `generation`
+    fn nearest(&self, generation: u64) -> Option<usize> {
+        self.snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.generation <= generation)
+    }
+
+    // Returns the cells of the snapshot `i`
+    fn cells(&self, i: usize) -> Vec<u8> {
+        let mut cells = Vec::new();
+        for snapshot in self.snapshots.iter().take(i + 1) {
+            cells = match snapshot.cells {
+                Record::Full(ref full) => full.clone(),
+                Record::Delta(ref delta) => apply_delta(cells, delta),
+            };
+        }
+
+        cells
+    }
+}
+
+fn apply_delta(mut cells: Vec<u8>, delta: &[(u32, u8)]) -> Vec<u8> {
+    for &(i, state) in delta {
+        cells[i as usize] = state;
+    }
+
+    cells
+}
+
+impl Grid {
+    /// Starts recording the last `capacity` generations of the current
+    /// `Grid`, so that it can be rewound. If the history is already
+    /// enabled, the recorded generations are kept within the new capacity.
+    pub fn enable_history(&mut self, capacity: usize) {
+        match self.history {
+            Some(ref mut history) => {
+                history.capacity = capacity.max(1);
+                while history.snapshots.len() > history.capacity {
+                    history.pop_oldest();
+                }
+            }
+            None => self.history = Some(History::new(capacity)),
+        }
+    }
+
+    /// Stops recording the generations and forgets the recorded ones.
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Returns `true` if the generations of the current `Grid` are
+    /// recorded.
+    pub fn is_history_enabled(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// Goes back `k` generations (see `seek`).
+    pub fn rewind(&mut self, k: u64) -> Result<(), GridErrorKind> {
+        if k > self.generation {
+            return Err(GridErrorKind::UnavailableGeneration);
+        }
+
+        let generation = self.generation - k;
+        self.seek(generation)
+    }
+
+    /// Restores the current `Grid` as it was at `generation`, from the
+    /// nearest recorded generation which isn't more recent. The missing
+    /// generations are simulated again, so a later generation can be
+    /// reached without any history.
+    ///
+    /// Returns `Err(GridErrorKind::UnavailableGeneration)` if `generation`
+    /// is in the past and older than the recorded generations.
+    pub fn seek(&mut self, generation: u64) -> Result<(), GridErrorKind> {
+        if generation == self.generation {
+            return Ok(());
+        }
+
+        // The current generation can be seeked again
+        self.record_history();
+
+        let snapshot = self
+            .history
+            .as_ref()
+            .and_then(|history| history.nearest(generation))
+            .filter(|_| generation < self.generation);
+        if let Some(i) = snapshot {
+            self.restore_snapshot(i);
+        } else if generation < self.generation {
+            return Err(GridErrorKind::UnavailableGeneration);
+        }
+
+        while self.generation < generation {
+            self.next_gen();
+        }
+
+        Ok(())
+    }
+
+    /// Records the current generation in the history, if it is enabled.
+    pub(crate) fn record_history(&mut self) {
+        if self.history.is_none() {
+            return;
+        }
+
+        let cells = self.read_cells();
+        let (generation, size, topology) =
+            (self.generation, (self.width, self.height), self.topology);
+        if let Some(ref mut history) = self.history {
+            history.push(generation, size, topology, cells);
+        }
+    }
+
+    // Restores the snapshot `i` of the history
+    fn restore_snapshot(&mut self, i: usize) {
+        let (generation, width, height, topology, cells) = {
+            let history = self.history.as_ref().unwrap();
+            let snapshot = &history.snapshots[i];
+            (
+                snapshot.generation,
+                snapshot.width,
+                snapshot.height,
+                snapshot.topology,
+                history.cells(i),
+            )
+        };
+
+        let cells = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            cells.into_iter(),
+        )
+        .expect("failed to create buffer");
+
+        self.clear_journal();
+        self.generation = generation;
+        self.width = width;
+        self.height = height;
+        self.topology = topology;
+        self.set_byte_cells(cells);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, Record};
+
+    use topology::Topology;
+
+    #[test]
+    fn test_delta_compression() {
+        let mut history = History::new(3);
+        history.push(0, (2, 2), Topology::Plane, vec![0, 255, 0, 0]);
+        history.push(1, (2, 2), Topology::Plane, vec![0, 0, 255, 0]);
+        history.push(2, (3, 1), Topology::Plane, vec![255, 0, 255]);
+        history.push(3, (3, 1), Topology::Plane, vec![255, 255, 255]);
+
+        // The oldest generation is forgotten, the next one becomes full
+        assert_eq!(3, history.snapshots.len());
+        match history.snapshots[0].cells {
+            Record::Full(ref cells) => assert_eq!(&vec![0, 0, 255, 0], cells),
+            _ => panic!("the oldest generation should be fully stored"),
+        }
+        match history.snapshots[2].cells {
+            Record::Delta(ref delta) => assert_eq!(&vec![(1, 255)], delta),
+            _ => panic!("the same size should be stored as a delta"),
+        }
+        assert_eq!(vec![255, 0, 255], history.cells(1));
+
+        assert_eq!(Some(1), history.nearest(2));
+        assert_eq!(Some(2), history.nearest(10));
+        assert_eq!(None, history.nearest(0));
+
+        // Recording an older generation replaces the more recent ones
+        history.push(2, (3, 1), Topology::Plane, vec![0, 0, 0]);
+        assert_eq!(2, history.snapshots.len());
+        assert_eq!(vec![0, 0, 0], history.cells(1));
+        assert_eq!(vec![0, 0, 0], history.latest);
+    }
+}
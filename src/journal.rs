@@ -0,0 +1,419 @@
+//! This module contains an optional journal of the edits of a grid, so
+//! that interactive editors can undo and redo them.
+//!
+//! The changed cells, the changes of size or topology (pastes, transforms,
+//! resizing) and the changes of rules are recorded. Computing generations
+//! isn't an edit: it clears the journal, since the recorded cells no
+//! longer match the grid.
+
+use std::collections::VecDeque;
+use std::mem;
+
+use super::vulkano::buffer::BufferUsage;
+use super::vulkano::buffer::CpuAccessibleBuffer;
+
+use topology::Topology;
+use Grid;
+
+/// The size, the topology, the file format and the cells of a grid.
+struct Frame {
+    width: usize,
+    height: usize,
+    topology: Topology,
+    format: String,
+    cells: Vec<u8>,
+}
+
+/// A reversible edit.
+enum Edit {
+    /// Changed cells, as `(x, y, before, after)`.
+    Cells(Vec<(usize, usize, u8, u8)>),
+    /// Change of size, topology or format, as the frames before and after it.
+    Frame(Frame, Frame),
+    /// Change of rules, as the `(survival, birth)` before and after it.
+    Rules((Vec<u32>, Vec<u32>), (Vec<u32>, Vec<u32>)),
+}
+
+impl Edit {
+    // Returns the edit which turns `before` into `after`, if they differ
+    fn between(before: Frame, after: Frame) -> Option<Edit> {
+        if (before.width, before.height, before.topology, &before.format)
+            != (after.width, after.height, after.topology, &after.format)
+        {
+            return Some(Edit::Frame(before, after));
+        }
+
+        let cells: Vec<(usize, usize, u8, u8)> = before
+            .cells
+            .iter()
+            .zip(after.cells.iter())
+            .enumerate()
+            .filter(|&(_, (b, a))| b != a)
+            .map(|(i, (&b, &a))| (i % after.width, i / after.width, b, a))
+            .collect();
+
+        if cells.is_empty() {
+            None
+        } else {
+            Some(Edit::Cells(cells))
+        }
+    }
+
+    // Returns the number of bytes used to store the edit
+    fn memory(&self) -> usize {
+        match *self {
+            Edit::Cells(ref cells) => cells.len() * mem::size_of::<(usize, usize, u8, u8)>(),
+            Edit::Frame(ref before, ref after) => {
+                before.format.len() + before.cells.len() + after.format.len() + after.cells.len()
+            }
+            Edit::Rules(ref before, ref after) => {
+                (before.0.len() + before.1.len() + after.0.len() + after.1.len())
+                    * mem::size_of::<u32>()
+            }
+        }
+    }
+}
+
+fn transaction_memory(transaction: &[Edit]) -> usize {
+    transaction.iter().map(Edit::memory).sum()
+}
+
+/// The undoable and redoable transactions of a grid.
+pub(crate) struct Journal {
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    transaction: Vec<Edit>,
+    depth: usize, // Number of nested transactions in progress
+    memory: usize,
+    memory_cap: usize,
+}
+
+impl Journal {
+    fn new(memory_cap: usize) -> Journal {
+        Journal {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            transaction: Vec::new(),
+            depth: 0,
+            memory: 0,
+            memory_cap,
+        }
+    }
+
+    fn record(&mut self, edit: Edit) {
+        for transaction in self.redo.drain(..) {
+            self.memory -= transaction_memory(&transaction);
+        }
+
+        self.memory += edit.memory();
+        self.transaction.push(edit);
+        if self.depth == 0 {
+            self.close_transaction();
+        }
+    }
+
+    // Makes the edits of the transaction in progress undoable
+    fn close_transaction(&mut self) {
+        self.depth = 0;
+        if !self.transaction.is_empty() {
+            let transaction = mem::take(&mut self.transaction);
+            self.undo.push_back(transaction);
+        }
+        self.trim();
+    }
+
+    // Forgets the oldest transactions until the memory cap is respected
+    fn trim(&mut self) {
+        while self.memory > self.memory_cap {
+            let transaction = match self.undo.pop_front() {
+                Some(transaction) => transaction,
+                None => match self.redo.pop() {
+                    Some(transaction) => transaction,
+                    None => break,
+                },
+            };
+            self.memory -= transaction_memory(&transaction);
+        }
+    }
+}
+
+impl Grid {
+    /// Starts recording the edits of the current `Grid`, so that they can
+    /// be undone. The oldest edits are forgotten when the recorded edits
+    /// use more than `memory_cap` bytes.
+    ///
+    /// If the journal is already enabled, only its memory cap is changed.
+    pub fn enable_journal(&mut self, memory_cap: usize) {
+        match self.journal {
+            Some(ref mut journal) => {
+                journal.memory_cap = memory_cap;
+                journal.trim();
+            }
+            None => self.journal = Some(Journal::new(memory_cap)),
+        }
+    }
+
+    /// Stops recording the edits and forgets the recorded ones.
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Returns `true` if the edits of the current `Grid` are recorded.
+    pub fn is_journal_enabled(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Forgets the recorded edits, the journal staying enabled.
+    pub fn clear_journal(&mut self) {
+        if let Some(ref mut journal) = self.journal {
+            *journal = Journal::new(journal.memory_cap);
+        }
+    }
+
+    /// Starts a transaction: the edits made until the matching
+    /// `end_transaction` are undone and redone at once. Transactions can
+    /// be nested.
+    pub fn begin_transaction(&mut self) {
+        if let Some(ref mut journal) = self.journal {
+            journal.depth += 1;
+        }
+    }
+
+    /// Ends the transaction started by `begin_transaction`.
+    pub fn end_transaction(&mut self) {
+        if let Some(ref mut journal) = self.journal {
+            if journal.depth > 1 {
+                journal.depth -= 1;
+            } else {
+                journal.close_transaction();
+            }
+        }
+    }
+
+    /// Returns `true` if there is an edit to undo.
+    pub fn can_undo(&self) -> bool {
+        match self.journal {
+            Some(ref journal) => !journal.undo.is_empty() || !journal.transaction.is_empty(),
+            None => false,
+        }
+    }
+
+    /// Returns `true` if there is an undone edit to redo.
+    pub fn can_redo(&self) -> bool {
+        match self.journal {
+            Some(ref journal) => !journal.redo.is_empty(),
+            None => false,
+        }
+    }
+
+    /// Undoes the last edit or transaction. A transaction in progress is
+    /// ended first. Returns `false` if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return false,
+        };
+        journal.close_transaction();
+
+        let undone = match journal.undo.pop_back() {
+            Some(transaction) => {
+                for edit in transaction.iter().rev() {
+                    self.apply_edit(edit, false);
+                }
+                journal.redo.push(transaction);
+                true
+            }
+            None => false,
+        };
+
+        self.journal = Some(journal);
+        undone
+    }
+
+    /// Redoes the last undone edit or transaction. Returns `false` if
+    /// there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return false,
+        };
+        journal.close_transaction();
+
+        let redone = match journal.redo.pop() {
+            Some(transaction) => {
+                for edit in &transaction {
+                    self.apply_edit(edit, true);
+                }
+                journal.undo.push_back(transaction);
+                true
+            }
+            None => false,
+        };
+
+        self.journal = Some(journal);
+        redone
+    }
+
+    /// Runs `edit` on the current `Grid`, and records the changes it made
+    /// to the cells, the size and the topology if the journal is enabled.
+    pub(crate) fn journaled<R, F>(&mut self, edit: F) -> R
+    where
+        F: FnOnce(&mut Grid) -> R,
+    {
+        self.edited();
+
+        // The journal is taken out so that the nested edits aren't recorded
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return edit(self),
+        };
+
+        let before = self.frame();
+        let result = edit(self);
+        if let Some(edit) = Edit::between(before, self.frame()) {
+            journal.record(edit);
+        }
+
+        self.journal = Some(journal);
+        result
+    }
+
+    /// Works like `journaled` for an edit of the rules.
+    pub(crate) fn journaled_rules<F>(&mut self, edit: F)
+    where
+        F: FnOnce(&mut Grid),
+    {
+        let mut journal = match self.journal.take() {
+            Some(journal) => journal,
+            None => return edit(self),
+        };
+
+        let before = (self.get_survival(), self.get_birth());
+        edit(self);
+        let after = (self.get_survival(), self.get_birth());
+        if before != after {
+            journal.record(Edit::Rules(before, after));
+        }
+
+        self.journal = Some(journal);
+    }
+
+    /// Records the changes of cells given as `(x, y, before, after)`, if
+    /// the journal is enabled.
+    pub(crate) fn record_cells(&mut self, cells: Vec<(usize, usize, u8, u8)>) {
+        if let Some(ref mut journal) = self.journal {
+            let cells: Vec<_> = cells.into_iter().filter(|&(_, _, b, a)| b != a).collect();
+            if !cells.is_empty() {
+                journal.record(Edit::Cells(cells));
+            }
+        }
+    }
+
+    fn frame(&self) -> Frame {
+        Frame {
+            width: self.width,
+            height: self.height,
+            topology: self.topology,
+            format: self.format.clone(),
+            cells: self.read_cells(),
+        }
+    }
+
+    // Applies `edit` forwards, or backwards to revert it
+    fn apply_edit(&mut self, edit: &Edit, forwards: bool) {
+        match *edit {
+            Edit::Cells(ref cells) => {
+                let states: Vec<(usize, usize, u8)> = if forwards {
+                    cells.iter().map(|&(x, y, _, a)| (x, y, a)).collect()
+                } else {
+                    cells.iter().rev().map(|&(x, y, b, _)| (x, y, b)).collect()
+                };
+                self.set_cells(states).unwrap(); // Shouldn't fail
+            }
+            Edit::Frame(ref before, ref after) => {
+                let frame = if forwards { after } else { before };
+                let cells = CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::all(),
+                    frame.cells.iter().cloned(),
+                )
+                .expect("failed to create buffer");
+
+                self.width = frame.width;
+                self.height = frame.height;
+                self.topology = frame.topology;
+                self.format = frame.format.clone();
+                self.set_byte_cells(cells);
+                self.edited();
+            }
+            Edit::Rules(ref before, ref after) => {
+                let (survival, birth) = if forwards { after } else { before };
+                self.set_survival(survival);
+                self.set_birth(birth);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edit, Frame, Journal};
+
+    use topology::Topology;
+
+    fn frame(width: usize, height: usize, cells: Vec<u8>) -> Frame {
+        Frame {
+            width,
+            height,
+            topology: Topology::Plane,
+            format: String::from("#Life"),
+            cells,
+        }
+    }
+
+    #[test]
+    fn test_edits_between_frames() {
+        let edit = Edit::between(
+            frame(2, 2, vec![0, 255, 0, 0]),
+            frame(2, 2, vec![0, 0, 0, 255]),
+        );
+        match edit {
+            Some(Edit::Cells(cells)) => assert_eq!(vec![(1, 0, 255, 0), (1, 1, 0, 255)], cells),
+            _ => panic!("expected changed cells"),
+        }
+
+        assert!(Edit::between(frame(2, 2, vec![0; 4]), frame(2, 2, vec![0; 4])).is_none());
+        match Edit::between(frame(2, 2, vec![0; 4]), frame(4, 1, vec![0; 4])) {
+            Some(Edit::Frame(..)) => {}
+            _ => panic!("expected a new frame"),
+        }
+
+        let mut toroidal = frame(2, 2, vec![0; 4]);
+        toroidal.format = String::from("#Toroidal Life");
+        match Edit::between(frame(2, 2, vec![0; 4]), toroidal) {
+            Some(Edit::Frame(..)) => {}
+            _ => panic!("expected a new frame"),
+        }
+    }
+
+    #[test]
+    fn test_memory_cap() {
+        let edit_memory = Edit::Cells(vec![(0, 0, 0, 255)]).memory();
+        let mut journal = Journal::new(3 * edit_memory);
+
+        for x in 0..5 {
+            journal.record(Edit::Cells(vec![(x, 0, 0, 255)]));
+        }
+        assert_eq!(3, journal.undo.len());
+        assert_eq!(3 * edit_memory, journal.memory);
+
+        // A transaction is recorded at once
+        journal.depth = 1;
+        journal.record(Edit::Cells(vec![(0, 1, 0, 255)]));
+        journal.record(Edit::Cells(vec![(1, 1, 0, 255)]));
+        assert_eq!(3, journal.undo.len());
+        journal.close_transaction();
+        assert_eq!(2, journal.undo.len());
+        assert_eq!(2, journal.undo.back().unwrap().len());
+    }
+}
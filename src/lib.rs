@@ -1,16 +1,38 @@
 //! This crate provides an interface to manipulate life
 //! cellular automata grids.
-//! Those grids can be toroidal or resizable.
+//! Those grids can be resizable or have a bounded topology
+//! (toroidal, Klein bottle, sphere...).
 
 extern crate rand;
+extern crate rayon;
 #[macro_use]
 extern crate vulkano_shader_derive;
 extern crate vulkano;
 
+pub mod access;
 pub mod analysis;
+pub mod batch;
+pub mod census;
+pub mod classification;
+pub mod comparison;
+pub mod context;
+mod cpu;
+pub mod editing;
 pub mod error;
 pub mod file;
+pub mod hashlife;
+pub mod history;
+pub mod journal;
+pub mod packed;
+pub mod partition;
 pub mod processing;
+pub mod resizing;
+pub mod soup;
+pub mod sparse;
+pub mod stabilisation;
+pub mod stepping;
+pub mod topology;
+pub mod transform;
 pub mod view;
 mod vulkan;
 
@@ -22,13 +44,19 @@ use vulkano::buffer::CpuAccessibleBuffer;
 use vulkano::device::Device;
 use vulkano::device::Queue;
 
+use context::Context;
 use error::GridErrorKind;
+use history::History;
+use journal::Journal;
+use soup::SoupOptions;
+use topology::Topology;
 
 /// This struct contains the grid of a life cellular automaton.
 ///
-/// This grid is stored as a `Vec<bool>`.
-/// When it is toroidal, its size is constant. When it is not,
-/// it is resized when computing the next generation
+/// This grid is stored as a `Vec<bool>`, or as 32 cells per `u32` when
+/// it is bit-packed (see `set_bit_packed`).
+/// When it has a bounded topology, its size is constant. When it is
+/// resizable, it is resized when computing the next generation
 /// according to the size of the contained pattern.
 ///
 /// The origin of the pattern is also stored in `Grid`:
@@ -38,47 +66,85 @@ use error::GridErrorKind;
 /// It also contains the cellular automaton's rules stored as two `Vec<u8>`s.
 /// These are the survival and birth conditions into `survival` and `birth`
 /// respectivly.
+///
+/// The rules can be made stochastic: a cell is born or survives only with
+/// a given probability, and each cell is updated only with a given
+/// probability (asynchronous updating). The random numbers are drawn from
+/// a counter-based generator seeded with `seed` and the current generation,
+/// so that the same run can be reproduced on the GPU and on the CPU.
 pub struct Grid {
-    format: String,                          // Contains the file format used
-    toroidal: Arc<CpuAccessibleBuffer<i32>>, // Resizable grid if set to false (note: false = 0 and true = 1)
+    format: String, // Contains the file format used
+    topology: Topology,
 
     survival: Arc<CpuAccessibleBuffer<[u32]>>,
     birth: Arc<CpuAccessibleBuffer<[u32]>>,
 
+    birth_probability: f64,
+    survival_probability: f64,
+    update_probability: f64,
+    seed: u64,
+    generation: u64, // Number of computed generations, used to seed the random numbers
+    generation_reset_on_edit: bool,
+
     width: usize,
     height: usize,
-    cells: Arc<CpuAccessibleBuffer<[u8]>>,
+    cells: Cells,
+    max_tile_size: Option<usize>,
+    changed_tiles: Option<Vec<bool>>, // 8x8 tiles changed by the last generation
+    journal: Option<Journal>,         // Recorded edits, if enabled
+    history: Option<History>,         // Recorded generations, if enabled
 
     device: Arc<Device>,
     queue: Arc<Queue>,
 }
 
+/// Storage of the cells of a `Grid`.
+#[derive(Clone)]
+enum Cells {
+    /// One byte per cell, the living cells being set to `255`.
+    Bytes(Arc<CpuAccessibleBuffer<[u8]>>),
+    /// 32 cells per `u32`: the bit `i` of the word `j` of a row is the
+    /// cell at the column `32 * j + i`. Each row starts on a new word.
+    Packed(Arc<CpuAccessibleBuffer<[u32]>>),
+}
+
 impl Grid {
     /// Returns a new `Grid`:
     /// * containing the file format `frmt`
-    /// * toroidal if `trdl` is `true`, resizable otherwise
+    /// * whose edges are joined according to `topology`
     /// * containing the rules given by `srvl` and `brth`
     /// * whose grid's size is determined by `width` and `height`
+    ///
+    /// Its generations are computed on the first discrete GPU.
     pub fn new(
         frmt: &String,
-        trdl: bool,
+        topology: Topology,
         srvl: &Vec<u32>,
         brth: &Vec<u32>,
         width: usize,
         height: usize,
     ) -> Grid {
-        let (device, queue) = vulkan::vk_init();
+        Grid::with_context(&Context::new(), frmt, topology, srvl, brth, width, height)
+    }
+
+    /// Returns a new `Grid` like `new`, whose generations are computed
+    /// on the device of `context`.
+    pub fn with_context(
+        context: &Context,
+        frmt: &String,
+        topology: Topology,
+        srvl: &Vec<u32>,
+        brth: &Vec<u32>,
+        width: usize,
+        height: usize,
+    ) -> Grid {
+        let (device, queue) = (context.get_device(), context.get_queue());
 
         let new_cells_iter = (0..width * height).map(|_| 0u8);
         let new_cells =
             CpuAccessibleBuffer::from_iter(device.clone(), BufferUsage::all(), new_cells_iter)
                 .expect("failed to create buffer");
 
-        let toroidal_val = if trdl { 1 } else { 0 };
-        let toroidal =
-            CpuAccessibleBuffer::from_data(device.clone(), BufferUsage::all(), toroidal_val)
-                .expect("failed to create buffer");
-
         let survival = CpuAccessibleBuffer::from_iter(
             device.clone(),
             BufferUsage::all(),
@@ -95,12 +161,22 @@ impl Grid {
 
         Grid {
             format: frmt.clone(),
-            toroidal,
+            topology,
             survival,
             birth,
+            birth_probability: 1.0,
+            survival_probability: 1.0,
+            update_probability: 1.0,
+            seed: 0,
+            generation: 0,
+            generation_reset_on_edit: false,
             width,
             height,
-            cells: new_cells,
+            cells: Cells::Bytes(new_cells),
+            max_tile_size: None,
+            changed_tiles: None,
+            journal: None,
+            history: None,
             device,
             queue,
         }
@@ -109,17 +185,38 @@ impl Grid {
     /// Returns a new `Grid` and initializes its cells randomly.
     pub fn new_random(
         frmt: &String,
-        trdl: bool,
+        topology: Topology,
         srvl: &Vec<u32>,
         brth: &Vec<u32>,
         width: usize,
         height: usize,
     ) -> Grid {
-        let mut new_grid = Grid::new(frmt, trdl, srvl, brth, width, height);
+        let mut new_grid = Grid::new(frmt, topology, srvl, brth, width, height);
         new_grid.randomize();
         new_grid
     }
 
+    /// Returns a new `Grid` and initializes its cells with the random
+    /// soup described by `options` (see `randomize_soup`).
+    pub fn new_random_with(
+        frmt: &String,
+        topology: Topology,
+        srvl: &Vec<u32>,
+        brth: &Vec<u32>,
+        width: usize,
+        height: usize,
+        options: &SoupOptions,
+    ) -> Result<Grid, GridErrorKind> {
+        let mut new_grid = Grid::new(frmt, topology, srvl, brth, width, height);
+        new_grid.randomize_soup(options)?;
+        Ok(new_grid)
+    }
+
+    /// Returns the context of the device computing the generations.
+    pub fn get_context(&self) -> Context {
+        Context::from_parts(self.device.clone(), self.queue.clone())
+    }
+
     /// Returns the file format used.
     pub fn get_format(&self) -> String {
         self.format.clone()
@@ -130,16 +227,25 @@ impl Grid {
         self.format = frmt.clone();
     }
 
-    /// Returns `true` if the grid is toroidal. Otherwise the grid is resizable.
-    pub fn is_toroidal(&self) -> bool {
-        let toroidal = self.toroidal.read().unwrap();
+    /// Returns the topology of the grid.
+    pub fn get_topology(&self) -> Topology {
+        self.topology
+    }
 
-        match *toroidal {
-            0 => false,
-            _ => true,
+    /// Returns `true` if the grid is toroidal (with or without a shift).
+    pub fn is_toroidal(&self) -> bool {
+        match self.topology {
+            Topology::Torus { .. } => true,
+            _ => false,
         }
     }
 
+    /// Returns `true` if the grid is resizable. Otherwise its size
+    /// is constant, except for the height of cylindrical grids.
+    pub fn is_resizable(&self) -> bool {
+        self.topology.is_resizable()
+    }
+
     /// Returns the survival conditions of the cellular automaton.
     pub fn get_survival(&self) -> Vec<u32> {
         self.survival.read().unwrap().to_vec()
@@ -147,12 +253,15 @@ impl Grid {
 
     /// Redefines the survival conditions of the cellular automaton.
     pub fn set_survival(&mut self, srvl: &Vec<u32>) {
-        self.survival = CpuAccessibleBuffer::from_iter(
-            self.device.clone(),
-            BufferUsage::all(),
-            srvl.iter().map(|&n| n),
-        )
-        .expect("failed to create buffer");
+        self.journaled_rules(|grid| {
+            grid.changed_tiles = None;
+            grid.survival = CpuAccessibleBuffer::from_iter(
+                grid.device.clone(),
+                BufferUsage::all(),
+                srvl.iter().map(|&n| n),
+            )
+            .expect("failed to create buffer");
+        });
     }
 
     /// Returns the birth conditions of the cellular automaton.
@@ -162,12 +271,121 @@ impl Grid {
 
     /// Redefines the birth conditions of the cellular automaton.
     pub fn set_birth(&mut self, brth: &Vec<u32>) {
-        self.birth = CpuAccessibleBuffer::from_iter(
-            self.device.clone(),
-            BufferUsage::all(),
-            brth.iter().map(|&n| n),
-        )
-        .expect("failed to create buffer");
+        self.journaled_rules(|grid| {
+            grid.changed_tiles = None;
+            grid.birth = CpuAccessibleBuffer::from_iter(
+                grid.device.clone(),
+                BufferUsage::all(),
+                brth.iter().map(|&n| n),
+            )
+            .expect("failed to create buffer");
+        });
+    }
+
+    /// Returns the probability for a cell to be born when the birth
+    /// conditions are met.
+    pub fn get_birth_probability(&self) -> f64 {
+        self.birth_probability
+    }
+
+    /// Redefines the probability for a cell to be born when the birth
+    /// conditions are met.
+    /// Returns `Err(GridErrorKind::InvalidProbability)` if `p` isn't
+    /// between 0 and 1.
+    pub fn set_birth_probability(&mut self, p: f64) -> Result<(), GridErrorKind> {
+        self.birth_probability = valid_probability(p)?;
+        Ok(())
+    }
+
+    /// Returns the probability for a cell to survive when the survival
+    /// conditions are met.
+    pub fn get_survival_probability(&self) -> f64 {
+        self.survival_probability
+    }
+
+    /// Redefines the probability for a cell to survive when the survival
+    /// conditions are met.
+    /// Returns `Err(GridErrorKind::InvalidProbability)` if `p` isn't
+    /// between 0 and 1.
+    pub fn set_survival_probability(&mut self, p: f64) -> Result<(), GridErrorKind> {
+        self.survival_probability = valid_probability(p)?;
+        Ok(())
+    }
+
+    /// Returns the probability for each cell to be updated when
+    /// computing a generation.
+    pub fn get_update_probability(&self) -> f64 {
+        self.update_probability
+    }
+
+    /// Redefines the probability for each cell to be updated when
+    /// computing a generation. A probability lower than 1 makes the
+    /// updating asynchronous.
+    /// Returns `Err(GridErrorKind::InvalidProbability)` if `p` isn't
+    /// between 0 and 1.
+    pub fn set_update_probability(&mut self, p: f64) -> Result<(), GridErrorKind> {
+        self.update_probability = valid_probability(p)?;
+        Ok(())
+    }
+
+    /// Returns the seed of the random numbers used by stochastic rules.
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Redefines the seed of the random numbers used by stochastic rules.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Returns the number of generations computed since the creation of
+    /// the current `Grid`, or since its generation was set.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Redefines the current generation. The random numbers of
+    /// stochastic rules depend on it.
+    pub fn set_generation(&mut self, generation: u64) {
+        self.generation = generation;
+    }
+
+    /// Returns `true` if editing the cells resets the generation to 0.
+    pub fn is_generation_reset_on_edit(&self) -> bool {
+        self.generation_reset_on_edit
+    }
+
+    /// Chooses whether editing the cells (e.g. with `set_cell_state`,
+    /// `paste` or a transform) resets the generation to 0, the edited
+    /// pattern being considered as a new one.
+    pub fn set_generation_reset_on_edit(&mut self, reset: bool) {
+        self.generation_reset_on_edit = reset;
+    }
+
+    /// Resets the generation if the cells were edited and it is enabled.
+    pub(crate) fn edited(&mut self) {
+        if self.generation_reset_on_edit {
+            self.generation = 0;
+        }
+    }
+
+    /// Returns the parameters of the stochastic rules for the
+    /// next generation.
+    fn stochastic(&self) -> cpu::Stochastic {
+        cpu::Stochastic {
+            birth: cpu::probability_to_threshold(self.birth_probability),
+            survival: cpu::probability_to_threshold(self.survival_probability),
+            update: cpu::probability_to_threshold(self.update_probability),
+            seed: self.seed,
+            generation: self.generation,
+        }
+    }
+
+    /// Returns `true` if the rules of the grid aren't stochastic.
+    fn is_deterministic(&self) -> bool {
+        self.birth_probability == 1.0
+            && self.survival_probability == 1.0
+            && self.update_probability == 1.0
     }
 
     /// Returns the width of the grid.
@@ -180,45 +398,54 @@ impl Grid {
         self.height
     }
 
+    /// Returns the maximum size of the side of the tiles the grid is split
+    /// into when it is too large to be stored in a single image. It is
+    /// limited by the largest image supported by the device.
+    pub fn get_max_tile_size(&self) -> usize {
+        // The tiles are stored with a halo of one cell around them
+        let device_limit = self
+            .device
+            .physical_device()
+            .limits()
+            .max_image_dimension_2d() as usize
+            - 2;
+
+        match self.max_tile_size {
+            Some(size) => size.min(device_limit),
+            None => device_limit,
+        }
+    }
+
+    /// Sets the maximum size of the side of the tiles. `None` uses the
+    /// largest size supported by the device.
+    pub fn set_max_tile_size(&mut self, size: Option<usize>) {
+        self.max_tile_size = size.map(|size| size.max(1));
+    }
+
     /// Returns the state of the cell at the coordinates (`x`, `y`).
     ///
-    /// If the coordinates are out of bounds and the grid is toroidal,
-    /// then it returns the state of the cell at the coordinates
-    /// modulo the size of the grid.
-    /// Otherwise, if the coordinates are out of bounds but
-    /// the grid is not toroidal, it returns `0u8`.
+    /// If the coordinates are out of bounds, the edges of the grid are
+    /// crossed according to its topology: for example, on a toroidal grid
+    /// it returns the state of the cell at the coordinates modulo the size
+    /// of the grid.
+    /// If there is no cell at these coordinates (e.g. the grid is
+    /// resizable or a bounded plane), it returns `0u8`.
     pub fn get_cell_state(&self, x: i64, y: i64) -> u8 {
-        let cells = self.cells.write().unwrap();
-
-        if cells.is_empty() {
-            return 0;
-        }
+        let (x, y) = match self.topology.map(x, y, self.width, self.height) {
+            Some(coords) => coords,
+            None => return 0,
+        };
 
-        // If the `x` and `y` parameters are out of bound of the grid
-        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
-            if self.is_toroidal() {
-                let (x, y) = (
-                    if x < 0 {
-                        (self.width as i64 + x) as usize
-                    } else if x as usize >= self.width {
-                        x as usize % self.width
-                    } else {
-                        x as usize
-                    },
-                    if y < 0 {
-                        (self.height as i64 + y) as usize
-                    } else if y as usize >= self.height {
-                        y as usize % self.height
-                    } else {
-                        y as usize
-                    },
-                );
-                cells[y * self.width + x]
-            } else {
-                0
+        match self.cells {
+            Cells::Bytes(ref cells) => cells.read().unwrap()[y * self.width + x],
+            Cells::Packed(ref words) => {
+                let word = words.read().unwrap()[y * self.words_per_row() + x / 32];
+                if word & (1 << (x % 32)) != 0 {
+                    255
+                } else {
+                    0
+                }
             }
-        } else {
-            cells[y as usize * self.width + x as usize]
         }
     }
 
@@ -227,41 +454,290 @@ impl Grid {
     /// Returns `Err(GridErrorKind::OutOfBoundCoords)` if the
     /// coordinates are out of bounds.
     pub fn set_cell_state(&mut self, x: usize, y: usize, state: u8) -> Result<(), GridErrorKind> {
-        let mut cells = self.cells.write().unwrap();
-
         if x >= self.width || y >= self.height {
-            Err(GridErrorKind::OutOfBoundCoords)
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+
+        self.edited();
+        if self.journal.is_some() {
+            let before = self.get_cell_state(x as i64, y as i64);
+            self.record_cells(vec![(x, y, before, state)]);
+        }
+
+        if let Some(ref mut changed_tiles) = self.changed_tiles {
+            let tiles_per_row = self.width.div_ceil(processing::ACTIVE_TILE_SIZE);
+            changed_tiles[(y / processing::ACTIVE_TILE_SIZE) * tiles_per_row
+                + x / processing::ACTIVE_TILE_SIZE] = true;
+        }
+
+        match self.cells {
+            Cells::Bytes(ref cells) => cells.write().unwrap()[y * self.width + x] = state,
+            Cells::Packed(ref words) => {
+                let word = &mut words.write().unwrap()[y * self.words_per_row() + x / 32];
+                if state != 0 {
+                    *word |= 1 << (x % 32);
+                } else {
+                    *word &= !(1 << (x % 32));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the cells are stored as 32 cells per `u32`.
+    pub fn is_bit_packed(&self) -> bool {
+        match self.cells {
+            Cells::Bytes(_) => false,
+            Cells::Packed(_) => true,
+        }
+    }
+
+    /// Chooses how the cells are stored. When `packed` is `true`, they
+    /// are stored as 32 cells per `u32` instead of one byte per cell,
+    /// which uses 8 times less memory, and the generations are computed
+    /// with bitwise operations. The cells are kept.
+    ///
+    /// A resizable grid is still unpacked to one byte per cell at each
+    /// generation to make room for its pattern, so the memory is only
+    /// saved with the bounded topologies.
+    pub fn set_bit_packed(&mut self, packed: bool) {
+        if packed == self.is_bit_packed() {
+            return;
+        }
+
+        let cells = self.read_cells();
+        self.changed_tiles = None;
+        self.cells = if packed {
+            Cells::Packed(
+                CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::all(),
+                    packed::pack_u32(&cells, self.width).into_iter(),
+                )
+                .expect("failed to create buffer"),
+            )
         } else {
-            cells[y * self.width + x] = state;
-            Ok(())
+            Cells::Bytes(
+                CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::all(),
+                    cells.into_iter(),
+                )
+                .expect("failed to create buffer"),
+            )
+        };
+    }
+
+    /// Returns the number of `u32`s used by a row of bit-packed cells.
+    fn words_per_row(&self) -> usize {
+        self.width.div_ceil(32)
+    }
+
+    /// Returns a copy of the cells, one byte per cell.
+    pub(crate) fn read_cells(&self) -> Vec<u8> {
+        match self.cells {
+            Cells::Bytes(ref cells) => cells.read().unwrap().to_vec(),
+            Cells::Packed(ref words) => {
+                packed::unpack_u32(&words.read().unwrap(), self.width, self.height)
+            }
         }
     }
+
+    /// Replaces the cells by `cells`, one byte per cell. The size of
+    /// the grid is unchanged.
+    pub(crate) fn write_cells(&mut self, cells: &[u8]) {
+        self.changed_tiles = None;
+        match self.cells {
+            Cells::Bytes(ref buffer) => buffer.write().unwrap().copy_from_slice(cells),
+            Cells::Packed(ref words) => words
+                .write()
+                .unwrap()
+                .copy_from_slice(&packed::pack_u32(cells, self.width)),
+        }
+    }
+
+    /// Returns a buffer of one byte per cell containing the cells.
+    /// The cells are unpacked into a new buffer if they are bit-packed.
+    pub(crate) fn byte_cells(&self) -> Arc<CpuAccessibleBuffer<[u8]>> {
+        match self.cells {
+            Cells::Bytes(ref cells) => cells.clone(),
+            Cells::Packed(_) => CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::all(),
+                self.read_cells().into_iter(),
+            )
+            .expect("failed to create buffer"),
+        }
+    }
+
+    /// Returns a buffer of one byte per cell containing the `width` x
+    /// `height` region whose north west corner is at (`x`, `y`).
+    pub(crate) fn byte_cells_region(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Arc<CpuAccessibleBuffer<[u8]>> {
+        let cells = self.read_region(x, y, width, height);
+
+        CpuAccessibleBuffer::from_iter(self.device.clone(), BufferUsage::all(), cells.into_iter())
+            .expect("failed to create buffer")
+    }
+
+    /// Returns a copy of the `width` x `height` region whose north west
+    /// corner is at (`x`, `y`), one byte per cell, row by row. The cells
+    /// beyond the edges of the grid are dead.
+    pub fn read_region(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<u8> {
+        let mut region = vec![0; width * height];
+        let words_per_row = self.words_per_row();
+
+        match self.cells {
+            Cells::Bytes(ref cells) => {
+                let cells = cells.read().unwrap();
+                for (i, cell) in region.iter_mut().enumerate() {
+                    let (cx, cy) = (x + i % width, y + i / width);
+                    if cx < self.width && cy < self.height {
+                        *cell = cells[cy * self.width + cx];
+                    }
+                }
+            }
+            Cells::Packed(ref words) => {
+                let words = words.read().unwrap();
+                for (i, cell) in region.iter_mut().enumerate() {
+                    let (cx, cy) = (x + i % width, y + i / width);
+                    if cx < self.width
+                        && cy < self.height
+                        && words[cy * words_per_row + cx / 32] & (1 << (cx % 32)) != 0
+                    {
+                        *cell = 255;
+                    }
+                }
+            }
+        }
+
+        region
+    }
+
+    /// Replaces the cells of the `width` x `height` region whose north
+    /// west corner is at (`x`, `y`) by `region`, one byte per cell, row
+    /// by row. The cells beyond the edges of the grid are ignored.
+    pub fn write_region(&mut self, x: usize, y: usize, width: usize, height: usize, region: &[u8]) {
+        self.journaled(|grid| grid.write_region_cells(x, y, width, height, region));
+    }
+
+    fn write_region_cells(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        region: &[u8],
+    ) {
+        self.changed_tiles = None;
+        let words_per_row = self.words_per_row();
+
+        match self.cells {
+            Cells::Bytes(ref cells) => {
+                let mut cells = cells.write().unwrap();
+                for (i, &cell) in region.iter().enumerate().take(width * height) {
+                    let (cx, cy) = (x + i % width, y + i / width);
+                    if cx < self.width && cy < self.height {
+                        cells[cy * self.width + cx] = cell;
+                    }
+                }
+            }
+            Cells::Packed(ref words) => {
+                let mut words = words.write().unwrap();
+                for (i, &cell) in region.iter().enumerate().take(width * height) {
+                    let (cx, cy) = (x + i % width, y + i / width);
+                    if cx < self.width && cy < self.height {
+                        let word = &mut words[cy * words_per_row + cx / 32];
+                        if cell != 0 {
+                            *word |= 1 << (cx % 32);
+                        } else {
+                            *word &= !(1 << (cx % 32));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces the cells by `cells`, one byte per cell, keeping the
+    /// current storage.
+    pub(crate) fn set_byte_cells(&mut self, cells: Arc<CpuAccessibleBuffer<[u8]>>) {
+        self.changed_tiles = None;
+        if self.is_bit_packed() {
+            let words = packed::pack_u32(&cells.read().unwrap(), self.width);
+            self.cells = Cells::Packed(
+                CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::all(),
+                    words.into_iter(),
+                )
+                .expect("failed to create buffer"),
+            );
+        } else {
+            self.cells = Cells::Bytes(cells);
+        }
+    }
+}
+
+fn valid_probability(p: f64) -> Result<f64, GridErrorKind> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(p)
+    } else {
+        Err(GridErrorKind::InvalidProbability)
+    }
 }
 
 impl Clone for Grid {
     fn clone(&self) -> Grid {
         let new_format = self.get_format();
-        let new_toroidal = self.is_toroidal();
+        let new_topology = self.get_topology();
         let new_survival = self.get_survival();
         let new_birth = self.get_birth();
         let new_width = self.get_width();
         let new_height = self.get_height();
 
-        let mut new_grid = Grid::new(
+        let mut new_grid = Grid::with_context(
+            &self.get_context(),
             &new_format,
-            new_toroidal,
+            new_topology,
             &new_survival,
             &new_birth,
             new_width,
             new_height,
         );
 
-        new_grid.cells = CpuAccessibleBuffer::from_iter(
-            new_grid.device.clone(),
-            BufferUsage::all(),
-            self.cells.read().unwrap().to_vec().into_iter(),
-        )
-        .expect("failed to create buffer");
+        new_grid.birth_probability = self.birth_probability;
+        new_grid.survival_probability = self.survival_probability;
+        new_grid.update_probability = self.update_probability;
+        new_grid.seed = self.seed;
+        new_grid.generation = self.generation;
+        new_grid.generation_reset_on_edit = self.generation_reset_on_edit;
+        new_grid.max_tile_size = self.max_tile_size;
+
+        new_grid.cells = match self.cells {
+            Cells::Bytes(ref cells) => Cells::Bytes(
+                CpuAccessibleBuffer::from_iter(
+                    new_grid.device.clone(),
+                    BufferUsage::all(),
+                    cells.read().unwrap().to_vec().into_iter(),
+                )
+                .expect("failed to create buffer"),
+            ),
+            Cells::Packed(ref words) => Cells::Packed(
+                CpuAccessibleBuffer::from_iter(
+                    new_grid.device.clone(),
+                    BufferUsage::all(),
+                    words.read().unwrap().to_vec().into_iter(),
+                )
+                .expect("failed to create buffer"),
+            ),
+        };
 
         new_grid
     }
@@ -271,25 +747,29 @@ impl fmt::Debug for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let Grid {
             ref format,
-            ref toroidal,
+            ref topology,
             ref survival,
             ref birth,
+            ref birth_probability,
+            ref survival_probability,
+            ref update_probability,
+            ref seed,
             ref width,
             ref height,
             ..
         } = *self;
 
-        write!(f, "Format:\n{:?}\nToroidal:\n{:?}\nSurvival:\n{:?}\nBirth:\n{:?}\nWidth:\n{:?}\nHeight:\n{:?}\nCells:\n{}", *format, *toroidal,  *survival, *birth, *width, height, self)
+        write!(f, "Format:\n{:?}\nTopology:\n{:?}\nSurvival:\n{:?}\nBirth:\n{:?}\nProbabilities (birth, survival, update):\n{:?}\nSeed:\n{:?}\nWidth:\n{:?}\nHeight:\n{:?}\nCells:\n{}", *format, *topology,  *survival, *birth, (*birth_probability, *survival_probability, *update_probability), *seed, *width, height, self)
     }
 }
 
 impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let Grid { width, height, .. } = *self;
+        let Grid { width, .. } = *self;
 
-        for y in 0..height {
-            for x in 0..width {
-                if self.get_cell_state(x as i64, y as i64) == 255 {
+        for row in self.cells().chunks(width.max(1)) {
+            for &cell in row {
+                if cell == 255 {
                     write!(f, "*")?;
                 } else {
                     write!(f, ".")?;
@@ -309,6 +789,8 @@ mod tests {
 
     use super::vulkan;
 
+    use topology::Topology;
+    use Cells;
     use Grid;
 
     #[test]
@@ -323,9 +805,6 @@ mod tests {
         )
         .expect("failed to create buffer");
 
-        let toroidal = CpuAccessibleBuffer::from_data(device.clone(), BufferUsage::all(), 1)
-            .expect("failed to create buffer");
-
         let srvl_content: Vec<u32> = vec![2, 3];
         let survival = CpuAccessibleBuffer::from_iter(
             device.clone(),
@@ -344,12 +823,22 @@ mod tests {
 
         let control_grid = Grid {
             format: String::from("#Toroidal Life"),
-            toroidal,
+            topology: Topology::torus(),
             survival,
             birth,
+            birth_probability: 1.0,
+            survival_probability: 1.0,
+            update_probability: 1.0,
+            seed: 0,
+            generation: 0,
+            generation_reset_on_edit: false,
             width: 3,
             height: 3,
-            cells,
+            cells: Cells::Bytes(cells),
+            max_tile_size: None,
+            changed_tiles: None,
+            journal: None,
+            history: None,
             device,
             queue,
         };
@@ -392,9 +881,6 @@ mod tests {
         )
         .expect("failed to create buffer");
 
-        let toroidal = CpuAccessibleBuffer::from_data(device.clone(), BufferUsage::all(), 1)
-            .expect("failed to create buffer");
-
         let srvl_content: Vec<u32> = vec![2, 3];
         let survival = CpuAccessibleBuffer::from_iter(
             device.clone(),
@@ -413,12 +899,22 @@ mod tests {
 
         let mut control_grid = Grid {
             format: String::from("#Toroidal Life"),
-            toroidal,
+            topology: Topology::torus(),
             survival,
             birth,
+            birth_probability: 1.0,
+            survival_probability: 1.0,
+            update_probability: 1.0,
+            seed: 0,
+            generation: 0,
+            generation_reset_on_edit: false,
             width: 3,
             height: 3,
-            cells,
+            cells: Cells::Bytes(cells),
+            max_tile_size: None,
+            changed_tiles: None,
+            journal: None,
+            history: None,
             device,
             queue,
         };
@@ -426,6 +922,10 @@ mod tests {
         control_grid.set_format(&String::from("#Resizable Life"));
         control_grid.set_survival(&vec![1, 7]);
         control_grid.set_birth(&vec![5]);
+        control_grid.set_birth_probability(0.5).unwrap();
+        control_grid.set_update_probability(0.25).unwrap();
+        control_grid.set_seed(42);
+        assert!(control_grid.set_survival_probability(1.5).is_err());
         control_grid.set_cell_state(0, 0, 255).unwrap();
         control_grid.set_cell_state(1, 1, 0).unwrap();
 
@@ -433,6 +933,10 @@ mod tests {
         assert_eq!("#Resizable Life", control_grid.get_format());
         assert_eq!(vec![1, 7], control_grid.get_survival());
         assert_eq!(vec![5], control_grid.get_birth());
+        assert_eq!(0.5, control_grid.get_birth_probability());
+        assert_eq!(1.0, control_grid.get_survival_probability());
+        assert_eq!(0.25, control_grid.get_update_probability());
+        assert_eq!(42, control_grid.get_seed());
 
         // Check new cells.
         assert_eq!(255, control_grid.get_cell_state(0, 0));
@@ -458,9 +962,6 @@ mod tests {
         )
         .expect("failed to create buffer");
 
-        let toroidal = CpuAccessibleBuffer::from_data(device.clone(), BufferUsage::all(), 0)
-            .expect("failed to create buffer");
-
         let srvl_content: Vec<u32> = vec![2, 3];
         let survival = CpuAccessibleBuffer::from_iter(
             device.clone(),
@@ -479,12 +980,22 @@ mod tests {
 
         let control_grid = Grid {
             format: String::from("#Resizable Life"),
-            toroidal,
+            topology: Topology::Resizable,
             survival,
             birth,
+            birth_probability: 1.0,
+            survival_probability: 1.0,
+            update_probability: 1.0,
+            seed: 0,
+            generation: 0,
+            generation_reset_on_edit: false,
             width: 3,
             height: 3,
-            cells,
+            cells: Cells::Bytes(cells),
+            max_tile_size: None,
+            changed_tiles: None,
+            journal: None,
+            history: None,
             device,
             queue,
         };
@@ -527,9 +1038,6 @@ mod tests {
         )
         .expect("failed to create buffer");
 
-        let toroidal = CpuAccessibleBuffer::from_data(device.clone(), BufferUsage::all(), 0)
-            .expect("failed to create buffer");
-
         let srvl_content: Vec<u32> = vec![2, 3];
         let survival = CpuAccessibleBuffer::from_iter(
             device.clone(),
@@ -548,12 +1056,22 @@ mod tests {
 
         let mut control_grid = Grid {
             format: String::from("#Resizable Life"),
-            toroidal,
+            topology: Topology::Resizable,
             survival,
             birth,
+            birth_probability: 1.0,
+            survival_probability: 1.0,
+            update_probability: 1.0,
+            seed: 0,
+            generation: 0,
+            generation_reset_on_edit: false,
             width: 3,
             height: 3,
-            cells,
+            cells: Cells::Bytes(cells),
+            max_tile_size: None,
+            changed_tiles: None,
+            journal: None,
+            history: None,
             device,
             queue,
         };
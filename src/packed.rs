@@ -0,0 +1,475 @@
+//! This module contains a CPU engine storing 64 cells per `u64`.
+//!
+//! The next generation is computed 64 cells at a time with bitwise
+//! full adders, and the rows are distributed over a thread pool.
+//! It doesn't need a GPU, which makes it the fast path for the
+//! deterministic rules when no GPU is present.
+
+use std::borrow::Cow;
+
+use rayon::prelude::*;
+
+use super::cpu;
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// This struct contains a life cellular automaton grid whose cells are
+/// packed into `u64`s: the bit `i` of the word `j` of a row is the cell
+/// at the column `64 * j + i`.
+///
+/// It supports the same topologies and rules as `Grid`, except for
+/// the stochastic rules. Like `Grid`, resizable and cylindrical grids
+/// are resized before computing each generation so that the pattern
+/// can grow.
+#[derive(Clone, Debug)]
+pub struct PackedGrid {
+    topology: Topology,
+    survival: Vec<u32>,
+    birth: Vec<u32>,
+
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    cells: Vec<u64>,
+}
+
+impl PackedGrid {
+    /// Returns a new empty `width` x `height` `PackedGrid` with the
+    /// given topology and rules.
+    pub fn new(
+        topology: Topology,
+        srvl: &[u32],
+        brth: &[u32],
+        width: usize,
+        height: usize,
+    ) -> PackedGrid {
+        let words_per_row = width.div_ceil(64);
+
+        PackedGrid {
+            topology,
+            survival: srvl.to_vec(),
+            birth: brth.to_vec(),
+            width,
+            height,
+            words_per_row,
+            cells: vec![0; words_per_row * height],
+        }
+    }
+
+    /// Returns a new `PackedGrid` with the size, the topology,
+    /// the rules and the cells of `grid`.
+    pub fn from_grid(grid: &Grid) -> PackedGrid {
+        let mut packed = PackedGrid::new(
+            grid.get_topology(),
+            &grid.get_survival(),
+            &grid.get_birth(),
+            grid.get_width(),
+            grid.get_height(),
+        );
+        packed.pack(&grid.read_cells());
+
+        packed
+    }
+
+    /// Returns a new `Grid` with the size, the topology, the rules and
+    /// the cells of this `PackedGrid`, using the file format `frmt`.
+    pub fn to_grid(&self, frmt: &String) -> Grid {
+        let mut grid = Grid::new(
+            frmt,
+            self.topology,
+            &self.survival,
+            &self.birth,
+            self.width,
+            self.height,
+        );
+        let mut cells = vec![0; self.width * self.height];
+        self.unpack(&mut cells);
+        grid.write_cells(&cells);
+
+        grid
+    }
+
+    /// Replaces the cells by `cells`, a buffer of one byte per cell
+    /// whose living cells are set to `255`.
+    pub(crate) fn pack(&mut self, cells: &[u8]) {
+        for word in self.cells.iter_mut() {
+            *word = 0;
+        }
+
+        for (y, row) in cells.chunks(self.width.max(1)).enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell == 255 {
+                    self.cells[y * self.words_per_row + x / 64] |= 1 << (x % 64);
+                }
+            }
+        }
+    }
+
+    /// Writes the cells into `cells`, a buffer of one byte per cell.
+    pub(crate) fn unpack(&self, cells: &mut [u8]) {
+        for (y, row) in cells.chunks_mut(self.width.max(1)).enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = if self.get_bit(x, y) { 255 } else { 0 };
+            }
+        }
+    }
+
+    /// Returns the topology of the grid.
+    pub fn get_topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Returns the survival conditions of the cellular automaton.
+    pub fn get_survival(&self) -> Vec<u32> {
+        self.survival.clone()
+    }
+
+    /// Returns the birth conditions of the cellular automaton.
+    pub fn get_birth(&self) -> Vec<u32> {
+        self.birth.clone()
+    }
+
+    /// Returns the width of the grid.
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the grid.
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the state of the cell at the coordinates (`x`, `y`),
+    /// crossing the edges according to the topology like
+    /// `Grid::get_cell_state`.
+    pub fn get_cell_state(&self, x: i64, y: i64) -> u8 {
+        if self.is_alive(x, y) {
+            255
+        } else {
+            0
+        }
+    }
+
+    /// Modifies the state of the cell at the coordinates (`x`, `y`)
+    /// with `state`. Any state other than `0` is a living cell.
+    pub fn set_cell_state(&mut self, x: usize, y: usize, state: u8) -> Result<(), GridErrorKind> {
+        if x >= self.width || y >= self.height {
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+
+        let word = &mut self.cells[y * self.words_per_row + x / 64];
+        if state != 0 {
+            *word |= 1 << (x % 64);
+        } else {
+            *word &= !(1 << (x % 64));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of living cells.
+    pub fn population(&self) -> u64 {
+        self.cells
+            .iter()
+            .map(|word| u64::from(word.count_ones()))
+            .sum()
+    }
+
+    /// Computes the next generation of the current `PackedGrid` and
+    /// updates it.
+    pub fn next_gen(&mut self) {
+        self.make_room_for_growth();
+
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        let survival = cpu::rule_mask(&self.survival);
+        let birth = cpu::rule_mask(&self.birth);
+
+        // Mask of the cells of the last word of a row which are in the grid
+        let last_mask = match self.width % 64 {
+            0 => !0,
+            n => (1 << n) - 1,
+        };
+
+        let mut new_cells = vec![0; self.cells.len()];
+        {
+            let this = &*self;
+            new_cells
+                .par_chunks_mut(this.words_per_row)
+                .enumerate()
+                .for_each(|(y, new_row)| {
+                    let y = y as i64;
+                    let above = this.neighbor_row(y - 1);
+                    let row = this.neighbor_row(y);
+                    let below = this.neighbor_row(y + 1);
+
+                    for (i, new_word) in new_row.iter_mut().enumerate() {
+                        *new_word = cpu::next_word(
+                            this.word_triple(&above, i),
+                            this.word_triple(&row, i),
+                            this.word_triple(&below, i),
+                            survival,
+                            birth,
+                        );
+                    }
+                    new_row[this.words_per_row - 1] &= last_mask;
+                });
+        }
+
+        self.cells = new_cells;
+    }
+
+    fn get_bit(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.words_per_row + x / 64] & (1 << (x % 64)) != 0
+    }
+
+    fn is_alive(&self, x: i64, y: i64) -> bool {
+        match self.topology.map(x, y, self.width, self.height) {
+            Some((x, y)) => self.get_bit(x, y),
+            None => false,
+        }
+    }
+
+    // Returns the words of the row `y`, which can be beyond the edges,
+    // along with the states of the cells on its left and on its right
+    fn neighbor_row(&self, y: i64) -> (Cow<'_, [u64]>, bool, bool) {
+        let words = if y >= 0 && y < self.height as i64 {
+            let start = y as usize * self.words_per_row;
+            Cow::Borrowed(&self.cells[start..start + self.words_per_row])
+        } else {
+            let mut words = vec![0; self.words_per_row];
+            for x in 0..self.width {
+                if self.is_alive(x as i64, y) {
+                    words[x / 64] |= 1 << (x % 64);
+                }
+            }
+            Cow::Owned(words)
+        };
+
+        (
+            words,
+            self.is_alive(-1, y),
+            self.is_alive(self.width as i64, y),
+        )
+    }
+
+    // Returns the word `i` of a row as expected by `cpu::next_word`
+    fn word_triple(
+        &self,
+        &(ref words, west, east): &(Cow<[u64]>, bool, bool),
+        i: usize,
+    ) -> [u64; 3] {
+        let center = words[i];
+
+        let west = if i == 0 {
+            u64::from(west)
+        } else {
+            words[i - 1] >> 63
+        };
+        let east = if i + 1 == self.words_per_row {
+            u64::from(east) << ((self.width - 1) % 64)
+        } else {
+            words[i + 1] << 63
+        };
+
+        [(center << 1) | west, center, (center >> 1) | east]
+    }
+
+    // Resizes the grid so that the pattern can grow during the next
+    // generation, like `Grid::next_gen` does
+    fn make_room_for_growth(&mut self) {
+        let (min_x, max_x, min_y, max_y) = match self.bounding_box() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        match self.topology {
+            Topology::Resizable => self.reframe(
+                (min_x, min_y),
+                (max_x - min_x + 3, max_y - min_y + 3),
+                (1, 1),
+            ),
+            Topology::Cylinder => self.reframe((0, min_y), (self.width, max_y - min_y + 3), (0, 1)),
+            _ => {}
+        }
+    }
+
+    // Replaces the cells by a `new_size` grid in which the pattern
+    // starting at `origin` is copied at `destination`
+    fn reframe(
+        &mut self,
+        origin: (usize, usize),
+        new_size: (usize, usize),
+        destination: (usize, usize),
+    ) {
+        if origin == destination && new_size == (self.width, self.height) {
+            return;
+        }
+
+        let mut reframed = PackedGrid::new(
+            self.topology,
+            &self.survival,
+            &self.birth,
+            new_size.0,
+            new_size.1,
+        );
+        for y in origin.1..self.height {
+            for x in origin.0..self.width {
+                let (new_x, new_y) = (x - origin.0 + destination.0, y - origin.1 + destination.1);
+                if self.get_bit(x, y) && new_x < new_size.0 && new_y < new_size.1 {
+                    reframed.set_cell_state(new_x, new_y, 255).unwrap(); // Shouldn't fail
+                }
+            }
+        }
+
+        *self = reframed;
+    }
+
+    // Returns the boundaries of the pattern as
+    // `(min_x, max_x, min_y, max_y)`
+    fn bounding_box(&self) -> Option<(usize, usize, usize, usize)> {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+
+        for (y, row) in self.cells.chunks(self.words_per_row.max(1)).enumerate() {
+            for (i, &word) in row.iter().enumerate() {
+                if word == 0 {
+                    continue;
+                }
+
+                let x0 = 64 * i + word.trailing_zeros() as usize;
+                let x1 = 64 * i + 63 - word.leading_zeros() as usize;
+                bounds = Some(match bounds {
+                    None => (x0, x1, y, y),
+                    Some((min_x, max_x, min_y, max_y)) => {
+                        (min_x.min(x0), max_x.max(x1), min_y.min(y), max_y.max(y))
+                    }
+                });
+            }
+        }
+
+        bounds
+    }
+}
+
+/// Packs `cells`, one byte per cell, into 32 cells per `u32` with each
+/// row of `width` cells starting on a new word.
+pub(crate) fn pack_u32(cells: &[u8], width: usize) -> Vec<u32> {
+    let words_per_row = width.div_ceil(32);
+    let mut words = vec![0; words_per_row * (cells.len() / width.max(1))];
+
+    for (y, row) in cells.chunks(width.max(1)).enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell == 255 {
+                words[y * words_per_row + x / 32] |= 1 << (x % 32);
+            }
+        }
+    }
+
+    words
+}
+
+/// Unpacks the cells packed by `pack_u32` into one byte per cell.
+pub(crate) fn unpack_u32(words: &[u32], width: usize, height: usize) -> Vec<u8> {
+    let words_per_row = width.div_ceil(32);
+    let mut cells = vec![0; width * height];
+
+    for (i, cell) in cells.iter_mut().enumerate() {
+        let (x, y) = (i % width, i / width);
+        if words[y * words_per_row + x / 32] & (1 << (x % 32)) != 0 {
+            *cell = 255;
+        }
+    }
+
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pack_u32, unpack_u32, PackedGrid};
+
+    use cpu;
+    use topology::Topology;
+
+    #[test]
+    fn test_same_as_scalar_engine() {
+        let topologies = [
+            Topology::torus(),
+            Topology::Torus {
+                horizontal_shift: 3,
+                vertical_shift: 0,
+            },
+            Topology::Plane,
+            Topology::KleinBottle {
+                horizontal_twist: true,
+            },
+            Topology::CrossSurface,
+            Topology::Sphere,
+        ];
+        let stochastic = cpu::Stochastic {
+            birth: u32::MAX,
+            survival: u32::MAX,
+            update: u32::MAX,
+            seed: 0,
+            generation: 0,
+        };
+
+        // 70 columns, so that the rows span two words
+        let size = 70;
+        let soup: Vec<u8> = (0..(size * size) as u32)
+            .map(|i| if cpu::hash(i) & 3 == 0 { 255 } else { 0 })
+            .collect();
+
+        for topology in topologies.iter() {
+            let mut packed = PackedGrid::new(*topology, &[2, 3], &[3], size, size);
+            packed.pack(&soup);
+
+            let mut cells = soup.clone();
+            for _ in 0..8 {
+                packed.next_gen();
+                cells = cpu::next_gen(&cells, size, size, topology, &[2, 3], &[3], &stochastic);
+            }
+
+            let mut unpacked = vec![0; size * size];
+            packed.unpack(&mut unpacked);
+            assert_eq!(cells, unpacked, "{:?}", topology);
+        }
+    }
+
+    #[test]
+    fn test_resizable_growth() {
+        let mut packed = PackedGrid::new(Topology::Resizable, &[2, 3], &[3], 3, 1);
+        for x in 0..3 {
+            packed.set_cell_state(x, 0, 255).unwrap();
+        }
+
+        packed.next_gen();
+        assert_eq!((5, 3), (packed.get_width(), packed.get_height()));
+        for y in 0..3 {
+            assert_eq!(255, packed.get_cell_state(2, y));
+        }
+        assert_eq!(3, packed.population());
+
+        packed.next_gen();
+        assert_eq!((3, 5), (packed.get_width(), packed.get_height()));
+        for x in 0..3 {
+            assert_eq!(255, packed.get_cell_state(x, 2));
+        }
+        assert_eq!(3, packed.population());
+    }
+
+    #[test]
+    fn test_pack_u32() {
+        // 33 columns, so that the rows span two words
+        let cells: Vec<u8> = (0..66).map(|i| if i % 3 == 0 { 255 } else { 0 }).collect();
+        let words = pack_u32(&cells, 33);
+
+        assert_eq!(4, words.len());
+        assert_eq!(0b1001, words[0] & 0b1111);
+        assert_eq!(0, words[1]);
+        assert_eq!(1, words[2] & 1);
+        assert_eq!(cells, unpack_u32(&words, 33, 2));
+    }
+}
@@ -0,0 +1,419 @@
+//! This module contains a grid split into horizontal bands, each band
+//! being computed by a different device.
+//!
+//! Each band is stored with a halo: a row above it and a row below it
+//! containing the edge rows of the neighboring bands. The halos are
+//! exchanged before each generation, then every band computes its next
+//! generation independently of the others.
+
+use rayon::prelude::*;
+
+use context::Context;
+use error::GridErrorKind;
+use packed::PackedGrid;
+use topology::Topology;
+use Grid;
+
+/// A device computing a band of a `PartitionedGrid`.
+#[derive(Clone)]
+pub enum Backend {
+    /// A Vulkan device.
+    Gpu(Context),
+    /// The bit-packed CPU engine of `PackedGrid`.
+    Cpu,
+}
+
+// A band and its two halo rows
+enum Band {
+    Gpu(Box<Grid>),
+    Cpu(PackedGrid),
+}
+
+impl Band {
+    fn get_cell_state(&self, x: usize, y: usize) -> u8 {
+        match *self {
+            Band::Gpu(ref grid) => grid.get_cell_state(x as i64, y as i64),
+            Band::Cpu(ref packed) => packed.get_cell_state(x as i64, y as i64),
+        }
+    }
+
+    fn set_cell_state(&mut self, x: usize, y: usize, state: u8) -> Result<(), GridErrorKind> {
+        match *self {
+            Band::Gpu(ref mut grid) => grid.set_cell_state(x, y, state),
+            Band::Cpu(ref mut packed) => packed.set_cell_state(x, y, state),
+        }
+    }
+
+    fn read_row(&self, y: usize, width: usize) -> Vec<u8> {
+        match *self {
+            Band::Gpu(ref grid) => grid.read_region(0, y, width, 1),
+            Band::Cpu(ref packed) => (0..width)
+                .map(|x| packed.get_cell_state(x as i64, y as i64))
+                .collect(),
+        }
+    }
+
+    fn write_row(&mut self, y: usize, row: &[u8]) {
+        match *self {
+            Band::Gpu(ref mut grid) => grid.write_region(0, y, row.len(), 1, row),
+            Band::Cpu(ref mut packed) => {
+                for (x, &state) in row.iter().enumerate() {
+                    packed.set_cell_state(x, y, state).unwrap(); // Shouldn't fail
+                }
+            }
+        }
+    }
+
+    fn next_gen(&mut self) {
+        match *self {
+            Band::Gpu(ref mut grid) => grid.next_gen(),
+            Band::Cpu(ref mut packed) => packed.next_gen(),
+        }
+    }
+}
+
+/// This struct contains a life cellular automaton grid split into
+/// horizontal bands of about the same height, one per backend.
+///
+/// Only the plane and the tori without vertical shift are supported,
+/// and the stochastic rules aren't.
+pub struct PartitionedGrid {
+    topology: Topology,
+    survival: Vec<u32>,
+    birth: Vec<u32>,
+
+    width: usize,
+    height: usize,
+    bands: Vec<Band>,
+    starts: Vec<usize>, // First row of each band in the grid
+}
+
+impl PartitionedGrid {
+    /// Returns a new empty `width` x `height` `PartitionedGrid` with the
+    /// given topology and rules, split into one band per backend.
+    ///
+    /// There are at most `height` bands. Without any backend, the grid
+    /// is computed by a single CPU band.
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` if the topology
+    /// isn't supported.
+    pub fn new(
+        topology: Topology,
+        srvl: &[u32],
+        brth: &[u32],
+        width: usize,
+        height: usize,
+        backends: &[Backend],
+    ) -> Result<PartitionedGrid, GridErrorKind> {
+        // Each band wraps around horizontally by itself, the rows
+        // crossing the vertical edges being given by the halos
+        let band_topology = match topology {
+            Topology::Plane => Topology::Plane,
+            Topology::Torus {
+                vertical_shift: 0, ..
+            } => Topology::torus(),
+            _ => return Err(GridErrorKind::UnsupportedTopology),
+        };
+
+        let backends = match backends.len() {
+            0 => vec![Backend::Cpu],
+            n => backends[..n.min(height.max(1))].to_vec(),
+        };
+
+        let mut bands = Vec::with_capacity(backends.len());
+        let mut starts = Vec::with_capacity(backends.len());
+        for (i, backend) in backends.iter().enumerate() {
+            let start = i * height / backends.len();
+            let band_height = (i + 1) * height / backends.len() - start + 2;
+
+            bands.push(match *backend {
+                Backend::Gpu(ref context) => Band::Gpu(Box::new(Grid::with_context(
+                    context,
+                    &String::new(),
+                    band_topology,
+                    &srvl.to_vec(),
+                    &brth.to_vec(),
+                    width,
+                    band_height,
+                ))),
+                Backend::Cpu => Band::Cpu(PackedGrid::new(
+                    band_topology,
+                    srvl,
+                    brth,
+                    width,
+                    band_height,
+                )),
+            });
+            starts.push(start);
+        }
+
+        Ok(PartitionedGrid {
+            topology,
+            survival: srvl.to_vec(),
+            birth: brth.to_vec(),
+            width,
+            height,
+            bands,
+            starts,
+        })
+    }
+
+    /// Returns a new `PartitionedGrid` like `new`, with one band per
+    /// device found by `Context::enumerate`.
+    pub fn with_all_devices(
+        topology: Topology,
+        srvl: &[u32],
+        brth: &[u32],
+        width: usize,
+        height: usize,
+    ) -> Result<PartitionedGrid, GridErrorKind> {
+        let backends: Vec<Backend> = Context::enumerate().into_iter().map(Backend::Gpu).collect();
+
+        PartitionedGrid::new(topology, srvl, brth, width, height, &backends)
+    }
+
+    /// Returns a new `PartitionedGrid` containing the size, the topology,
+    /// the rules and the cells of `grid`, split into one band per backend.
+    pub fn from_grid(grid: &Grid, backends: &[Backend]) -> Result<PartitionedGrid, GridErrorKind> {
+        let mut partitioned = PartitionedGrid::new(
+            grid.get_topology(),
+            &grid.get_survival(),
+            &grid.get_birth(),
+            grid.get_width(),
+            grid.get_height(),
+            backends,
+        )?;
+
+        let cells = grid.read_cells();
+        for (i, &cell) in cells.iter().enumerate() {
+            if cell != 0 {
+                partitioned.set_cell_state(i % grid.get_width(), i / grid.get_width(), cell)?;
+            }
+        }
+
+        Ok(partitioned)
+    }
+
+    /// Returns a new `Grid` containing the size, the topology, the rules
+    /// and the cells of this `PartitionedGrid`, using the file format `frmt`.
+    pub fn to_grid(&self, frmt: &String) -> Grid {
+        let mut grid = Grid::new(
+            frmt,
+            self.topology,
+            &self.survival,
+            &self.birth,
+            self.width,
+            self.height,
+        );
+
+        let mut cells = Vec::with_capacity(self.width * self.height);
+        for (i, band) in self.bands.iter().enumerate() {
+            for y in 0..self.band_height(i) {
+                cells.extend(band.read_row(y + 1, self.width));
+            }
+        }
+        grid.write_cells(&cells);
+
+        grid
+    }
+
+    /// Returns the topology of the grid.
+    pub fn get_topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Returns the survival conditions of the cellular automaton.
+    pub fn get_survival(&self) -> Vec<u32> {
+        self.survival.clone()
+    }
+
+    /// Returns the birth conditions of the cellular automaton.
+    pub fn get_birth(&self) -> Vec<u32> {
+        self.birth.clone()
+    }
+
+    /// Returns the width of the grid.
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the grid.
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the number of bands the grid is split into.
+    pub fn get_partition_count(&self) -> usize {
+        self.bands.len()
+    }
+
+    /// Returns the first row and the height of the band `i`.
+    pub fn get_partition_rows(&self, i: usize) -> (usize, usize) {
+        (self.starts[i], self.band_height(i))
+    }
+
+    /// Returns the state of the cell at the coordinates (`x`, `y`),
+    /// crossing the edges according to the topology like
+    /// `Grid::get_cell_state`.
+    pub fn get_cell_state(&self, x: i64, y: i64) -> u8 {
+        match self.topology.map(x, y, self.width, self.height) {
+            Some((x, y)) => {
+                let i = self.band_of_row(y);
+                self.bands[i].get_cell_state(x, y - self.starts[i] + 1)
+            }
+            None => 0,
+        }
+    }
+
+    /// Modifies the state of the cell at the coordinates (`x`, `y`)
+    /// with `state`.
+    /// Returns `Err(GridErrorKind::OutOfBoundCoords)` if the
+    /// coordinates are out of bounds.
+    pub fn set_cell_state(&mut self, x: usize, y: usize, state: u8) -> Result<(), GridErrorKind> {
+        if x >= self.width || y >= self.height {
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+
+        let i = self.band_of_row(y);
+        let row = y - self.starts[i] + 1;
+        self.bands[i].set_cell_state(x, row, state)
+    }
+
+    /// Computes the next generation of the current `PartitionedGrid` and
+    /// updates it. The bands are computed in parallel.
+    pub fn next_gen(&mut self) {
+        self.exchange_halos();
+
+        self.bands.par_iter_mut().for_each(|band| band.next_gen());
+    }
+
+    // Copies the edge rows of each band into the halos of its neighbors
+    fn exchange_halos(&mut self) {
+        let count = self.bands.len();
+        let firsts: Vec<Vec<u8>> = self
+            .bands
+            .iter()
+            .map(|band| band.read_row(1, self.width))
+            .collect();
+        let lasts: Vec<Vec<u8>> = (0..count)
+            .map(|i| self.bands[i].read_row(self.band_height(i), self.width))
+            .collect();
+
+        // The rows crossing the north and south edges
+        let (north, south) = match self.topology {
+            Topology::Torus {
+                horizontal_shift, ..
+            } => (
+                shift_row(&lasts[count - 1], -horizontal_shift),
+                shift_row(&firsts[0], horizontal_shift),
+            ),
+            _ => (vec![0; self.width], vec![0; self.width]),
+        };
+
+        for i in 0..count {
+            let above = if i == 0 { &north } else { &lasts[i - 1] };
+            let below = if i == count - 1 {
+                &south
+            } else {
+                &firsts[i + 1]
+            };
+            let last_row = self.band_height(i) + 1;
+
+            self.bands[i].write_row(0, above);
+            self.bands[i].write_row(last_row, below);
+        }
+    }
+
+    fn band_height(&self, i: usize) -> usize {
+        match self.starts.get(i + 1) {
+            Some(&next) => next - self.starts[i],
+            None => self.height - self.starts[i],
+        }
+    }
+
+    fn band_of_row(&self, y: usize) -> usize {
+        match self.starts.binary_search(&y) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        }
+    }
+}
+
+// Returns `row` whose cell `x` is the cell `x + shift` of `row`,
+// wrapping around
+fn shift_row(row: &[u8], shift: i64) -> Vec<u8> {
+    let width = row.len() as i64;
+
+    (0..width)
+        .map(|x| row[(x + shift).rem_euclid(width.max(1)) as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, PartitionedGrid};
+
+    use cpu;
+    use packed::PackedGrid;
+    use topology::Topology;
+
+    #[test]
+    fn test_same_result_as_single_grid() {
+        let topologies = [
+            Topology::Plane,
+            Topology::torus(),
+            Topology::Torus {
+                horizontal_shift: 5,
+                vertical_shift: 0,
+            },
+        ];
+
+        for topology in topologies.iter() {
+            let (width, height) = (70, 31);
+            let mut single = PackedGrid::new(*topology, &[2, 3], &[3], width, height);
+            let mut partitioned = PartitionedGrid::new(
+                *topology,
+                &[2, 3],
+                &[3],
+                width,
+                height,
+                &[Backend::Cpu, Backend::Cpu, Backend::Cpu],
+            )
+            .unwrap();
+            assert_eq!(3, partitioned.get_partition_count());
+            assert_eq!((10, 10), partitioned.get_partition_rows(1));
+
+            for i in 0..(width * height) as u32 {
+                if cpu::hash(i) % 3 == 0 {
+                    let (x, y) = (i as usize % width, i as usize / width);
+                    single.set_cell_state(x, y, 255).unwrap();
+                    partitioned.set_cell_state(x, y, 255).unwrap();
+                }
+            }
+
+            for _ in 0..20 {
+                single.next_gen();
+                partitioned.next_gen();
+            }
+
+            for y in -1..height as i64 + 1 {
+                for x in -1..width as i64 + 1 {
+                    assert_eq!(
+                        single.get_cell_state(x, y),
+                        partitioned.get_cell_state(x, y)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported_topology() {
+        let sphere = PartitionedGrid::new(Topology::Sphere, &[2, 3], &[3], 8, 8, &[]);
+        assert!(sphere.is_err());
+
+        // A band can't be empty
+        let grid =
+            PartitionedGrid::new(Topology::Plane, &[2, 3], &[3], 8, 2, &vec![Backend::Cpu; 3]);
+        assert_eq!(2, grid.unwrap().get_partition_count());
+    }
+}
@@ -19,9 +19,28 @@ use super::vulkano::image::StorageImage;
 use super::vulkano::pipeline::ComputePipeline;
 use super::vulkano::sync::GpuFuture;
 
+use super::cpu;
+use super::vulkan::ngps;
 use super::vulkan::ngs;
+use analysis::{read_hash_buffer, Reduction};
+use packed::PackedGrid;
+use topology::Topology;
+use Cells;
 use Grid;
 
+/// Size of the side of the tiles whose activity is tracked, which must
+/// match the size of the work groups of the `ngs` shader.
+pub(crate) const ACTIVE_TILE_SIZE: usize = 8;
+
+/// Number of work groups which can be dispatched along one dimension
+/// on every device.
+pub(crate) const MAX_WORK_GROUPS: usize = 65535;
+
+/// Flags of the statistics computed by the `ngps` shader: the population
+/// of each grid, and whether its cells changed.
+pub(crate) const STATS_POPULATION: i32 = 1;
+pub(crate) const STATS_CHANGES: i32 = 2;
+
 impl Grid {
     /// Randomizes the current `Grid` by setting a random state to
     /// each cell.
@@ -30,28 +49,129 @@ impl Grid {
 
         let width = self.get_width();
         let height = self.get_height();
-        for y in 0..height {
-            for x in 0..width {
-                if rng.gen::<bool>() {
-                    self.set_cell_state(x, y, 255).unwrap(); // Shouldn't fail
-                } else {
-                    self.set_cell_state(x, y, 0).unwrap(); // Shouldn't fail
-                }
-            }
-        }
+        let cells = (0..width * height).map(|i| {
+            let state = if rng.gen::<bool>() { 255 } else { 0 };
+            (i % width, i / width, state)
+        });
+        self.set_cells(cells).unwrap(); // Shouldn't fail
     }
 
     /// Computes the next generation of the current `Grid` and updates it.
     pub fn next_gen(&mut self) {
-        if !self.is_toroidal() {
-            self.recenter_pattern(1);
+        self.compute_next_gen(None);
+    }
+
+    /// Works like `next_gen`, and returns the population of the new
+    /// generation. It is counted on the GPU with the same submission as
+    /// the generation when possible.
+    pub fn next_gen_with_population(&mut self) -> u64 {
+        let population =
+            CpuAccessibleBuffer::from_data(self.device.clone(), BufferUsage::all(), 0u32)
+                .expect("failed to create buffer");
+
+        if self.compute_next_gen(Some(Reduction::Population(population.clone()))) {
+            u64::from(*population.read().unwrap())
+        } else {
+            self.get_population()
+        }
+    }
+
+    /// Works like `next_gen`, and returns the population and the hash of
+    /// the new generation (see `summarise_pattern`). They are computed on
+    /// the GPU with the same submission as the generation when possible.
+    pub(crate) fn next_gen_with_hash(&mut self) -> (u64, u64) {
+        let hash = self.new_hash_buffer();
+
+        if self.compute_next_gen(Some(Reduction::Hash(hash.clone()))) {
+            read_hash_buffer(&hash)
+        } else {
+            self.summarise_pattern()
+        }
+    }
+
+    /// Computes the next generation, and computes `reduction` on it if it
+    /// is given and if it can be computed during the same submission.
+    /// Returns `true` if it was computed.
+    fn compute_next_gen(&mut self, reduction: Option<Reduction>) -> bool {
+        self.clear_journal();
+        self.record_history();
+        self.make_room_for_growth();
+
+        if self.is_bit_packed() {
+            // The `ngps` shader can count the population
+            let population = match reduction {
+                Some(Reduction::Population(population)) => Some(population),
+                _ => None,
+            };
+            let stats = self.next_gen_packed(population.is_some());
+            self.generation += 1;
+
+            return match population {
+                Some(population) => {
+                    *population.write().unwrap() = stats.read().unwrap()[0];
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let mut counted = false;
+        if self.is_tiled() {
+            self.next_gen_tiled();
+        } else {
+            let cells = self.byte_cells();
+            let tiles = self.active_tiles();
+            // Without any active tile, the shaders aren't run at all
+            counted = reduction.is_some() && !tiles.is_empty();
+            let changed_tiles = self.run_ngs(
+                cells,
+                (self.width, self.height),
+                self.topology,
+                (0, 0),
+                &tiles,
+                reduction,
+            );
+
+            // With stochastic rules, a cell can change even if its
+            // neighborhood didn't
+            self.changed_tiles = if self.is_deterministic() {
+                Some(changed_tiles)
+            } else {
+                None
+            };
+        }
+
+        self.generation += 1;
+        counted
+    }
+
+    /// Computes the next generation of `cells`, a `size` region of the
+    /// grid whose north west corner is at `offset`, with the `ngs` shader.
+    /// The edges of the region are joined according to `topology`.
+    ///
+    /// Only the active tiles given by `tiles` are computed, the other cells
+    /// are kept. Returns for each tile of the region whether it changed.
+    /// `reduction` is computed on the new generation if it is given.
+    fn run_ngs(
+        &self,
+        cells: Arc<CpuAccessibleBuffer<[u8]>>,
+        size: (usize, usize),
+        topology: Topology,
+        offset: (i64, i64),
+        tiles: &[(usize, usize)],
+        reduction: Option<Reduction>,
+    ) -> Vec<bool> {
+        let tiles_per_row = size.0.div_ceil(ACTIVE_TILE_SIZE);
+        let tile_count = tiles_per_row * size.1.div_ceil(ACTIVE_TILE_SIZE);
+        if tiles.is_empty() {
+            return vec![false; tile_count];
         }
 
         let cells_in_img = StorageImage::new(
             self.device.clone(),
             Dimensions::Dim2d {
-                width: self.width as u32,
-                height: self.height as u32,
+                width: size.0 as u32,
+                height: size.1 as u32,
             },
             Format::R8Unorm,
             Some(self.queue.family()),
@@ -61,14 +181,54 @@ impl Grid {
         let cells_out_img = StorageImage::new(
             self.device.clone(),
             Dimensions::Dim2d {
-                width: self.width as u32,
-                height: self.height as u32,
+                width: size.0 as u32,
+                height: size.1 as u32,
             },
             Format::R8Unorm,
             Some(self.queue.family()),
         )
         .expect("failed to create image");
 
+        let topology = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            topology.as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let stochastic = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            self.stochastic().as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let offset = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            [offset.0 as i32, offset.1 as i32],
+        )
+        .expect("failed to create buffer");
+
+        let mut active_data = vec![tiles_per_row as u32, tiles.len() as u32];
+        for &(x, y) in tiles {
+            active_data.push(x as u32);
+            active_data.push(y as u32);
+        }
+        let active_tiles = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            active_data.into_iter(),
+        )
+        .expect("failed to create buffer");
+
+        let changed_tiles = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..tile_count).map(|_| 0u32),
+        )
+        .expect("failed to create buffer");
+
         let shader =
             ngs::Shader::load(self.device.clone()).expect("failed to create shader module");
         let compute_pipeline = Arc::new(
@@ -82,24 +242,275 @@ impl Grid {
                 .unwrap()
                 .add_image(cells_out_img.clone())
                 .unwrap()
-                .add_buffer(self.toroidal.clone())
+                .add_buffer(topology.clone())
+                .unwrap()
+                .add_buffer(self.survival.clone())
+                .unwrap()
+                .add_buffer(self.birth.clone())
+                .unwrap()
+                .add_buffer(stochastic.clone())
+                .unwrap()
+                .add_buffer(offset.clone())
+                .unwrap()
+                .add_buffer(active_tiles.clone())
+                .unwrap()
+                .add_buffer(changed_tiles.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let builder = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .copy_buffer_to_image(cells.clone(), cells_in_img.clone())
+            .unwrap()
+            // The cells of the inactive tiles are kept
+            .copy_buffer_to_image(cells.clone(), cells_out_img.clone())
+            .unwrap()
+            .dispatch(
+                [
+                    tiles.len().min(MAX_WORK_GROUPS) as u32,
+                    tiles.len().div_ceil(MAX_WORK_GROUPS) as u32,
+                    1,
+                ],
+                compute_pipeline.clone(),
+                set.clone(),
+                (),
+            )
+            .unwrap();
+        let builder = match reduction {
+            Some(reduction) => self.reduce(builder, cells_out_img.clone(), size, reduction),
+            None => builder,
+        };
+        let command_buffer = builder
+            .copy_image_to_buffer(cells_out_img.clone(), cells.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        let changed_tiles = changed_tiles.read().unwrap();
+        changed_tiles.iter().map(|&changed| changed != 0).collect()
+    }
+
+    /// Returns the number of 8x8 tiles which will be computed during the
+    /// next generation.
+    ///
+    /// Only the tiles in which a cell changed during the last generation
+    /// and their neighbors are computed. All of them are computed with
+    /// stochastic rules, bit-packed cells, grids split into tiles and
+    /// after the grid is modified, except by `set_cell_state`.
+    pub fn get_active_tile_count(&self) -> usize {
+        if self.is_bit_packed() || self.is_tiled() || !self.is_deterministic() {
+            self.width.div_ceil(ACTIVE_TILE_SIZE) * self.height.div_ceil(ACTIVE_TILE_SIZE)
+        } else {
+            self.active_tiles().len()
+        }
+    }
+
+    /// Returns the coordinates of the tiles to compute during the next
+    /// generation, according to the tiles which changed during the last one.
+    /// All of them are computed with stochastic rules, even right after a
+    /// deterministic generation.
+    fn active_tiles(&self) -> Vec<(usize, usize)> {
+        let changed_tiles = match self.changed_tiles {
+            Some(ref changed_tiles) if self.is_deterministic() => changed_tiles,
+            _ => return all_tiles(self.width, self.height),
+        };
+
+        let tiles_per_row = self.width.div_ceil(ACTIVE_TILE_SIZE);
+        let tiles_per_col = self.height.div_ceil(ACTIVE_TILE_SIZE);
+        let mut active = vec![false; changed_tiles.len()];
+        let mut border_changed = false;
+
+        for (i, _) in changed_tiles.iter().enumerate().filter(|&(_, &c)| c) {
+            let (x, y) = (i % tiles_per_row, i / tiles_per_row);
+            if x == 0 || y == 0 || x + 1 == tiles_per_row || y + 1 == tiles_per_col {
+                border_changed = true;
+            }
+
+            for ny in y.saturating_sub(1)..(y + 2).min(tiles_per_col) {
+                for nx in x.saturating_sub(1)..(x + 2).min(tiles_per_row) {
+                    active[ny * tiles_per_row + nx] = true;
+                }
+            }
+        }
+
+        // The cells across the edges are always on the border of the grid,
+        // whatever the topology is
+        let joined_edges = !matches!(self.topology, Topology::Resizable | Topology::Plane);
+        if border_changed && joined_edges {
+            for (i, tile) in active.iter_mut().enumerate() {
+                let (x, y) = (i % tiles_per_row, i / tiles_per_row);
+                if x == 0 || y == 0 || x + 1 == tiles_per_row || y + 1 == tiles_per_col {
+                    *tile = true;
+                }
+            }
+        }
+
+        active
+            .iter()
+            .enumerate()
+            .filter(|&(_, &a)| a)
+            .map(|(i, _)| (i % tiles_per_row, i / tiles_per_row))
+            .collect()
+    }
+
+    /// Returns `true` if the grid is too large to be stored in a single
+    /// image, in which case it is split into tiles (see `get_max_tile_size`).
+    pub fn is_tiled(&self) -> bool {
+        let max_tile_size = self.get_max_tile_size();
+
+        self.width > max_tile_size || self.height > max_tile_size
+    }
+
+    /// Computes the next generation of a grid too large to be stored in
+    /// a single image. Each tile is surrounded by a halo of one cell
+    /// taken from its neighbors, and computed with its own dispatch.
+    fn next_gen_tiled(&mut self) {
+        let tile_size = self.get_max_tile_size();
+        let cells = self.read_cells();
+        let mut new_cells = vec![0u8; self.width * self.height];
+
+        for tile_y in (0..self.height).step_by(tile_size) {
+            for tile_x in (0..self.width).step_by(tile_size) {
+                let tile_width = tile_size.min(self.width - tile_x);
+                let tile_height = tile_size.min(self.height - tile_y);
+                let (halo_width, halo_height) = (tile_width + 2, tile_height + 2);
+
+                let halo_iter = (0..halo_width * halo_height).map(|i| {
+                    let x = (tile_x + i % halo_width) as i64 - 1;
+                    let y = (tile_y + i / halo_width) as i64 - 1;
+                    match self.topology.map(x, y, self.width, self.height) {
+                        Some((x, y)) => cells[y * self.width + x],
+                        None => 0,
+                    }
+                });
+                let halo = CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::all(),
+                    halo_iter,
+                )
+                .expect("failed to create buffer");
+
+                // The halo already contains the cells across the edges
+                let tiles = all_tiles(halo_width, halo_height);
+                self.run_ngs(
+                    halo.clone(),
+                    (halo_width, halo_height),
+                    Topology::Plane,
+                    (tile_x as i64 - 1, tile_y as i64 - 1),
+                    &tiles,
+                    None,
+                );
+
+                let halo = halo.read().unwrap();
+                for y in 0..tile_height {
+                    let src = (y + 1) * halo_width + 1;
+                    let dst = (tile_y + y) * self.width + tile_x;
+                    new_cells[dst..dst + tile_width].copy_from_slice(&halo[src..src + tile_width]);
+                }
+            }
+        }
+
+        self.write_cells(&new_cells);
+    }
+
+    /// Computes the next generation of bit-packed cells with the
+    /// `ngps` shader. Returns its statistics, the first one being the
+    /// population if `count_population` is `true`.
+    fn next_gen_packed(&mut self, count_population: bool) -> Arc<CpuAccessibleBuffer<[u32]>> {
+        let words_in = match self.cells {
+            Cells::Packed(ref words) => words.clone(),
+            Cells::Bytes(_) => unreachable!(),
+        };
+        let words_per_row = self.width.div_ceil(32);
+
+        let words_out = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..words_per_row * self.height).map(|_| 0u32),
+        )
+        .expect("failed to create buffer");
+
+        let topology = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            self.topology.as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let stochastic = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            self.stochastic().as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let size = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            [
+                self.width as i32,
+                self.height as i32,
+                words_per_row as i32,
+                if count_population {
+                    STATS_POPULATION
+                } else {
+                    0
+                },
+            ],
+        )
+        .expect("failed to create buffer");
+
+        let stats = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..3).map(|_| 0u32),
+        )
+        .expect("failed to create buffer");
+
+        let shader =
+            ngps::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                .add_buffer(words_in.clone())
+                .unwrap()
+                .add_buffer(words_out.clone())
+                .unwrap()
+                .add_buffer(topology.clone())
                 .unwrap()
                 .add_buffer(self.survival.clone())
                 .unwrap()
                 .add_buffer(self.birth.clone())
                 .unwrap()
+                .add_buffer(stochastic.clone())
+                .unwrap()
+                .add_buffer(size.clone())
+                .unwrap()
+                .add_buffer(stats.clone())
+                .unwrap()
                 .build()
                 .unwrap(),
         );
 
         let command_buffer =
             AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
-                .unwrap()
-                .copy_buffer_to_image(self.cells.clone(), cells_in_img.clone())
                 .unwrap()
                 .dispatch(
                     [
-                        (self.width as f64 / 8.0).ceil() as u32,
+                        (words_per_row as f64 / 8.0).ceil() as u32,
                         (self.height as f64 / 8.0).ceil() as u32,
                         1,
                     ],
@@ -108,8 +519,6 @@ impl Grid {
                     (),
                 )
                 .unwrap()
-                .copy_image_to_buffer(cells_out_img.clone(), self.cells.clone())
-                .unwrap()
                 .build()
                 .unwrap();
 
@@ -119,6 +528,56 @@ impl Grid {
             .unwrap()
             .wait(None)
             .unwrap();
+
+        self.cells = Cells::Packed(words_out);
+        stats
+    }
+
+    /// Computes the next generation of the current `Grid` on the CPU
+    /// and updates it.
+    ///
+    /// It gives the same result as `next_gen`, stochastic rules included,
+    /// so a run can be reproduced from its seed without a GPU.
+    /// Deterministic rules are computed by the bit-packed engine
+    /// of `PackedGrid`.
+    pub fn next_gen_cpu(&mut self) {
+        self.clear_journal();
+        self.record_history();
+        self.make_room_for_growth();
+
+        if self.is_deterministic() {
+            let mut packed = PackedGrid::from_grid(self);
+            packed.next_gen();
+            let mut cells = vec![0; self.width * self.height];
+            packed.unpack(&mut cells);
+            self.write_cells(&cells);
+
+            self.generation += 1;
+            return;
+        }
+
+        let new_cells = cpu::next_gen(
+            &self.read_cells(),
+            self.width,
+            self.height,
+            &self.topology,
+            &self.get_survival(),
+            &self.get_birth(),
+            &self.stochastic(),
+        );
+        self.write_cells(&new_cells);
+
+        self.generation += 1;
+    }
+
+    /// Resizes the grid so that the pattern can grow during the next
+    /// generation, if the topology of the grid is unbounded.
+    fn make_room_for_growth(&mut self) {
+        match self.topology {
+            Topology::Resizable => self.recenter_pattern(1),
+            Topology::Cylinder => self.recenter_rows(1),
+            _ => {}
+        }
     }
 
     pub fn recenter_pattern(&mut self, border_width: usize) {
@@ -135,9 +594,66 @@ impl Grid {
             max_y.unwrap(),
         );
 
-        let pattern_origin = (min_x as i32, min_y as i32);
+        let pattern_origin = (min_x, min_y);
         let pattern_size = ((max_x - min_x + 1), (max_y - min_y + 1));
 
+        self.reframe(
+            pattern_origin,
+            pattern_size,
+            (
+                pattern_size.0 + 2 * border_width,
+                pattern_size.1 + 2 * border_width,
+            ),
+            (border_width, border_width),
+        );
+    }
+
+    /// Keeps only the rows containing the pattern, surrounded by
+    /// `border_width` empty rows. The width of the grid is unchanged.
+    pub fn recenter_rows(&mut self, border_width: usize) {
+        let (_, _, min_y, max_y) = self.compute_pattern_boundaries();
+
+        if min_y.is_none() || max_y.is_none() {
+            return;
+        }
+
+        let (min_y, max_y) = (min_y.unwrap(), max_y.unwrap());
+        let rows = max_y - min_y + 1;
+
+        self.reframe(
+            (0, min_y),
+            (self.width, rows),
+            (self.width, rows + 2 * border_width),
+            (0, border_width),
+        );
+    }
+
+    /// Replaces the cells by a new empty `new_size` grid in which the
+    /// `size` region starting at `origin` is copied at `destination`.
+    pub(crate) fn reframe(
+        &mut self,
+        origin: (usize, usize),
+        size: (usize, usize),
+        new_size: (usize, usize),
+        destination: (usize, usize),
+    ) {
+        // The pattern already has the right size and position
+        if origin == destination && new_size == (self.width, self.height) {
+            return;
+        }
+
+        // An empty region can't be copied between images
+        let max_tile_size = self.get_max_tile_size();
+        if self.is_tiled()
+            || new_size.0 > max_tile_size
+            || new_size.1 > max_tile_size
+            || size.0 == 0
+            || size.1 == 0
+        {
+            self.reframe_cpu(origin, size, new_size, destination);
+            return;
+        }
+
         let cells_img = StorageImage::new(
             self.device.clone(),
             Dimensions::Dim2d {
@@ -149,21 +665,21 @@ impl Grid {
         )
         .expect("failed to create image");
 
-        let centered_img = StorageImage::new(
+        let reframed_img = StorageImage::new(
             self.device.clone(),
             Dimensions::Dim2d {
-                width: pattern_size.0 as u32 + 2 * border_width as u32,
-                height: pattern_size.1 as u32 + 2 * border_width as u32,
+                width: new_size.0 as u32,
+                height: new_size.1 as u32,
             },
             Format::R8Unorm,
             Some(self.queue.family()),
         )
         .expect("failed to create image");
 
-        let centered_buff = unsafe {
+        let reframed_buff = unsafe {
             CpuAccessibleBuffer::uninitialized_array(
                 self.device.clone(),
-                (pattern_size.0 + 2 * border_width) * (pattern_size.1 + 2 * border_width),
+                new_size.0 * new_size.1,
                 BufferUsage::all(),
             )
             .expect("failed to create buffer")
@@ -173,7 +689,7 @@ impl Grid {
             AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
                 .unwrap()
                 .clear_color_image(
-                    centered_img.clone(),
+                    reframed_img.clone(),
                     ClearValue::Float([0.0, 0.0, 0.0, 0.0]),
                 )
                 .unwrap()
@@ -190,22 +706,22 @@ impl Grid {
         let command_buffer =
             AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
                 .unwrap()
-                .copy_buffer_to_image(self.cells.clone(), cells_img.clone())
+                .copy_buffer_to_image(self.byte_cells(), cells_img.clone())
                 .unwrap()
                 .copy_image(
                     cells_img.clone(),
-                    [pattern_origin.0, pattern_origin.1, 0],
+                    [origin.0 as i32, origin.1 as i32, 0],
                     0,
                     0,
-                    centered_img.clone(),
-                    [border_width as i32, border_width as i32, 0],
+                    reframed_img.clone(),
+                    [destination.0 as i32, destination.1 as i32, 0],
                     0,
                     0,
-                    [pattern_size.0 as u32, pattern_size.1 as u32, 1],
+                    [size.0 as u32, size.1 as u32, 1],
                     1,
                 )
                 .unwrap()
-                .copy_image_to_buffer(centered_img.clone(), centered_buff.clone())
+                .copy_image_to_buffer(reframed_img.clone(), reframed_buff.clone())
                 .unwrap()
                 .build()
                 .unwrap();
@@ -217,9 +733,39 @@ impl Grid {
             .wait(None)
             .unwrap();
 
-        self.width = pattern_size.0 + 2 * border_width;
-        self.height = pattern_size.1 + 2 * border_width;
-        self.cells = centered_buff;
+        self.width = new_size.0;
+        self.height = new_size.1;
+        self.set_byte_cells(reframed_buff);
+    }
+
+    /// Works like `reframe`, on the CPU, for grids too large to be stored
+    /// in a single image.
+    fn reframe_cpu(
+        &mut self,
+        origin: (usize, usize),
+        size: (usize, usize),
+        new_size: (usize, usize),
+        destination: (usize, usize),
+    ) {
+        let cells = self.read_cells();
+        let mut reframed = vec![0u8; new_size.0 * new_size.1];
+
+        for y in 0..size.1 {
+            let src = (origin.1 + y) * self.width + origin.0;
+            let dst = (destination.1 + y) * new_size.0 + destination.0;
+            reframed[dst..dst + size.0].copy_from_slice(&cells[src..src + size.0]);
+        }
+
+        let reframed_buff = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            reframed.into_iter(),
+        )
+        .expect("failed to create buffer");
+
+        self.width = new_size.0;
+        self.height = new_size.1;
+        self.set_byte_cells(reframed_buff);
     }
 }
 
@@ -233,11 +779,25 @@ impl Grid {
         img_width: usize,
         img_height: usize,
     ) -> Vec<u8> {
+        // Bit-packed cells and the cells of tiled grids are only copied
+        // in the rendered region
+        let (cells, (source_width, source_height), x_pos, y_pos) = match self.cells {
+            Cells::Bytes(ref cells) if !self.is_tiled() => {
+                (cells.clone(), (self.width, self.height), x_pos, y_pos)
+            }
+            _ => (
+                self.byte_cells_region(x_pos, y_pos, view_width, view_height),
+                (view_width, view_height),
+                0,
+                0,
+            ),
+        };
+
         let cells_img = StorageImage::new(
             self.device.clone(),
             Dimensions::Dim2d {
-                width: self.width as u32,
-                height: self.height as u32,
+                width: source_width as u32,
+                height: source_height as u32,
             },
             Format::R8Unorm,
             Some(self.queue.family()),
@@ -267,7 +827,7 @@ impl Grid {
         let command_buffer =
             AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
                 .unwrap()
-                .copy_buffer_to_image(self.cells.clone(), cells_img.clone())
+                .copy_buffer_to_image(cells, cells_img.clone())
                 .unwrap()
                 .blit_image(
                     cells_img.clone(),
@@ -306,3 +866,13 @@ impl Grid {
         output
     }
 }
+
+/// Returns the coordinates of all the tiles of a `width` x `height` grid.
+pub(crate) fn all_tiles(width: usize, height: usize) -> Vec<(usize, usize)> {
+    let tiles_per_row = width.div_ceil(ACTIVE_TILE_SIZE);
+    let tiles_per_col = height.div_ceil(ACTIVE_TILE_SIZE);
+
+    (0..tiles_per_row * tiles_per_col)
+        .map(|i| (i % tiles_per_row, i / tiles_per_row))
+        .collect()
+}
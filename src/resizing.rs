@@ -0,0 +1,200 @@
+//! This module contains some methods to change the size of a grid
+//! explicitly, and to convert a resizable grid into a toroidal one and
+//! vice versa.
+
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// Represents the point of a grid which stays in place when it is
+/// resized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    NorthWest,
+    North,
+    NorthEast,
+    West,
+    Center,
+    East,
+    SouthWest,
+    South,
+    SouthEast,
+}
+
+impl Anchor {
+    // Returns the position of the old grid in the new one, as a fraction
+    // (0, 1 or 2 halves) of the size difference along each axis
+    fn halves(self) -> (i64, i64) {
+        match self {
+            Anchor::NorthWest => (0, 0),
+            Anchor::North => (1, 0),
+            Anchor::NorthEast => (2, 0),
+            Anchor::West => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::East => (2, 1),
+            Anchor::SouthWest => (0, 2),
+            Anchor::South => (1, 2),
+            Anchor::SouthEast => (2, 2),
+        }
+    }
+}
+
+impl Grid {
+    /// Changes the size of the current `Grid` to `width` x `height`,
+    /// keeping the topology. The cells stay in place relatively to
+    /// `anchor`: the new cells are dead and the cells beyond the new edges
+    /// are lost.
+    ///
+    /// Returns `Err(GridErrorKind::OutOfBoundCoords)` if the new size is
+    /// empty, or `Err(GridErrorKind::UnsupportedTopology)` if a sphere
+    /// would no longer be square.
+    pub fn resize(
+        &mut self,
+        width: usize,
+        height: usize,
+        anchor: Anchor,
+    ) -> Result<(), GridErrorKind> {
+        let (halves_x, halves_y) = anchor.halves();
+        let offset_x = (width as i64 - self.width as i64) * halves_x / 2;
+        let offset_y = (height as i64 - self.height as i64) * halves_y / 2;
+
+        self.move_into((width, height), (offset_x, offset_y))
+    }
+
+    /// Adds rows and columns of dead cells around the current `Grid`.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` if a sphere
+    /// would no longer be square.
+    pub fn pad(
+        &mut self,
+        top: usize,
+        right: usize,
+        bottom: usize,
+        left: usize,
+    ) -> Result<(), GridErrorKind> {
+        let new_size = (self.width + left + right, self.height + top + bottom);
+
+        self.move_into(new_size, (left as i64, top as i64))
+    }
+
+    /// Shrinks or grows the current `Grid` so that it only contains its
+    /// pattern surrounded by `border` rows and columns of dead cells. An
+    /// empty grid is left unchanged.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` if a sphere
+    /// would no longer be square.
+    pub fn crop_to_pattern(&mut self, border: usize) -> Result<(), GridErrorKind> {
+        let (min_x, max_x, min_y, max_y) = match self.compute_pattern_boundaries() {
+            (Some(min_x), Some(max_x), Some(min_y), Some(max_y)) => (min_x, max_x, min_y, max_y),
+            _ => return Ok(()),
+        };
+
+        let new_size = (
+            max_x - min_x + 1 + 2 * border,
+            max_y - min_y + 1 + 2 * border,
+        );
+        self.move_into(
+            new_size,
+            (border as i64 - min_x as i64, border as i64 - min_y as i64),
+        )
+    }
+
+    /// Turns the current `Grid` into a `width` x `height` torus without
+    /// shift, its pattern being placed at the center. The cells which
+    /// don't fit in the torus are lost.
+    ///
+    /// Returns `Err(GridErrorKind::OutOfBoundCoords)` if the size is
+    /// empty.
+    pub fn make_toroidal(&mut self, width: usize, height: usize) -> Result<(), GridErrorKind> {
+        if width == 0 || height == 0 {
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+
+        self.journaled(|grid| {
+            // The pattern is cropped first so that it is centered
+            grid.topology = Topology::Resizable;
+            grid.crop_to_pattern(0)?;
+            grid.resize(width, height, Anchor::Center)?;
+
+            grid.topology = Topology::torus();
+            grid.format = String::from("#Toroidal Life");
+            Ok(())
+        })
+    }
+
+    /// Turns the current `Grid` into a resizable grid which grows with its
+    /// pattern. The cells are kept where they are, the grid being cropped
+    /// around its pattern like before a generation.
+    pub fn make_resizable(&mut self) {
+        self.journaled(|grid| {
+            grid.topology = Topology::Resizable;
+            grid.format = String::from("#Resizable Life");
+            grid.recenter_pattern(1);
+        });
+    }
+
+    // Replaces the cells by a `new_size` grid in which the cells are
+    // moved by `offset`
+    fn move_into(
+        &mut self,
+        new_size: (usize, usize),
+        offset: (i64, i64),
+    ) -> Result<(), GridErrorKind> {
+        if new_size.0 == 0 || new_size.1 == 0 {
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+        if self.topology == Topology::Sphere && new_size.0 != new_size.1 {
+            return Err(GridErrorKind::UnsupportedTopology);
+        }
+
+        // Only the part of the old grid which is still inside the new one
+        // is copied
+        let (origin_x, destination_x, copied_width) = overlap(self.width, new_size.0, offset.0);
+        let (origin_y, destination_y, copied_height) = overlap(self.height, new_size.1, offset.1);
+
+        self.journaled(|grid| {
+            grid.reframe(
+                (origin_x, origin_y),
+                (copied_width, copied_height),
+                new_size,
+                (destination_x, destination_y),
+            )
+        });
+
+        Ok(())
+    }
+}
+
+// Returns the start of the part of a segment of length `old` which stays
+// in a segment of length `new` when it is moved by `offset`, its start in
+// the new segment and its length
+fn overlap(old: usize, new: usize, offset: i64) -> (usize, usize, usize) {
+    let start = offset.max(0);
+    let end = (offset + old as i64).min(new as i64);
+
+    if end <= start {
+        return (0, 0, 0);
+    }
+
+    (
+        (start - offset) as usize,
+        start as usize,
+        (end - start) as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::overlap;
+
+    #[test]
+    fn test_overlap() {
+        // Growing
+        assert_eq!((0, 2, 5), overlap(5, 9, 2));
+        // Shrinking
+        assert_eq!((2, 0, 3), overlap(5, 3, -2));
+        // Moved beyond the new edges
+        assert_eq!((0, 0, 0), overlap(5, 3, 4));
+        assert_eq!((0, 0, 0), overlap(5, 3, -5));
+    }
+}
@@ -0,0 +1,331 @@
+//! This module contains the generation of reproducible random soups,
+//! optionally symmetric like the soups of apgsearch.
+//!
+//! The state of each cell of a soup only depends on the seed and on its
+//! coordinates in the soup, so the same seed always gives the same soup.
+
+use super::cpu;
+use error::GridErrorKind;
+use Grid;
+
+/// Stream of the random numbers used to generate the soups.
+const SOUP_STREAM: u32 = 2;
+
+/// The symmetries of the soups, named as in apgsearch.
+///
+/// The suffix gives the position of the center of the symmetry:
+/// `1` on a cell, `2` on the middle of the edge of a cell and `4` on a
+/// corner of a cell. The size of the soup is reduced by one cell in a
+/// direction if needed to match it. The symmetries involving a diagonal
+/// or a rotation by 90 degrees need a square soup: the smallest side of
+/// the region is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symmetry {
+    C1,
+    C2_1,
+    C2_2,
+    C2_4,
+    C4_1,
+    C4_4,
+    D2Plus1,
+    D2Plus2,
+    D2X,
+    D4Plus1,
+    D4Plus2,
+    D4Plus4,
+    D4X1,
+    D4X4,
+    D8_1,
+    D8_4,
+}
+
+/// The transformations of the cells of a soup, which make up the
+/// symmetry groups.
+#[derive(Clone, Copy)]
+enum Transform {
+    FlipHorizontal,
+    FlipVertical,
+    Rotate180,
+    Transpose,
+    AntiTranspose,
+    Rotate90,
+    Rotate270,
+}
+
+const SYMMETRIES: [(Symmetry, &str); 16] = [
+    (Symmetry::C1, "C1"),
+    (Symmetry::C2_1, "C2_1"),
+    (Symmetry::C2_2, "C2_2"),
+    (Symmetry::C2_4, "C2_4"),
+    (Symmetry::C4_1, "C4_1"),
+    (Symmetry::C4_4, "C4_4"),
+    (Symmetry::D2Plus1, "D2_+1"),
+    (Symmetry::D2Plus2, "D2_+2"),
+    (Symmetry::D2X, "D2_x"),
+    (Symmetry::D4Plus1, "D4_+1"),
+    (Symmetry::D4Plus2, "D4_+2"),
+    (Symmetry::D4Plus4, "D4_+4"),
+    (Symmetry::D4X1, "D4_x1"),
+    (Symmetry::D4X4, "D4_x4"),
+    (Symmetry::D8_1, "D8_1"),
+    (Symmetry::D8_4, "D8_4"),
+];
+
+impl Symmetry {
+    /// Returns the symmetry named `name` by apgsearch (e.g. `"D2_+1"`).
+    pub fn from_name(name: &str) -> Option<Symmetry> {
+        SYMMETRIES
+            .iter()
+            .find(|&&(_, n)| n == name)
+            .map(|&(symmetry, _)| symmetry)
+    }
+
+    /// Returns the name of the symmetry used by apgsearch.
+    pub fn get_name(&self) -> &'static str {
+        SYMMETRIES.iter().find(|&&(s, _)| s == *self).unwrap().1
+    }
+
+    // Returns the transformations of the group, the identity excepted
+    fn transforms(&self) -> &'static [Transform] {
+        use self::Transform::*;
+
+        match *self {
+            Symmetry::C1 => &[],
+            Symmetry::C2_1 | Symmetry::C2_2 | Symmetry::C2_4 => &[Rotate180],
+            Symmetry::C4_1 | Symmetry::C4_4 => &[Rotate90, Rotate180, Rotate270],
+            Symmetry::D2Plus1 | Symmetry::D2Plus2 => &[FlipVertical],
+            Symmetry::D2X => &[Transpose],
+            Symmetry::D4Plus1 | Symmetry::D4Plus2 | Symmetry::D4Plus4 => {
+                &[FlipHorizontal, FlipVertical, Rotate180]
+            }
+            Symmetry::D4X1 | Symmetry::D4X4 => &[Transpose, AntiTranspose, Rotate180],
+            Symmetry::D8_1 | Symmetry::D8_4 => &[
+                FlipHorizontal,
+                FlipVertical,
+                Rotate180,
+                Transpose,
+                AntiTranspose,
+                Rotate90,
+                Rotate270,
+            ],
+        }
+    }
+
+    // Returns the size of the soup generated in a `width` x `height`
+    // region
+    fn soup_size(&self, width: usize, height: usize) -> (usize, usize) {
+        // Rounds `n` down to the given parity
+        let odd = |n: usize| if n % 2 == 1 { n } else { n.saturating_sub(1) };
+        let even = |n: usize| n - n % 2;
+
+        match *self {
+            Symmetry::C1 => (width, height),
+            Symmetry::D2X => (width.min(height), width.min(height)),
+            Symmetry::C2_1 | Symmetry::D4Plus1 => (odd(width), odd(height)),
+            Symmetry::C2_2 | Symmetry::D4Plus2 => (odd(width), even(height)),
+            Symmetry::C2_4 | Symmetry::D4Plus4 => (even(width), even(height)),
+            Symmetry::D2Plus1 => (width, odd(height)),
+            Symmetry::D2Plus2 => (width, even(height)),
+            Symmetry::C4_1 | Symmetry::D4X1 | Symmetry::D8_1 => {
+                (odd(width.min(height)), odd(width.min(height)))
+            }
+            Symmetry::C4_4 | Symmetry::D4X4 | Symmetry::D8_4 => {
+                (even(width.min(height)), even(width.min(height)))
+            }
+        }
+    }
+}
+
+impl Transform {
+    fn apply(self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Transform::FlipHorizontal => (width - 1 - x, y),
+            Transform::FlipVertical => (x, height - 1 - y),
+            Transform::Rotate180 => (width - 1 - x, height - 1 - y),
+            Transform::Transpose => (y, x),
+            Transform::AntiTranspose => (height - 1 - y, width - 1 - x),
+            Transform::Rotate90 => (height - 1 - y, x),
+            Transform::Rotate270 => (y, width - 1 - x),
+        }
+    }
+}
+
+/// The parameters of a random soup.
+#[derive(Clone, Copy, Debug)]
+pub struct SoupOptions {
+    /// Seed of the random numbers.
+    pub seed: u64,
+    /// Probability for each cell to be alive.
+    pub density: f64,
+    /// Region `(x, y, width, height)` containing the soup, or `None` for
+    /// the whole grid.
+    pub region: Option<(usize, usize, usize, usize)>,
+    /// Symmetry of the soup.
+    pub symmetry: Symmetry,
+}
+
+impl Default for SoupOptions {
+    fn default() -> SoupOptions {
+        SoupOptions {
+            seed: 0,
+            density: 0.5,
+            region: None,
+            symmetry: Symmetry::C1,
+        }
+    }
+}
+
+impl Grid {
+    /// Randomizes the cells of `region` (`(x, y, width, height)`, or the
+    /// whole grid if `None`) from `seed`, each cell being alive with the
+    /// probability `density`. The cells outside of the region are kept.
+    ///
+    /// Returns an error if `density` isn't between 0 and 1 or if the
+    /// region isn't inside the grid.
+    pub fn randomize_with(
+        &mut self,
+        seed: u64,
+        density: f64,
+        region: Option<(usize, usize, usize, usize)>,
+    ) -> Result<(), GridErrorKind> {
+        self.randomize_soup(&SoupOptions {
+            seed,
+            density,
+            region,
+            symmetry: Symmetry::C1,
+        })
+    }
+
+    /// Randomizes the cells of a region like `randomize_with`, the soup
+    /// having the symmetry of `options`. The soup is placed at the north
+    /// west corner of the region when it is smaller (see `Symmetry`), and
+    /// the rest of the region is cleared.
+    pub fn randomize_soup(&mut self, options: &SoupOptions) -> Result<(), GridErrorKind> {
+        if !(0.0..=1.0).contains(&options.density) {
+            return Err(GridErrorKind::InvalidProbability);
+        }
+
+        let (x0, y0, width, height) =
+            options
+                .region
+                .unwrap_or((0, 0, self.get_width(), self.get_height()));
+        if x0 + width > self.get_width() || y0 + height > self.get_height() {
+            return Err(GridErrorKind::OutOfBoundCoords);
+        }
+
+        let (soup_width, soup_height, soup) = soup_cells(
+            options.seed,
+            options.density,
+            width,
+            height,
+            options.symmetry,
+        );
+
+        let cells = (0..width * height).map(|i| {
+            let (x, y) = (i % width, i / width);
+            let state = if x < soup_width && y < soup_height {
+                soup[y * soup_width + x]
+            } else {
+                0
+            };
+            (x0 + x, y0 + y, state)
+        });
+        self.set_cells(cells)
+    }
+}
+
+// Returns the size and the cells of a soup generated in a `width` x
+// `height` region
+fn soup_cells(
+    seed: u64,
+    density: f64,
+    width: usize,
+    height: usize,
+    symmetry: Symmetry,
+) -> (usize, usize, Vec<u8>) {
+    let (width, height) = symmetry.soup_size(width, height);
+    let threshold = cpu::probability_to_threshold(density);
+
+    let mut cells = vec![0; width * height];
+    for (i, cell) in cells.iter_mut().enumerate() {
+        // Every cell of an orbit takes the random number of the same cell
+        let (x, y) = symmetry
+            .transforms()
+            .iter()
+            .map(|t| t.apply(i % width, i / width, width, height))
+            .fold((i % width, i / width), |min, (x, y)| {
+                if (y, x) < (min.1, min.0) {
+                    (x, y)
+                } else {
+                    min
+                }
+            });
+
+        let random = cpu::random(seed, 0, x as u32, y as u32, SOUP_STREAM);
+        if cpu::chance(random, threshold) {
+            *cell = 255;
+        }
+    }
+
+    (width, height, cells)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{soup_cells, Symmetry, Transform, SYMMETRIES};
+
+    #[test]
+    fn test_names() {
+        for &(symmetry, name) in SYMMETRIES.iter() {
+            assert_eq!(Some(symmetry), Symmetry::from_name(name));
+            assert_eq!(name, symmetry.get_name());
+        }
+        assert_eq!(None, Symmetry::from_name("D2_y"));
+    }
+
+    #[test]
+    fn test_symmetric_soups() {
+        for &(symmetry, _) in SYMMETRIES.iter() {
+            let (width, height, cells) = soup_cells(42, 0.5, 16, 16, symmetry);
+
+            for t in symmetry.transforms() {
+                for (i, &cell) in cells.iter().enumerate() {
+                    let (x, y) = t.apply(i % width, i / width, width, height);
+                    assert_eq!(cell, cells[y * width + x]);
+                }
+            }
+        }
+
+        // The center of the symmetry
+        assert_eq!((16, 16), Symmetry::C2_4.soup_size(16, 16));
+        assert_eq!((15, 15), Symmetry::C2_1.soup_size(16, 16));
+        assert_eq!((15, 16), Symmetry::C2_2.soup_size(16, 16));
+        assert_eq!((15, 15), Symmetry::D8_1.soup_size(16, 20));
+
+        // An asymmetric soup isn't invariant
+        let (width, height, cells) = soup_cells(42, 0.5, 16, 16, Symmetry::C1);
+        assert!(cells.iter().enumerate().any(|(i, &cell)| {
+            let (x, y) = Transform::Rotate180.apply(i % width, i / width, width, height);
+            cell != cells[y * width + x]
+        }));
+    }
+
+    #[test]
+    fn test_reproducible_density() {
+        assert_eq!(
+            soup_cells(7, 0.3, 64, 64, Symmetry::C1),
+            soup_cells(7, 0.3, 64, 64, Symmetry::C1)
+        );
+        assert_ne!(
+            soup_cells(7, 0.3, 64, 64, Symmetry::C1),
+            soup_cells(8, 0.3, 64, 64, Symmetry::C1)
+        );
+
+        let (_, _, cells) = soup_cells(7, 0.3, 64, 64, Symmetry::C1);
+        let population = cells.iter().filter(|&&cell| cell == 255).count();
+        assert!(population > 1000 && population < 1450);
+
+        let (_, _, cells) = soup_cells(7, 0.0, 64, 64, Symmetry::C1);
+        assert!(cells.iter().all(|&cell| cell == 0));
+    }
+}
@@ -0,0 +1,348 @@
+//! This module contains an unbounded grid whose cells are addressed by
+//! signed 64-bit coordinates.
+//!
+//! Unlike the resizable mode of `Grid`, which keeps a dense buffer of
+//! the size of the pattern, the grid is split into 64x64 tiles and only
+//! the tiles containing living cells are stored and simulated. This makes
+//! it suitable for patterns made of distant objects, like two gliders
+//! flying away from each other.
+
+use std::collections::{HashMap, HashSet};
+
+use super::cpu;
+use topology::Topology;
+use Grid;
+
+/// Size of the side of a tile.
+pub const TILE_SIZE: i64 = 64;
+
+/// A 64x64 tile, stored as one `u64` per row.
+/// The bit `i` of a row is the cell at the column `i` of the tile.
+type Tile = [u64; TILE_SIZE as usize];
+
+const EMPTY_TILE: Tile = [0; TILE_SIZE as usize];
+
+/// This struct contains an unbounded life cellular automaton grid.
+///
+/// The cells are stored in 64x64 tiles kept in a `HashMap` indexed by the
+/// coordinates of the tiles. Empty tiles aren't stored, and computing the
+/// next generation only simulates the stored tiles and their neighbors.
+///
+/// It supports the same survival and birth conditions as `Grid`,
+/// but not the stochastic rules.
+#[derive(Clone, Debug)]
+pub struct SparseGrid {
+    survival: Vec<u32>,
+    birth: Vec<u32>,
+
+    tiles: HashMap<(i64, i64), Tile>,
+}
+
+impl SparseGrid {
+    /// Returns a new empty `SparseGrid` containing the rules given by
+    /// `srvl` and `brth`.
+    pub fn new(srvl: &[u32], brth: &[u32]) -> SparseGrid {
+        SparseGrid {
+            survival: srvl.to_vec(),
+            birth: brth.to_vec(),
+            tiles: HashMap::new(),
+        }
+    }
+
+    /// Returns a new `SparseGrid` containing the rules and the living
+    /// cells of `grid`. The cell at the coordinates (`x`, `y`) of `grid`
+    /// has the same coordinates in the new `SparseGrid`.
+    pub fn from_grid(grid: &Grid) -> SparseGrid {
+        let mut sparse = SparseGrid::new(&grid.get_survival(), &grid.get_birth());
+
+        for y in 0..grid.get_height() {
+            for x in 0..grid.get_width() {
+                if grid.get_cell_state(x as i64, y as i64) == 255 {
+                    sparse.set_cell_state(x as i64, y as i64, 255);
+                }
+            }
+        }
+
+        sparse
+    }
+
+    /// Returns a new resizable `Grid` containing the rules and the living
+    /// cells of this `SparseGrid`, using the file format `frmt`.
+    ///
+    /// The pattern is translated so that the north west corner of its
+    /// bounding box (see `bounding_box`) is at the coordinates (0, 0).
+    /// An empty `SparseGrid` gives a 1x1 grid.
+    pub fn to_grid(&self, frmt: &String) -> Grid {
+        let (min_x, max_x, min_y, max_y) = self.bounding_box().unwrap_or((0, 0, 0, 0));
+
+        let mut grid = Grid::new(
+            frmt,
+            Topology::Resizable,
+            &self.survival,
+            &self.birth,
+            (max_x - min_x + 1) as usize,
+            (max_y - min_y + 1) as usize,
+        );
+
+        for (x, y) in self.live_cells() {
+            grid.set_cell_state((x - min_x) as usize, (y - min_y) as usize, 255)
+                .unwrap(); // Shouldn't fail
+        }
+
+        grid
+    }
+
+    /// Returns the survival conditions of the cellular automaton.
+    pub fn get_survival(&self) -> Vec<u32> {
+        self.survival.clone()
+    }
+
+    /// Returns the birth conditions of the cellular automaton.
+    pub fn get_birth(&self) -> Vec<u32> {
+        self.birth.clone()
+    }
+
+    /// Returns the state of the cell at the coordinates (`x`, `y`).
+    pub fn get_cell_state(&self, x: i64, y: i64) -> u8 {
+        let (tile_coords, col, row) = split_coords(x, y);
+
+        match self.tiles.get(&tile_coords) {
+            Some(tile) if tile[row] & (1 << col) != 0 => 255,
+            _ => 0,
+        }
+    }
+
+    /// Modifies the state of the cell at the coordinates (`x`, `y`)
+    /// with `state`. Any state other than `0` is a living cell.
+    pub fn set_cell_state(&mut self, x: i64, y: i64, state: u8) {
+        let (tile_coords, col, row) = split_coords(x, y);
+
+        if state != 0 {
+            self.tiles.entry(tile_coords).or_insert(EMPTY_TILE)[row] |= 1 << col;
+        } else if let Some(tile) = self.tiles.get_mut(&tile_coords) {
+            tile[row] &= !(1 << col);
+        }
+    }
+
+    /// Returns the number of living cells.
+    pub fn population(&self) -> u64 {
+        self.tiles
+            .values()
+            .flat_map(|tile| tile.iter())
+            .map(|row| u64::from(row.count_ones()))
+            .sum()
+    }
+
+    /// Returns the number of tiles currently stored.
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Returns the coordinates of the living cells, row by row.
+    pub fn live_cells(&self) -> Vec<(i64, i64)> {
+        let mut cells = Vec::new();
+
+        for (&(tx, ty), tile) in &self.tiles {
+            for (row, &bits) in tile.iter().enumerate() {
+                for col in 0..TILE_SIZE {
+                    if bits & (1 << col) != 0 {
+                        cells.push((tx * TILE_SIZE + col, ty * TILE_SIZE + row as i64));
+                    }
+                }
+            }
+        }
+
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells
+    }
+
+    /// Returns the boundaries of the pattern as
+    /// `(min_x, max_x, min_y, max_y)`, or `None` if there is no living cell.
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let mut bounds: Option<(i64, i64, i64, i64)> = None;
+
+        for (&(tx, ty), tile) in &self.tiles {
+            for (row, &bits) in tile.iter().enumerate() {
+                if bits == 0 {
+                    continue;
+                }
+
+                let y = ty * TILE_SIZE + row as i64;
+                let x0 = tx * TILE_SIZE + i64::from(bits.trailing_zeros());
+                let x1 = tx * TILE_SIZE + 63 - i64::from(bits.leading_zeros());
+
+                bounds = Some(match bounds {
+                    None => (x0, x1, y, y),
+                    Some((min_x, max_x, min_y, max_y)) => {
+                        (min_x.min(x0), max_x.max(x1), min_y.min(y), max_y.max(y))
+                    }
+                });
+            }
+        }
+
+        bounds
+    }
+
+    /// Computes the next generation of the current `SparseGrid` and
+    /// updates it.
+    pub fn next_gen(&mut self) {
+        let survival = cpu::rule_mask(&self.survival);
+        let birth = cpu::rule_mask(&self.birth);
+
+        // Only the stored tiles and the tiles next to their living edge
+        // cells can contain living cells in the next generation
+        let mut active: HashSet<(i64, i64)> = HashSet::new();
+        for (&(tx, ty), tile) in &self.tiles {
+            let left = tile.iter().any(|row| row & 1 != 0);
+            let right = tile.iter().any(|row| row & (1 << 63) != 0);
+            let top = tile[0] != 0;
+            let bottom = tile[TILE_SIZE as usize - 1] != 0;
+
+            for dy in -1..2 {
+                for dx in -1..2 {
+                    let reaches = (dx != -1 || left)
+                        && (dx != 1 || right)
+                        && (dy != -1 || top)
+                        && (dy != 1 || bottom);
+                    if reaches {
+                        active.insert((tx + dx, ty + dy));
+                    }
+                }
+            }
+        }
+
+        let mut new_tiles = HashMap::with_capacity(active.len());
+        for tile_coords in active {
+            let new_tile = self.next_tile(tile_coords, survival, birth);
+            if new_tile.iter().any(|&row| row != 0) {
+                new_tiles.insert(tile_coords, new_tile);
+            }
+        }
+
+        self.tiles = new_tiles;
+    }
+
+    fn next_tile(&self, (tx, ty): (i64, i64), survival: u16, birth: u16) -> Tile {
+        let tile = |dx: i64, dy: i64| self.tiles.get(&(tx + dx, ty + dy));
+        let neighborhood = [
+            [tile(-1, -1), tile(0, -1), tile(1, -1)],
+            [tile(-1, 0), tile(0, 0), tile(1, 0)],
+            [tile(-1, 1), tile(0, 1), tile(1, 1)],
+        ];
+
+        // Returns the row `row` of the tiles in the line `line` of the
+        // neighborhood, as expected by `cpu::next_word`
+        let get_row = |line: usize, row: usize| -> [u64; 3] {
+            let bits = |tile: Option<&Tile>| tile.map_or(0, |tile| tile[row]);
+            let (west, center, east) = (
+                bits(neighborhood[line][0]),
+                bits(neighborhood[line][1]),
+                bits(neighborhood[line][2]),
+            );
+
+            [
+                (center << 1) | (west >> 63),
+                center,
+                (center >> 1) | (east << 63),
+            ]
+        };
+
+        let last = TILE_SIZE as usize - 1;
+        let mut new_tile = EMPTY_TILE;
+        for (row, new_row) in new_tile.iter_mut().enumerate() {
+            let above = if row == 0 {
+                get_row(0, last)
+            } else {
+                get_row(1, row - 1)
+            };
+            let below = if row == last {
+                get_row(2, 0)
+            } else {
+                get_row(1, row + 1)
+            };
+
+            *new_row = cpu::next_word(above, get_row(1, row), below, survival, birth);
+        }
+
+        new_tile
+    }
+}
+
+// Returns the coordinates of the tile containing the cell at (`x`, `y`)
+// and the column and row of the cell in this tile
+fn split_coords(x: i64, y: i64) -> ((i64, i64), u32, usize) {
+    (
+        (x.div_euclid(TILE_SIZE), y.div_euclid(TILE_SIZE)),
+        x.rem_euclid(TILE_SIZE) as u32,
+        y.rem_euclid(TILE_SIZE) as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SparseGrid;
+
+    #[test]
+    fn test_negative_coords() {
+        let mut grid = SparseGrid::new(&[2, 3], &[3]);
+
+        grid.set_cell_state(-1, -70, 255);
+        grid.set_cell_state(63, 64, 255);
+
+        assert_eq!(255, grid.get_cell_state(-1, -70));
+        assert_eq!(255, grid.get_cell_state(63, 64));
+        assert_eq!(0, grid.get_cell_state(0, -70));
+        assert_eq!(2, grid.population());
+        assert_eq!(Some((-1, 63, -70, 64)), grid.bounding_box());
+
+        grid.set_cell_state(-1, -70, 0);
+        assert_eq!(vec![(63, 64)], grid.live_cells());
+    }
+
+    #[test]
+    fn test_blinker_across_tiles() {
+        let mut grid = SparseGrid::new(&[2, 3], &[3]);
+
+        // A horizontal blinker on the edges of four tiles
+        grid.set_cell_state(-1, -1, 255);
+        grid.set_cell_state(0, -1, 255);
+        grid.set_cell_state(1, -1, 255);
+
+        grid.next_gen();
+        assert_eq!(vec![(0, -2), (0, -1), (0, 0)], grid.live_cells());
+
+        grid.next_gen();
+        assert_eq!(vec![(-1, -1), (0, -1), (1, -1)], grid.live_cells());
+    }
+
+    #[test]
+    fn test_distant_gliders() {
+        let mut grid = SparseGrid::new(&[2, 3], &[3]);
+
+        // A glider flying north west
+        for &(x, y) in &[(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)] {
+            grid.set_cell_state(x, y, 255);
+        }
+        // A glider flying south east, far away
+        for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            grid.set_cell_state(x + 1_000_000, y + 1_000_000, 255);
+        }
+
+        for _ in 0..4 * 40 {
+            grid.next_gen();
+        }
+
+        let mut expected = vec![];
+        for &(x, y) in &[(0, 0), (1, 0), (2, 0), (0, 1), (1, 2)] {
+            expected.push((x - 40, y - 40));
+        }
+        for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            expected.push((x + 1_000_040, y + 1_000_040));
+        }
+        expected.sort_by_key(|&(x, y)| (y, x));
+
+        assert_eq!(expected, grid.live_cells());
+        assert_eq!(10, grid.population());
+        assert!(grid.tile_count() <= 8);
+    }
+}
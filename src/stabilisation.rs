@@ -0,0 +1,85 @@
+//! This module contains the detection of the stabilisation of a pattern,
+//! like a methuselah or a soup.
+//!
+//! Each generation is summarised on the GPU by its population and a hash
+//! of its pattern which doesn't depend on its position, so that the cells
+//! don't have to be read back: the pattern stabilised as soon as a summary
+//! appears again.
+
+use std::collections::VecDeque;
+
+use error::GridErrorKind;
+use Grid;
+
+/// The stabilisation of a pattern found by `Grid::run_until_stable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stabilisation {
+    generation: u64,
+    period: u64,
+    population: u64,
+}
+
+impl Stabilisation {
+    /// Returns the first generation of the final cycle of the pattern.
+    pub fn get_generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the period of the final cycle, 1 if the pattern doesn't
+    /// change.
+    pub fn get_period(&self) -> u64 {
+        self.period
+    }
+
+    /// Returns the population of the pattern when the stabilisation was
+    /// detected.
+    pub fn get_population(&self) -> u64 {
+        self.population
+    }
+}
+
+impl Grid {
+    /// Computes up to `max_gens` generations of the current `Grid`, until
+    /// its population and the hash of its pattern repeat with a period of
+    /// at most `period_window` generations. The grid is left at the
+    /// generation in which the repetition was detected.
+    ///
+    /// Since the hash doesn't depend on the position of the pattern, a
+    /// lone spaceship is stable too. A pattern which keeps emitting
+    /// spaceships never is.
+    ///
+    /// Returns `Ok(None)` if the pattern didn't stabilise, or
+    /// `Err(GridErrorKind::UnsupportedRule)` if the rules are stochastic.
+    pub fn run_until_stable(
+        &mut self,
+        max_gens: u64,
+        period_window: u64,
+    ) -> Result<Option<Stabilisation>, GridErrorKind> {
+        if !self.is_deterministic() {
+            return Err(GridErrorKind::UnsupportedRule);
+        }
+
+        // Summaries of the last generations, the most recent one last
+        let mut summaries = VecDeque::new();
+        let mut summary = self.summarise_pattern();
+
+        for _ in 0..max_gens {
+            summaries.push_back(summary);
+            if summaries.len() as u64 > period_window {
+                summaries.pop_front();
+            }
+
+            summary = self.next_gen_with_hash();
+            if let Some(i) = summaries.iter().rev().position(|&old| old == summary) {
+                let period = i as u64 + 1;
+                return Ok(Some(Stabilisation {
+                    generation: self.generation - period,
+                    period,
+                    population: summary.0,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+}
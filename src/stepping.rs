@@ -0,0 +1,407 @@
+//! This module contains the asynchronous computation of the generations
+//! of a grid, so that the previous state can still be rendered while
+//! the GPU computes the next ones.
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+use super::vulkano::buffer::BufferUsage;
+use super::vulkano::buffer::CpuAccessibleBuffer;
+use super::vulkano::command_buffer::AutoCommandBufferBuilder;
+use super::vulkano::command_buffer::CommandBuffer;
+use super::vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use super::vulkano::format::Format;
+use super::vulkano::image::Dimensions;
+use super::vulkano::image::StorageImage;
+use super::vulkano::pipeline::ComputePipeline;
+use super::vulkano::sync::FenceSignalFuture;
+use super::vulkano::sync::GpuFuture;
+
+use super::cpu;
+use super::vulkan::ngps;
+use super::vulkan::ngs;
+use processing::{all_tiles, ACTIVE_TILE_SIZE, MAX_WORK_GROUPS};
+use topology::Topology;
+use Cells;
+use Grid;
+
+/// A handle on generations being computed by the GPU, returned by
+/// `Grid::step_async`.
+///
+/// The grid is borrowed by the handle, so it can't be modified while the
+/// generations are computed. It keeps its previous state until the handle
+/// is waited on or dropped.
+pub struct StepHandle<'a> {
+    grid: &'a mut Grid,
+    generations: u64,
+
+    new_cells: Option<Cells>,
+    finished: Option<Receiver<()>>,
+}
+
+impl<'a> StepHandle<'a> {
+    /// Returns the grid in the state it had before the generations
+    /// were computed, e.g. to render it.
+    pub fn grid(&self) -> &Grid {
+        self.grid
+    }
+
+    /// Returns the number of generations being computed.
+    pub fn get_generations(&self) -> u64 {
+        self.generations
+    }
+
+    /// Returns `true` if the GPU finished computing the generations,
+    /// without blocking.
+    pub fn is_finished(&mut self) -> bool {
+        let finished = match self.finished {
+            Some(ref finished) => finished.try_recv().is_ok(),
+            None => true,
+        };
+        if finished {
+            self.finished = None;
+        }
+
+        finished
+    }
+
+    /// Blocks until the generations are computed, and updates the grid.
+    pub fn wait(mut self) {
+        self.finish();
+    }
+
+    fn finish(&mut self) {
+        if let Some(finished) = self.finished.take() {
+            // An error means the GPU work is over anyway
+            let _ = finished.recv();
+        }
+
+        if let Some(new_cells) = self.new_cells.take() {
+            self.grid.cells = new_cells;
+            self.grid.changed_tiles = None;
+            self.grid.generation += self.generations;
+        }
+    }
+}
+
+impl<'a> Drop for StepHandle<'a> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl Grid {
+    /// Starts computing the `n` next generations of the current `Grid`
+    /// on the GPU, and returns a handle which can be polled or waited on.
+    ///
+    /// The generations of resizable and cylindrical grids, and of grids
+    /// split into tiles, need to be read back after each generation: they
+    /// are computed before returning, and the handle is already finished.
+    pub fn step_async(&mut self, n: u64) -> StepHandle<'_> {
+        let synchronous = match self.topology {
+            Topology::Resizable | Topology::Cylinder => true,
+            _ => self.is_tiled(),
+        };
+        if synchronous || n == 0 {
+            for _ in 0..n {
+                self.next_gen();
+            }
+
+            return StepHandle {
+                grid: self,
+                generations: 0,
+                new_cells: None,
+                finished: None,
+            };
+        }
+
+        self.clear_journal();
+        self.record_history();
+        let (new_cells, finished) = if self.is_bit_packed() {
+            self.submit_packed_generations(n)
+        } else {
+            self.submit_generations(n)
+        };
+
+        StepHandle {
+            grid: self,
+            generations: n,
+            new_cells: Some(new_cells),
+            finished: Some(finished),
+        }
+    }
+
+    /// Returns the parameters of the stochastic rules of the `i`th
+    /// generation after the current one.
+    fn stochastic_data(&self, i: u64) -> Arc<CpuAccessibleBuffer<[u32; 7]>> {
+        let stochastic = cpu::Stochastic {
+            generation: self.generation + i,
+            ..self.stochastic()
+        };
+
+        CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            stochastic.as_shader_data(),
+        )
+        .expect("failed to create buffer")
+    }
+
+    /// Submits `n` generations computed by the `ngs` shader, alternating
+    /// between two images.
+    fn submit_generations(&self, n: u64) -> (Cells, Receiver<()>) {
+        let new_image = || {
+            StorageImage::new(
+                self.device.clone(),
+                Dimensions::Dim2d {
+                    width: self.width as u32,
+                    height: self.height as u32,
+                },
+                Format::R8Unorm,
+                Some(self.queue.family()),
+            )
+            .expect("failed to create image")
+        };
+        let images = [new_image(), new_image()];
+
+        let new_cells = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..self.width * self.height).map(|_| 0u8),
+        )
+        .expect("failed to create buffer");
+
+        let topology = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            self.topology.as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let offset =
+            CpuAccessibleBuffer::from_data(self.device.clone(), BufferUsage::all(), [0i32, 0i32])
+                .expect("failed to create buffer");
+
+        // Every tile is computed
+        let tiles = all_tiles(self.width, self.height);
+        let tiles_per_row = self.width.div_ceil(ACTIVE_TILE_SIZE);
+        let mut active_data = vec![tiles_per_row as u32, tiles.len() as u32];
+        for &(x, y) in &tiles {
+            active_data.push(x as u32);
+            active_data.push(y as u32);
+        }
+        let active_tiles = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            active_data.into_iter(),
+        )
+        .expect("failed to create buffer");
+        let changed_tiles = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..tiles.len()).map(|_| 0u32),
+        )
+        .expect("failed to create buffer");
+
+        let shader =
+            ngs::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let mut builder = AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+            .unwrap()
+            .copy_buffer_to_image(self.byte_cells(), images[0].clone())
+            .unwrap();
+
+        for i in 0..n {
+            let (cells_in_img, cells_out_img) = if i % 2 == 0 {
+                (images[0].clone(), images[1].clone())
+            } else {
+                (images[1].clone(), images[0].clone())
+            };
+
+            let set = Arc::new(
+                PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                    .add_image(cells_in_img)
+                    .unwrap()
+                    .add_image(cells_out_img)
+                    .unwrap()
+                    .add_buffer(topology.clone())
+                    .unwrap()
+                    .add_buffer(self.survival.clone())
+                    .unwrap()
+                    .add_buffer(self.birth.clone())
+                    .unwrap()
+                    .add_buffer(self.stochastic_data(i))
+                    .unwrap()
+                    .add_buffer(offset.clone())
+                    .unwrap()
+                    .add_buffer(active_tiles.clone())
+                    .unwrap()
+                    .add_buffer(changed_tiles.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
+
+            builder = builder
+                .dispatch(
+                    [
+                        tiles.len().min(MAX_WORK_GROUPS) as u32,
+                        tiles.len().div_ceil(MAX_WORK_GROUPS) as u32,
+                        1,
+                    ],
+                    compute_pipeline.clone(),
+                    set,
+                    (),
+                )
+                .unwrap();
+        }
+
+        let command_buffer = builder
+            .copy_image_to_buffer(images[(n % 2) as usize].clone(), new_cells.clone())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let future = command_buffer
+            .execute(self.queue.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+
+        (Cells::Bytes(new_cells), wait_in_background(future))
+    }
+
+    /// Submits `n` generations of bit-packed cells computed by the
+    /// `ngps` shader, alternating between two buffers.
+    fn submit_packed_generations(&self, n: u64) -> (Cells, Receiver<()>) {
+        let words = match self.cells {
+            Cells::Packed(ref words) => words.clone(),
+            Cells::Bytes(_) => unreachable!(),
+        };
+        let words_per_row = self.width.div_ceil(32);
+
+        let new_buffer = || {
+            CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::all(),
+                (0..words_per_row * self.height).map(|_| 0u32),
+            )
+            .expect("failed to create buffer")
+        };
+        let buffers = [new_buffer(), new_buffer()];
+
+        let topology = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            self.topology.as_shader_data(),
+        )
+        .expect("failed to create buffer");
+
+        let size = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            [
+                self.width as i32,
+                self.height as i32,
+                words_per_row as i32,
+                0,
+            ],
+        )
+        .expect("failed to create buffer");
+
+        // No statistics are computed
+        let stats = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..3).map(|_| 0u32),
+        )
+        .expect("failed to create buffer");
+
+        let shader =
+            ngps::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let mut builder =
+            AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family()).unwrap();
+
+        for i in 0..n {
+            // The cells of the grid are only read by the first generation
+            let words_in = match i {
+                0 => words.clone(),
+                _ => buffers[((i + 1) % 2) as usize].clone(),
+            };
+            let words_out = buffers[(i % 2) as usize].clone();
+
+            let set = Arc::new(
+                PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                    .add_buffer(words_in)
+                    .unwrap()
+                    .add_buffer(words_out)
+                    .unwrap()
+                    .add_buffer(topology.clone())
+                    .unwrap()
+                    .add_buffer(self.survival.clone())
+                    .unwrap()
+                    .add_buffer(self.birth.clone())
+                    .unwrap()
+                    .add_buffer(self.stochastic_data(i))
+                    .unwrap()
+                    .add_buffer(size.clone())
+                    .unwrap()
+                    .add_buffer(stats.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
+
+            builder = builder
+                .dispatch(
+                    [
+                        words_per_row.div_ceil(8) as u32,
+                        self.height.div_ceil(8) as u32,
+                        1,
+                    ],
+                    compute_pipeline.clone(),
+                    set,
+                    (),
+                )
+                .unwrap();
+        }
+
+        let future = builder
+            .build()
+            .unwrap()
+            .execute(self.queue.clone())
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap();
+
+        (
+            Cells::Packed(buffers[((n + 1) % 2) as usize].clone()),
+            wait_in_background(future),
+        )
+    }
+}
+
+// Waits for `future` in another thread, so that the handle can be polled.
+// Returns a channel receiving a message once the GPU work is over.
+fn wait_in_background<F>(future: FenceSignalFuture<F>) -> Receiver<()>
+where
+    F: GpuFuture + Send + Sync + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = future.wait(None);
+        let _ = sender.send(());
+    });
+
+    receiver
+}
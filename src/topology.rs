@@ -0,0 +1,424 @@
+//! This module contains the topologies a grid can have, that is to say
+//! how its edges are joined.
+//!
+//! Bounded topologies are encoded as a suffix of the ruleset, as done by
+//! Golly: for example "23/3:T30,20" is Conway's Life on a 30x20 torus.
+
+use error::FileParsingErrorKind;
+
+/// Represents how the edges of a `Grid` are joined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    /// Unbounded plane: the grid is resized when computing the next
+    /// generation according to the size of the contained pattern.
+    Resizable,
+    /// Bounded plane (":P"): the cells beyond the edges are always dead.
+    Plane,
+    /// Torus (":T"): the opposite edges are joined.
+    ///
+    /// When crossing the top or bottom edge, a cell is moved by
+    /// `horizontal_shift` cells to the right for each crossing downwards
+    /// (and to the left upwards). `vertical_shift` works the same way
+    /// when crossing the left or right edge. Only one of them can be
+    /// different from 0.
+    Torus {
+        horizontal_shift: i64,
+        vertical_shift: i64,
+    },
+    /// Cylinder (":T<width>,0"): the left and right edges are joined, and
+    /// the grid is resized vertically according to the size of the
+    /// contained pattern.
+    Cylinder,
+    /// Klein bottle (":K"): the opposite edges are joined, one pair of them
+    /// with a twist. The top and bottom edges are twisted if
+    /// `horizontal_twist` is `true`, the left and right edges otherwise.
+    KleinBottle { horizontal_twist: bool },
+    /// Cross-surface (":C"): both pairs of opposite edges are joined with
+    /// a twist. The corner cells have no neighbors across the corners.
+    CrossSurface,
+    /// Sphere (":S"): the top edge is joined to the left edge and the
+    /// right edge to the bottom edge. The grid must be a square.
+    /// The corner cells have no neighbors across the corners.
+    Sphere,
+}
+
+impl Topology {
+    /// Returns a torus without any shift.
+    pub fn torus() -> Topology {
+        Topology::Torus {
+            horizontal_shift: 0,
+            vertical_shift: 0,
+        }
+    }
+
+    /// Returns `true` if the grid is resized in both directions when
+    /// computing the next generation.
+    pub fn is_resizable(&self) -> bool {
+        *self == Topology::Resizable
+    }
+
+    /// Returns the coordinates of the cell found at (`x`, `y`) on a
+    /// `width` x `height` grid with this topology, or `None` if there is
+    /// no such cell (it is always dead).
+    pub fn map(&self, x: i64, y: i64, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (w, h) = (width as i64, height as i64);
+
+        if w == 0 || h == 0 {
+            return None;
+        }
+        if x >= 0 && x < w && y >= 0 && y < h {
+            return Some((x as usize, y as usize));
+        }
+
+        // Number of times the edges are crossed in each direction
+        let (kx, ky) = (x.div_euclid(w), y.div_euclid(h));
+
+        match *self {
+            Topology::Resizable | Topology::Plane => None,
+            Topology::Torus {
+                horizontal_shift,
+                vertical_shift,
+            } => Some((
+                (x + ky * horizontal_shift).rem_euclid(w) as usize,
+                (y + kx * vertical_shift).rem_euclid(h) as usize,
+            )),
+            Topology::Cylinder => {
+                if ky != 0 {
+                    None
+                } else {
+                    Some((x.rem_euclid(w) as usize, y as usize))
+                }
+            }
+            Topology::KleinBottle { horizontal_twist } => {
+                let (mut x, mut y) = (x.rem_euclid(w), y.rem_euclid(h));
+                if horizontal_twist && ky % 2 != 0 {
+                    x = w - 1 - x;
+                }
+                if !horizontal_twist && kx % 2 != 0 {
+                    y = h - 1 - y;
+                }
+                Some((x as usize, y as usize))
+            }
+            Topology::CrossSurface => {
+                if kx != 0 && ky != 0 {
+                    return None;
+                }
+                let (mut x, mut y) = (x.rem_euclid(w), y.rem_euclid(h));
+                if ky % 2 != 0 {
+                    x = w - 1 - x;
+                }
+                if kx % 2 != 0 {
+                    y = h - 1 - y;
+                }
+                Some((x as usize, y as usize))
+            }
+            Topology::Sphere => {
+                if w != h {
+                    return None;
+                }
+                let (mut x, mut y) = (x, y);
+                loop {
+                    let out_x = x < 0 || x >= w;
+                    let out_y = y < 0 || y >= h;
+                    if out_x && out_y {
+                        return None;
+                    }
+                    if !out_x && !out_y {
+                        return Some((x as usize, y as usize));
+                    }
+
+                    // Each step brings the cell closer to the grid
+                    let (nx, ny) = if x < 0 {
+                        (y, -1 - x)
+                    } else if x >= w {
+                        (y, 2 * w - 1 - x)
+                    } else if y < 0 {
+                        (-1 - y, x)
+                    } else {
+                        (2 * h - 1 - y, x)
+                    };
+                    x = nx;
+                    y = ny;
+                }
+            }
+        }
+    }
+
+    /// Returns the suffix of the ruleset describing this topology for a
+    /// `width` x `height` grid, or an empty string if it is resizable.
+    pub fn to_suffix(&self, width: usize, height: usize) -> String {
+        match *self {
+            Topology::Resizable => String::new(),
+            Topology::Plane => format!(":P{},{}", width, height),
+            Topology::Torus {
+                horizontal_shift,
+                vertical_shift,
+            } => format!(
+                ":T{}{},{}{}",
+                width,
+                format_shift(horizontal_shift),
+                height,
+                format_shift(vertical_shift)
+            ),
+            Topology::Cylinder => format!(":T{},0", width),
+            Topology::KleinBottle { horizontal_twist } => {
+                if horizontal_twist {
+                    format!(":K{}*,{}", width, height)
+                } else {
+                    format!(":K{},{}*", width, height)
+                }
+            }
+            Topology::CrossSurface => format!(":C{},{}", width, height),
+            Topology::Sphere => format!(":S{}", width),
+        }
+    }
+
+    /// Parses a suffix of a ruleset (without the leading ':') and returns
+    /// the topology it describes along with the size of the grid.
+    /// A size of 0 means that the grid is unbounded in that direction.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FileParsingErrorKind::RuleParsingError` if the suffix
+    /// isn't valid or describes an unsupported topology.
+    pub fn from_suffix(suffix: &str) -> Result<(Topology, usize, usize), FileParsingErrorKind> {
+        let mut chars = suffix.chars();
+        let kind = chars.next().ok_or(FileParsingErrorKind::RuleParsingError)?;
+        let dims: Vec<Dimension> = chars
+            .as_str()
+            .split(',')
+            .map(parse_dimension)
+            .collect::<Result<_, _>>()?;
+
+        match (kind.to_ascii_uppercase(), dims.as_slice()) {
+            ('P', &[w, h]) if w.plain() && h.plain() && w.size > 0 && h.size > 0 => {
+                Ok((Topology::Plane, w.size, h.size))
+            }
+            ('T', &[w, h]) if !w.twisted && !h.twisted && w.size > 0 => {
+                if h.size == 0 && w.shift == 0 && h.shift == 0 {
+                    Ok((Topology::Cylinder, w.size, 0))
+                } else if h.size > 0 && (w.shift == 0 || h.shift == 0) {
+                    let torus = Topology::Torus {
+                        horizontal_shift: w.shift,
+                        vertical_shift: h.shift,
+                    };
+                    Ok((torus, w.size, h.size))
+                } else {
+                    Err(FileParsingErrorKind::RuleParsingError)
+                }
+            }
+            ('K', &[w, h])
+                if w.shift == 0
+                    && h.shift == 0
+                    && w.twisted != h.twisted
+                    && w.size > 0
+                    && h.size > 0 =>
+            {
+                let klein = Topology::KleinBottle {
+                    horizontal_twist: w.twisted,
+                };
+                Ok((klein, w.size, h.size))
+            }
+            ('C', &[w, h]) if w.plain() && h.plain() && w.size > 0 && h.size > 0 => {
+                Ok((Topology::CrossSurface, w.size, h.size))
+            }
+            ('S', &[w]) if w.plain() && w.size > 0 => Ok((Topology::Sphere, w.size, w.size)),
+            ('S', &[w, h]) if w.plain() && h.plain() && w.size > 0 && w.size == h.size => {
+                Ok((Topology::Sphere, w.size, h.size))
+            }
+            _ => Err(FileParsingErrorKind::RuleParsingError),
+        }
+    }
+
+    /// Returns the content of the `Topology` buffer of the `ngs` shader.
+    pub(crate) fn as_shader_data(&self) -> [i32; 4] {
+        match *self {
+            Topology::Resizable => [0, 0, 0, 0],
+            Topology::Plane => [1, 0, 0, 0],
+            Topology::Torus {
+                horizontal_shift,
+                vertical_shift,
+            } => [2, horizontal_shift as i32, vertical_shift as i32, 0],
+            Topology::Cylinder => [3, 0, 0, 0],
+            Topology::KleinBottle { horizontal_twist } => {
+                [4, 0, 0, if horizontal_twist { 1 } else { 2 }]
+            }
+            Topology::CrossSurface => [5, 0, 0, 3],
+            Topology::Sphere => [6, 0, 0, 0],
+        }
+    }
+}
+
+/// One of the two dimensions of a suffix, like "30", "30*" or "30+5".
+#[derive(Clone, Copy)]
+struct Dimension {
+    size: usize,
+    twisted: bool,
+    shift: i64,
+}
+
+impl Dimension {
+    fn plain(&self) -> bool {
+        !self.twisted && self.shift == 0
+    }
+}
+
+fn parse_dimension(dim: &str) -> Result<Dimension, FileParsingErrorKind> {
+    let dim = dim.trim();
+    let size_end = dim.find(|c: char| !c.is_ascii_digit()).unwrap_or(dim.len());
+    let size = dim[..size_end]
+        .parse::<usize>()
+        .map_err(|_| FileParsingErrorKind::RuleParsingError)?;
+
+    let mut rest = &dim[size_end..];
+    let twisted = rest.starts_with('*');
+    if twisted {
+        rest = &rest[1..];
+    }
+
+    let shift = if rest.is_empty() {
+        0
+    } else if rest.starts_with('+') || rest.starts_with('-') {
+        rest.parse::<i64>()
+            .map_err(|_| FileParsingErrorKind::RuleParsingError)?
+    } else {
+        return Err(FileParsingErrorKind::RuleParsingError);
+    };
+
+    Ok(Dimension {
+        size,
+        twisted,
+        shift,
+    })
+}
+
+fn format_shift(shift: i64) -> String {
+    if shift > 0 {
+        format!("+{}", shift)
+    } else if shift < 0 {
+        format!("{}", shift)
+    } else {
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Topology;
+
+    #[test]
+    fn test_map_torus() {
+        let torus = Topology::torus();
+        assert_eq!(Some((1, 2)), torus.map(1, 2, 4, 3));
+        assert_eq!(Some((3, 2)), torus.map(-1, -1, 4, 3));
+        assert_eq!(Some((0, 0)), torus.map(4, 3, 4, 3));
+
+        let shifted = Topology::Torus {
+            horizontal_shift: 1,
+            vertical_shift: 0,
+        };
+        assert_eq!(Some((2, 0)), shifted.map(1, 3, 4, 3));
+        assert_eq!(Some((0, 2)), shifted.map(1, -1, 4, 3));
+        assert_eq!(Some((0, 1)), shifted.map(4, 1, 4, 3));
+    }
+
+    #[test]
+    fn test_map_bounded() {
+        assert_eq!(None, Topology::Plane.map(-1, 0, 4, 3));
+        assert_eq!(None, Topology::Resizable.map(0, 3, 4, 3));
+        assert_eq!(Some((3, 1)), Topology::Cylinder.map(-1, 1, 4, 3));
+        assert_eq!(None, Topology::Cylinder.map(1, -1, 4, 3));
+    }
+
+    #[test]
+    fn test_map_twisted() {
+        let klein = Topology::KleinBottle {
+            horizontal_twist: true,
+        };
+        assert_eq!(Some((3, 2)), klein.map(0, -1, 4, 3));
+        assert_eq!(Some((0, 1)), klein.map(4, 1, 4, 3));
+
+        let klein = Topology::KleinBottle {
+            horizontal_twist: false,
+        };
+        assert_eq!(Some((3, 2)), klein.map(-1, 0, 4, 3));
+        assert_eq!(Some((1, 0)), klein.map(1, 3, 4, 3));
+
+        assert_eq!(Some((3, 2)), Topology::CrossSurface.map(0, -1, 4, 3));
+        assert_eq!(Some((3, 2)), Topology::CrossSurface.map(-1, 0, 4, 3));
+        assert_eq!(None, Topology::CrossSurface.map(-1, -1, 4, 3));
+    }
+
+    #[test]
+    fn test_map_sphere() {
+        let sphere = Topology::Sphere;
+        assert_eq!(Some((0, 2)), sphere.map(2, -1, 4, 4));
+        assert_eq!(Some((2, 0)), sphere.map(-1, 2, 4, 4));
+        assert_eq!(Some((1, 3)), sphere.map(4, 1, 4, 4));
+        assert_eq!(Some((3, 1)), sphere.map(1, 4, 4, 4));
+        assert_eq!(None, sphere.map(-1, -1, 4, 4));
+    }
+
+    #[test]
+    fn test_suffixes() {
+        let topologies = vec![
+            (Topology::Plane, 30, 20),
+            (Topology::torus(), 30, 20),
+            (
+                Topology::Torus {
+                    horizontal_shift: 5,
+                    vertical_shift: 0,
+                },
+                30,
+                20,
+            ),
+            (
+                Topology::Torus {
+                    horizontal_shift: 0,
+                    vertical_shift: -2,
+                },
+                30,
+                20,
+            ),
+            (Topology::Cylinder, 30, 0),
+            (
+                Topology::KleinBottle {
+                    horizontal_twist: true,
+                },
+                30,
+                20,
+            ),
+            (
+                Topology::KleinBottle {
+                    horizontal_twist: false,
+                },
+                30,
+                20,
+            ),
+            (Topology::CrossSurface, 30, 20),
+            (Topology::Sphere, 30, 30),
+        ];
+
+        for (topology, width, height) in topologies {
+            let suffix = topology.to_suffix(width, height);
+            assert_eq!(
+                (topology, width, height),
+                Topology::from_suffix(&suffix[1..]).unwrap()
+            );
+        }
+
+        assert_eq!(
+            ":T30+5,20",
+            &Topology::Torus {
+                horizontal_shift: 5,
+                vertical_shift: 0
+            }
+            .to_suffix(30, 20)
+        );
+        assert!(Topology::from_suffix("T30+1,20+1").is_err());
+        assert!(Topology::from_suffix("K30,20").is_err());
+        assert!(Topology::from_suffix("S30,20").is_err());
+        assert!(Topology::from_suffix("X30,20").is_err());
+        assert!(Topology::from_suffix("P30").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+//! This module contains the geometric transformations of a grid:
+//! rotations, flips, transposition and translation.
+//!
+//! The cells are moved by the `tfs` shader, or on the CPU when the grid
+//! is too large to be stored in a single image.
+
+use std::sync::Arc;
+
+use super::vulkano::buffer::BufferUsage;
+use super::vulkano::buffer::CpuAccessibleBuffer;
+use super::vulkano::command_buffer::AutoCommandBufferBuilder;
+use super::vulkano::command_buffer::CommandBuffer;
+use super::vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use super::vulkano::format::Format;
+use super::vulkano::image::Dimensions;
+use super::vulkano::image::StorageImage;
+use super::vulkano::pipeline::ComputePipeline;
+use super::vulkano::sync::GpuFuture;
+
+use super::vulkan::tfs;
+use error::GridErrorKind;
+use topology::Topology;
+use Grid;
+
+/// Maps the coordinates of a cell of the transformed grid to the
+/// coordinates of the cell it is copied from:
+/// `(m00 * x + m01 * y + offset_x, m10 * x + m11 * y + offset_y)`.
+#[derive(Clone, Copy, Debug)]
+struct Affine {
+    matrix: [i64; 4],
+    offset: (i64, i64),
+}
+
+impl Affine {
+    fn apply(&self, x: i64, y: i64) -> (i64, i64) {
+        let m = self.matrix;
+        (
+            m[0] * x + m[1] * y + self.offset.0,
+            m[2] * x + m[3] * y + self.offset.1,
+        )
+    }
+
+    // Returns the content of the `Transform` buffer of the `tfs` shader
+    fn as_shader_data(&self, wrap: bool) -> [i32; 7] {
+        let m = self.matrix;
+        [
+            m[0] as i32,
+            m[1] as i32,
+            m[2] as i32,
+            m[3] as i32,
+            self.offset.0 as i32,
+            self.offset.1 as i32,
+            wrap as i32,
+        ]
+    }
+}
+
+impl Grid {
+    /// Rotates the current `Grid` by 90 degrees clockwise. Its width and
+    /// height are swapped.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` if the rotated
+    /// edges can't be joined the same way, like on a sphere or a cylinder.
+    pub fn rotate_cw(&mut self) -> Result<(), GridErrorKind> {
+        let topology = rotated_topology(self.topology)?;
+        let affine = Affine {
+            matrix: [0, 1, -1, 0],
+            offset: (0, self.height as i64 - 1),
+        };
+
+        let size = (self.height, self.width);
+        self.journaled(|grid| grid.transform(affine, size, topology));
+        Ok(())
+    }
+
+    /// Rotates the current `Grid` by 90 degrees counterclockwise. Its
+    /// width and height are swapped.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` if the rotated
+    /// edges can't be joined the same way, like on a sphere or a cylinder.
+    pub fn rotate_ccw(&mut self) -> Result<(), GridErrorKind> {
+        let topology = rotated_topology(self.topology)?;
+        let affine = Affine {
+            matrix: [0, -1, 1, 0],
+            offset: (self.width as i64 - 1, 0),
+        };
+
+        let size = (self.height, self.width);
+        self.journaled(|grid| grid.transform(affine, size, topology));
+        Ok(())
+    }
+
+    /// Mirrors the current `Grid` so that its left and right edges
+    /// are swapped.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` on a sphere.
+    pub fn flip_horizontal(&mut self) -> Result<(), GridErrorKind> {
+        let topology = flipped_topology(self.topology)?;
+        let affine = Affine {
+            matrix: [-1, 0, 0, 1],
+            offset: (self.width as i64 - 1, 0),
+        };
+
+        let size = (self.width, self.height);
+        self.journaled(|grid| grid.transform(affine, size, topology));
+        Ok(())
+    }
+
+    /// Mirrors the current `Grid` so that its top and bottom edges
+    /// are swapped.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` on a sphere.
+    pub fn flip_vertical(&mut self) -> Result<(), GridErrorKind> {
+        let topology = flipped_topology(self.topology)?;
+        let affine = Affine {
+            matrix: [1, 0, 0, -1],
+            offset: (0, self.height as i64 - 1),
+        };
+
+        let size = (self.width, self.height);
+        self.journaled(|grid| grid.transform(affine, size, topology));
+        Ok(())
+    }
+
+    /// Mirrors the current `Grid` along its diagonal going from its north
+    /// west corner: the cell at (`x`, `y`) is moved to (`y`, `x`). Its
+    /// width and height are swapped.
+    ///
+    /// Returns `Err(GridErrorKind::UnsupportedTopology)` on a cylinder.
+    pub fn transpose(&mut self) -> Result<(), GridErrorKind> {
+        let topology = transposed_topology(self.topology)?;
+        let affine = Affine {
+            matrix: [0, 1, 1, 0],
+            offset: (0, 0),
+        };
+
+        let size = (self.height, self.width);
+        self.journaled(|grid| grid.transform(affine, size, topology));
+        Ok(())
+    }
+
+    /// Moves the cells by `dx` cells to the right and `dy` cells
+    /// downwards.
+    ///
+    /// The cells crossing the edges are moved according to the topology.
+    /// A resizable grid grows so that no cell is lost, and a cylindrical
+    /// grid grows vertically. On a plane, the cells moved beyond the edges
+    /// are lost.
+    pub fn shift(&mut self, dx: i64, dy: i64) {
+        self.journaled(|grid| grid.move_cells(dx, dy));
+    }
+
+    fn move_cells(&mut self, dx: i64, dy: i64) {
+        let (dx, dy) = match self.topology {
+            Topology::Resizable => {
+                self.grow_by(dx, dy);
+                return;
+            }
+            Topology::Cylinder => {
+                self.grow_by(0, dy);
+                (dx, 0)
+            }
+            _ => (dx, dy),
+        };
+
+        let affine = Affine {
+            matrix: [1, 0, 0, 1],
+            offset: (-dx, -dy),
+        };
+        let size = (self.width, self.height);
+
+        // Only the plane and the torus without shift are handled by the
+        // shader, the other topologies can move the cells crossing the edges
+        match self.topology {
+            Topology::Plane => self.transform(affine, size, Topology::Plane),
+            topology if topology == Topology::torus() => self.transform(affine, size, topology),
+            topology => self.transform_cpu(affine, size, topology),
+        }
+    }
+
+    // Grows the grid by `dx` columns and `dy` rows, on the left and top
+    // when they are positive and on the right and bottom otherwise
+    fn grow_by(&mut self, dx: i64, dy: i64) {
+        let size = (self.width, self.height);
+
+        self.reframe(
+            (0, 0),
+            size,
+            (
+                size.0 + dx.unsigned_abs() as usize,
+                size.1 + dy.unsigned_abs() as usize,
+            ),
+            (dx.max(0) as usize, dy.max(0) as usize),
+        );
+    }
+
+    // Replaces the cells by a `new_size` grid whose cells are copied from
+    // the cells given by `affine`, and sets the topology to `topology`
+    fn transform(&mut self, affine: Affine, new_size: (usize, usize), topology: Topology) {
+        let max_tile_size = self.get_max_tile_size();
+        if self.is_tiled() || new_size.0 > max_tile_size || new_size.1 > max_tile_size {
+            self.transform_cpu(affine, new_size, topology);
+            return;
+        }
+        if self.width == 0 || self.height == 0 {
+            self.width = new_size.0;
+            self.height = new_size.1;
+            self.topology = topology;
+            return;
+        }
+
+        let cells_in_img = StorageImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d {
+                width: self.width as u32,
+                height: self.height as u32,
+            },
+            Format::R8Unorm,
+            Some(self.queue.family()),
+        )
+        .expect("failed to create image");
+
+        let cells_out_img = StorageImage::new(
+            self.device.clone(),
+            Dimensions::Dim2d {
+                width: new_size.0 as u32,
+                height: new_size.1 as u32,
+            },
+            Format::R8Unorm,
+            Some(self.queue.family()),
+        )
+        .expect("failed to create image");
+
+        let transformed_buff = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            (0..new_size.0 * new_size.1).map(|_| 0u8),
+        )
+        .expect("failed to create buffer");
+
+        // Only the torus without shift wraps around its edges this way
+        let wrap = self.topology == Topology::torus();
+        let transform = CpuAccessibleBuffer::from_data(
+            self.device.clone(),
+            BufferUsage::all(),
+            affine.as_shader_data(wrap),
+        )
+        .expect("failed to create buffer");
+
+        let shader =
+            tfs::Shader::load(self.device.clone()).expect("failed to create shader module");
+        let compute_pipeline = Arc::new(
+            ComputePipeline::new(self.device.clone(), &shader.main_entry_point(), &())
+                .expect("failed to create compute pipeline"),
+        );
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(compute_pipeline.clone(), 0)
+                .add_image(cells_in_img.clone())
+                .unwrap()
+                .add_image(cells_out_img.clone())
+                .unwrap()
+                .add_buffer(transform.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        );
+
+        let command_buffer =
+            AutoCommandBufferBuilder::new(self.device.clone(), self.queue.family())
+                .unwrap()
+                .copy_buffer_to_image(self.byte_cells(), cells_in_img.clone())
+                .unwrap()
+                .dispatch(
+                    [
+                        new_size.0.div_ceil(8) as u32,
+                        new_size.1.div_ceil(8) as u32,
+                        1,
+                    ],
+                    compute_pipeline.clone(),
+                    set.clone(),
+                    (),
+                )
+                .unwrap()
+                .copy_image_to_buffer(cells_out_img.clone(), transformed_buff.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+
+        let finished = command_buffer.execute(self.queue.clone()).unwrap();
+        finished
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+
+        self.width = new_size.0;
+        self.height = new_size.1;
+        self.topology = topology;
+        self.set_byte_cells(transformed_buff);
+    }
+
+    // Works like `transform`, on the CPU. The cells beyond the edges are
+    // found according to the topology of the grid.
+    fn transform_cpu(&mut self, affine: Affine, new_size: (usize, usize), topology: Topology) {
+        let transformed: Vec<u8> = {
+            let cells = self.cells();
+            (0..new_size.0 * new_size.1)
+                .map(|i| {
+                    let (x, y) = affine.apply((i % new_size.0) as i64, (i / new_size.0) as i64);
+                    match self.topology.map(x, y, self.width, self.height) {
+                        Some((x, y)) => cells[y * self.width + x],
+                        None => 0,
+                    }
+                })
+                .collect()
+        };
+
+        let transformed_buff = CpuAccessibleBuffer::from_iter(
+            self.device.clone(),
+            BufferUsage::all(),
+            transformed.into_iter(),
+        )
+        .expect("failed to create buffer");
+
+        self.width = new_size.0;
+        self.height = new_size.1;
+        self.topology = topology;
+        self.set_byte_cells(transformed_buff);
+    }
+}
+
+// Returns the topology of a grid rotated by 90 degrees
+fn rotated_topology(topology: Topology) -> Result<Topology, GridErrorKind> {
+    match topology {
+        Topology::Torus {
+            horizontal_shift,
+            vertical_shift,
+        } => Ok(Topology::Torus {
+            horizontal_shift: -vertical_shift,
+            vertical_shift: -horizontal_shift,
+        }),
+        Topology::KleinBottle { horizontal_twist } => Ok(Topology::KleinBottle {
+            horizontal_twist: !horizontal_twist,
+        }),
+        Topology::Cylinder | Topology::Sphere => Err(GridErrorKind::UnsupportedTopology),
+        topology => Ok(topology),
+    }
+}
+
+// Returns the topology of a transposed grid: the shifts are swapped
+// without being negated, since a rotation is a transposition followed by
+// a flip
+fn transposed_topology(topology: Topology) -> Result<Topology, GridErrorKind> {
+    match topology {
+        Topology::Torus {
+            horizontal_shift,
+            vertical_shift,
+        } => Ok(Topology::Torus {
+            horizontal_shift: vertical_shift,
+            vertical_shift: horizontal_shift,
+        }),
+        Topology::KleinBottle { horizontal_twist } => Ok(Topology::KleinBottle {
+            horizontal_twist: !horizontal_twist,
+        }),
+        Topology::Cylinder => Err(GridErrorKind::UnsupportedTopology),
+        topology => Ok(topology),
+    }
+}
+
+// Returns the topology of a flipped grid
+fn flipped_topology(topology: Topology) -> Result<Topology, GridErrorKind> {
+    match topology {
+        Topology::Torus {
+            horizontal_shift,
+            vertical_shift,
+        } => Ok(Topology::Torus {
+            horizontal_shift: -horizontal_shift,
+            vertical_shift: -vertical_shift,
+        }),
+        Topology::Sphere => Err(GridErrorKind::UnsupportedTopology),
+        topology => Ok(topology),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{flipped_topology, rotated_topology, transposed_topology};
+
+    use topology::Topology;
+
+    #[test]
+    fn test_transformed_topologies() {
+        let torus = Topology::Torus {
+            horizontal_shift: 3,
+            vertical_shift: 0,
+        };
+
+        // Two rotations by 90 degrees or two flips give back the topology
+        let rotated = rotated_topology(torus).unwrap();
+        assert_eq!(
+            Topology::Torus {
+                horizontal_shift: 0,
+                vertical_shift: -3,
+            },
+            rotated
+        );
+        assert_eq!(torus, rotated_topology(rotated).unwrap());
+        assert_eq!(
+            torus,
+            flipped_topology(flipped_topology(torus).unwrap()).unwrap()
+        );
+
+        // A transposition swaps the shifts, and is a rotation followed by
+        // a flip
+        let transposed = transposed_topology(Topology::Torus {
+            horizontal_shift: 1,
+            vertical_shift: 0,
+        })
+        .unwrap();
+        assert_eq!(
+            Topology::Torus {
+                horizontal_shift: 0,
+                vertical_shift: 1,
+            },
+            transposed
+        );
+        assert_eq!(
+            transposed_topology(torus).unwrap(),
+            flipped_topology(rotated_topology(torus).unwrap()).unwrap()
+        );
+        assert_eq!(
+            Topology::Sphere,
+            transposed_topology(Topology::Sphere).unwrap()
+        );
+
+        assert!(rotated_topology(Topology::Cylinder).is_err());
+        assert!(transposed_topology(Topology::Cylinder).is_err());
+        assert!(flipped_topology(Topology::Sphere).is_err());
+        assert_eq!(
+            Ok(Topology::Cylinder),
+            flipped_topology(Topology::Cylinder).map_err(|_| ())
+        );
+    }
+}
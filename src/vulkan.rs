@@ -17,10 +17,24 @@ pub fn vk_init() -> (Arc<Device>, Arc<Queue>) {
         .find(|&dev| dev.ty() == DiscreteGpu)
         .expect("no discrete GPU available");
 
-    let queue_family = physical
-        .queue_families()
-        .find(|&q| q.supports_graphics())
-        .expect("couldn't find a graphical queue family");
+    create_device(physical).expect("failed to create device")
+}
+
+/// Returns a device and a queue for every physical device which can
+/// compute the generations, whatever its type (e.g. software devices).
+pub fn vk_init_all() -> Vec<(Arc<Device>, Arc<Queue>)> {
+    let instance =
+        Instance::new(None, &InstanceExtensions::none(), None).expect("failed to create instance");
+
+    PhysicalDevice::enumerate(&instance)
+        .filter_map(create_device)
+        .collect()
+}
+
+// Returns `None` if the physical device has no graphical queue family or
+// lacks the features needed by the shaders
+fn create_device(physical: PhysicalDevice) -> Option<(Arc<Device>, Arc<Queue>)> {
+    let queue_family = physical.queue_families().find(|&q| q.supports_graphics())?;
 
     let img_extended_formats_feature = Features {
         shader_storage_image_extended_formats: true,
@@ -33,11 +47,11 @@ pub fn vk_init() -> (Arc<Device>, Arc<Queue>) {
         &DeviceExtensions::none(),
         [(queue_family, 0.5)].iter().cloned(),
     )
-    .expect("failed to create device");
+    .ok()?;
 
     let queue = queues.next().unwrap();
 
-    (device, queue)
+    Some((device, queue))
 }
 
 pub mod ngs {
@@ -52,9 +66,12 @@ pub mod ngs {
 
     layout(set = 0, binding = 1, r8) uniform writeonly image2D img_out;
 
-    layout(set = 0, binding = 2) buffer Toroidal {
-        int opt;
-    } tor;
+    layout(set = 0, binding = 2) buffer Topology {
+        int kind;
+        int horizontal_shift;
+        int vertical_shift;
+        int twist;
+    } topo;
 
     layout(set = 0, binding = 3) buffer Survival {
         uint rules[];
@@ -64,32 +81,147 @@ pub mod ngs {
         uint rules[];
     } brth;
 
+    layout(set = 0, binding = 5) buffer Stochastic {
+        uint birth;
+        uint survival;
+        uint update;
+        uint seed_lo;
+        uint seed_hi;
+        uint gen_lo;
+        uint gen_hi;
+    } stoch;
+
+    // Coordinates of the north west corner of the image in the grid,
+    // when the grid is split into tiles
+    layout(set = 0, binding = 6) buffer Offset {
+        int x;
+        int y;
+    } offset;
+
+    // Each work group computes the 8x8 tile whose coordinates are
+    // given by `tiles`
+    layout(set = 0, binding = 7) buffer ActiveTiles {
+        uint tiles_per_row;
+        uint count;
+        uvec2 tiles[];
+    } active_tiles;
+
+    // Set to 1 for each tile in which a cell changed
+    layout(set = 0, binding = 8) buffer ChangedTiles {
+        uint tiles[];
+    } changed_tiles;
+
+    // Must be kept in sync with `cpu::hash`
+    uint hash(uint x) {
+        x ^= x >> 16;
+        x *= 0x7feb352dU;
+        x ^= x >> 15;
+        x *= 0x846ca68bU;
+        x ^= x >> 16;
+        return x;
+    }
+
+    // Must be kept in sync with `cpu::random`
+    uint random(uvec2 coord, uint stream) {
+        uint h = hash(stream);
+        h = hash(coord.y ^ h);
+        h = hash(coord.x ^ h);
+        h = hash(stoch.gen_hi ^ h);
+        h = hash(stoch.gen_lo ^ h);
+        h = hash(stoch.seed_hi ^ h);
+        return hash(stoch.seed_lo ^ h);
+    }
+
+    bool chance(uint r, uint threshold) {
+        return threshold == 0xffffffffU || r < threshold;
+    }
+
+    const int RESIZABLE = 0;
+    const int PLANE = 1;
+    const int TORUS = 2;
+    const int CYLINDER = 3;
+    const int KLEIN_BOTTLE = 4;
+    const int CROSS_SURFACE = 5;
+    const int SPHERE = 6;
+
+    int floor_div(int a, int b) {
+        return (a >= 0) ? a / b : -((-a + b - 1) / b);
+    }
+
+    int floor_mod(int a, int b) {
+        return a - b * floor_div(a, b);
+    }
+
+    // Must be kept in sync with `Topology::map`
+    // Returns false if there is no cell at `coord`
+    bool map_coord(inout ivec2 coord, ivec2 size) {
+        if (coord.x >= 0 && coord.x < size.x && coord.y >= 0 && coord.y < size.y) {
+            return true;
+        }
+
+        int kx = floor_div(coord.x, size.x);
+        int ky = floor_div(coord.y, size.y);
+
+        if (topo.kind == TORUS) {
+            coord = ivec2(floor_mod(coord.x + ky * topo.horizontal_shift, size.x),
+                          floor_mod(coord.y + kx * topo.vertical_shift, size.y));
+            return true;
+        } else if (topo.kind == CYLINDER) {
+            coord.x = floor_mod(coord.x, size.x);
+            return ky == 0;
+        } else if (topo.kind == KLEIN_BOTTLE || topo.kind == CROSS_SURFACE) {
+            if (topo.kind == CROSS_SURFACE && kx != 0 && ky != 0) {
+                return false;
+            }
+            coord = ivec2(floor_mod(coord.x, size.x), floor_mod(coord.y, size.y));
+            if ((topo.twist & 1) != 0 && (ky & 1) != 0) {
+                coord.x = size.x - 1 - coord.x;
+            }
+            if ((topo.twist & 2) != 0 && (kx & 1) != 0) {
+                coord.y = size.y - 1 - coord.y;
+            }
+            return true;
+        } else if (topo.kind == SPHERE) {
+            // The neighbors are at most one cell away from the grid
+            if (size.x != size.y || (kx != 0 && ky != 0)) {
+                return false;
+            }
+            if (coord.x < 0) {
+                coord = ivec2(coord.y, -1 - coord.x);
+            } else if (coord.x >= size.x) {
+                coord = ivec2(coord.y, 2 * size.x - 1 - coord.x);
+            } else if (coord.y < 0) {
+                coord = ivec2(-1 - coord.y, coord.x);
+            } else {
+                coord = ivec2(2 * size.y - 1 - coord.y, coord.x);
+            }
+            return true;
+        }
+
+        // Resizable grids and bounded planes
+        return false;
+    }
+
     void main() {
         ivec2 offsets[8] = { ivec2(-1, -1), ivec2(0, -1), ivec2(1, -1), ivec2(-1, 0), ivec2(1, 0),
                              ivec2(-1, 1), ivec2(0, 1), ivec2(1, 1) };
         ivec2 grid_size = imageSize(img_in);
         int living_neighbors = 0;
 
-        for (int i = 0; i < 8; i++) {
-            ivec2 access_coord = ivec2(gl_GlobalInvocationID.xy) + offsets[i];
+        uint index = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+        if (index >= active_tiles.count) {
+            return;
+        }
+        uvec2 tile = active_tiles.tiles[index];
+        ivec2 cell = ivec2(tile * 8 + gl_LocalInvocationID.xy);
+        if (cell.x >= grid_size.x || cell.y >= grid_size.y) {
+            return;
+        }
 
-            if (tor.opt != 0) {
-                if (access_coord.x == -1) {
-                    access_coord.x = grid_size.x - 1;
-                }
-                if (access_coord.y == -1) {
-                    access_coord.y = grid_size.y - 1;
-                }
-                if (access_coord.x == grid_size.x) {
-                    access_coord.x = 0;
-                }
-                if (access_coord.y == grid_size.y) {
-                    access_coord.y = 0;
-                }
-            }
+        for (int i = 0; i < 8; i++) {
+            ivec2 access_coord = cell + offsets[i];
 
-            if (access_coord.x >= 0 && access_coord.x < grid_size.x && access_coord.y >= 0 &&
-                access_coord.y < grid_size.y) {
+            if (map_coord(access_coord, grid_size)) {
                 if (imageLoad(img_in, access_coord).x == 1.0) {
                     living_neighbors++;
                 }
@@ -97,22 +229,315 @@ pub mod ngs {
         }
 
         vec4 to_write = vec4(0.0);
+        bool alive = imageLoad(img_in, cell).x == 1.0;
+        uvec2 coord = uvec2(cell + ivec2(offset.x, offset.y));
 
-        if (imageLoad(img_in, ivec2(gl_GlobalInvocationID.xy)).x == 1.0) {
+        if (!chance(random(coord, 1), stoch.update)) {
+            // The cell isn't updated during this generation
+            to_write.x = alive ? 1.0 : 0.0;
+        } else if (alive) {
             for (int i = 0; i < srvl.rules.length(); i++) {
                 if (living_neighbors == srvl.rules[i]) {
                     to_write.x = 1.0;
                 }
             }
+            if (!chance(random(coord, 0), stoch.survival)) {
+                to_write.x = 0.0;
+            }
         } else {
             for (int i = 0; i < brth.rules.length(); i++) {
                 if (living_neighbors == brth.rules[i]) {
                     to_write.x = 1.0;
                 }
             }
+            if (!chance(random(coord, 0), stoch.birth)) {
+                to_write.x = 0.0;
+            }
+        }
+
+        imageStore(img_out, cell, to_write);
+        if ((to_write.x == 1.0) != alive) {
+            changed_tiles.tiles[tile.y * active_tiles.tiles_per_row + tile.x] = 1;
+        }
+    }
+    "]
+    struct Dummy;
+}
+
+pub mod ngps {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+    #version 450
+
+    layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+    // 32 cells per word, each row starting on a new word.
+    // Several grids of the same size can be stored one after the other,
+    // the grid being selected by the z coordinate of the invocation.
+    layout(set = 0, binding = 0) readonly buffer CellsIn {
+        uint words[];
+    } cells_in;
+
+    layout(set = 0, binding = 1) buffer CellsOut {
+        uint words[];
+    } cells_out;
+
+    layout(set = 0, binding = 2) buffer Topology {
+        int kind;
+        int horizontal_shift;
+        int vertical_shift;
+        int twist;
+    } topo;
+
+    layout(set = 0, binding = 3) buffer Survival {
+        uint rules[];
+    } srvl;
+
+    layout(set = 0, binding = 4) buffer Birth {
+        uint rules[];
+    } brth;
+
+    layout(set = 0, binding = 5) buffer Stochastic {
+        uint birth;
+        uint survival;
+        uint update;
+        uint seed_lo;
+        uint seed_hi;
+        uint gen_lo;
+        uint gen_hi;
+    } stoch;
+
+    // `stats` tells which statistics are computed, see `STATS_*`
+    layout(set = 0, binding = 6) buffer Size {
+        int width;
+        int height;
+        int words_per_row;
+        int stats;
+    } size;
+
+    // For each grid: its population, whether a cell changed, and whether
+    // a cell differs from the previous content of `cells_out`
+    layout(set = 0, binding = 7) buffer Stats {
+        uint data[];
+    } stats;
+
+    // Must be kept in sync with `processing::STATS_*`
+    const int STATS_POPULATION = 1;
+    const int STATS_CHANGES = 2;
+
+    // Index of the first word of the grid computed by this invocation
+    int base;
+
+    // Must be kept in sync with `cpu::hash`
+    uint hash(uint x) {
+        x ^= x >> 16;
+        x *= 0x7feb352dU;
+        x ^= x >> 15;
+        x *= 0x846ca68bU;
+        x ^= x >> 16;
+        return x;
+    }
+
+    // Must be kept in sync with `cpu::random`
+    uint random(uvec2 coord, uint stream) {
+        uint h = hash(stream);
+        h = hash(coord.y ^ h);
+        h = hash(coord.x ^ h);
+        h = hash(stoch.gen_hi ^ h);
+        h = hash(stoch.gen_lo ^ h);
+        h = hash(stoch.seed_hi ^ h);
+        return hash(stoch.seed_lo ^ h);
+    }
+
+    bool chance(uint r, uint threshold) {
+        return threshold == 0xffffffffU || r < threshold;
+    }
+
+    const int RESIZABLE = 0;
+    const int PLANE = 1;
+    const int TORUS = 2;
+    const int CYLINDER = 3;
+    const int KLEIN_BOTTLE = 4;
+    const int CROSS_SURFACE = 5;
+    const int SPHERE = 6;
+
+    int floor_div(int a, int b) {
+        return (a >= 0) ? a / b : -((-a + b - 1) / b);
+    }
+
+    int floor_mod(int a, int b) {
+        return a - b * floor_div(a, b);
+    }
+
+    // Must be kept in sync with `Topology::map` and the `ngs` shader
+    bool map_coord(inout ivec2 coord, ivec2 size) {
+        if (coord.x >= 0 && coord.x < size.x && coord.y >= 0 && coord.y < size.y) {
+            return true;
+        }
+
+        int kx = floor_div(coord.x, size.x);
+        int ky = floor_div(coord.y, size.y);
+
+        if (topo.kind == TORUS) {
+            coord = ivec2(floor_mod(coord.x + ky * topo.horizontal_shift, size.x),
+                          floor_mod(coord.y + kx * topo.vertical_shift, size.y));
+            return true;
+        } else if (topo.kind == CYLINDER) {
+            coord.x = floor_mod(coord.x, size.x);
+            return ky == 0;
+        } else if (topo.kind == KLEIN_BOTTLE || topo.kind == CROSS_SURFACE) {
+            if (topo.kind == CROSS_SURFACE && kx != 0 && ky != 0) {
+                return false;
+            }
+            coord = ivec2(floor_mod(coord.x, size.x), floor_mod(coord.y, size.y));
+            if ((topo.twist & 1) != 0 && (ky & 1) != 0) {
+                coord.x = size.x - 1 - coord.x;
+            }
+            if ((topo.twist & 2) != 0 && (kx & 1) != 0) {
+                coord.y = size.y - 1 - coord.y;
+            }
+            return true;
+        } else if (topo.kind == SPHERE) {
+            if (size.x != size.y || (kx != 0 && ky != 0)) {
+                return false;
+            }
+            if (coord.x < 0) {
+                coord = ivec2(coord.y, -1 - coord.x);
+            } else if (coord.x >= size.x) {
+                coord = ivec2(coord.y, 2 * size.x - 1 - coord.x);
+            } else if (coord.y < 0) {
+                coord = ivec2(-1 - coord.y, coord.x);
+            } else {
+                coord = ivec2(2 * size.y - 1 - coord.y, coord.x);
+            }
+            return true;
+        }
+
+        return false;
+    }
+
+    uint get_cell(ivec2 coord) {
+        if (!map_coord(coord, ivec2(size.width, size.height))) {
+            return 0;
+        }
+        return (cells_in.words[base + coord.y * size.words_per_row + coord.x / 32] >> (coord.x % 32)) & 1;
+    }
+
+    // Returns the word `wx` of the row `y`, which can be beyond the edges
+    uint load_word(int wx, int y) {
+        if (y >= 0 && y < size.height) {
+            return cells_in.words[base + y * size.words_per_row + wx];
+        }
+
+        uint word = 0;
+        for (int i = 0; i < 32 && wx * 32 + i < size.width; i++) {
+            word |= get_cell(ivec2(wx * 32 + i, y)) << i;
+        }
+        return word;
+    }
+
+    // Returns the cells on the left, the cells themselves and the cells
+    // on the right of the word `wx` of the row `y`
+    uvec3 load_row(int wx, int y) {
+        uint center = load_word(wx, y);
+        uint west = get_cell(ivec2(wx * 32 - 1, y));
+        uint east = get_cell(ivec2(min(wx * 32 + 32, size.width), y));
+        int last = min(31, size.width - 1 - wx * 32);
+
+        return uvec3((center << 1) | west, center, (center >> 1) | (east << last));
+    }
+
+    // Must be kept in sync with `cpu::next_word`
+    uint next_word(uvec3 above, uvec3 row, uvec3 below, uint survival, uint birth) {
+        uint sum_a = above.x ^ above.y ^ above.z;
+        uint carry_a = (above.x & above.y) | (above.z & (above.x ^ above.y));
+        uint sum_b = below.x ^ below.y ^ below.z;
+        uint carry_b = (below.x & below.y) | (below.z & (below.x ^ below.y));
+        uint sum_c = row.x ^ row.z;
+        uint carry_c = row.x & row.z;
+
+        uint bit0 = sum_a ^ sum_b ^ sum_c;
+        uint carry_0 = (sum_a & sum_b) | (sum_c & (sum_a ^ sum_b));
+        uint twos = carry_a ^ carry_b ^ carry_c;
+        uint carry_1 = (carry_a & carry_b) | (carry_c & (carry_a ^ carry_b));
+        uint bit1 = twos ^ carry_0;
+        uint carry_2 = twos & carry_0;
+        uint bit2 = carry_1 ^ carry_2;
+        uint bit3 = carry_1 & carry_2;
+
+        uint survives = 0;
+        uint born = 0;
+        for (uint n = 0; n < 9; n++) {
+            uint count_is = ((n & 1) != 0 ? bit0 : ~bit0) & ((n & 2) != 0 ? bit1 : ~bit1)
+                & ((n & 4) != 0 ? bit2 : ~bit2) & ((n & 8) != 0 ? bit3 : ~bit3);
+            if ((survival & (1 << n)) != 0) {
+                survives |= count_is;
+            }
+            if ((birth & (1 << n)) != 0) {
+                born |= count_is;
+            }
+        }
+
+        return (row.y & survives) | (~row.y & born);
+    }
+
+    void main() {
+        int wx = int(gl_GlobalInvocationID.x);
+        int y = int(gl_GlobalInvocationID.y);
+        if (wx >= size.words_per_row || y >= size.height) {
+            return;
+        }
+        uint grid = gl_GlobalInvocationID.z;
+        base = int(grid) * size.words_per_row * size.height;
+
+        uint survival = 0;
+        for (int i = 0; i < srvl.rules.length(); i++) {
+            survival |= 1 << srvl.rules[i];
+        }
+        uint birth = 0;
+        for (int i = 0; i < brth.rules.length(); i++) {
+            birth |= 1 << brth.rules[i];
+        }
+
+        uvec3 row = load_row(wx, y);
+        uint next = next_word(load_row(wx, y - 1), row, load_row(wx, y + 1), survival, birth);
+
+        // The stochastic rules are applied cell by cell
+        if (stoch.birth != 0xffffffffU || stoch.survival != 0xffffffffU
+                || stoch.update != 0xffffffffU) {
+            for (int i = 0; i < 32 && wx * 32 + i < size.width; i++) {
+                uvec2 coord = uvec2(wx * 32 + i, y);
+                uint alive = (row.y >> i) & 1;
+
+                if (!chance(random(coord, 1), stoch.update)) {
+                    next = (next & ~(1 << i)) | (alive << i);
+                } else if (!chance(random(coord, 0), alive == 1 ? stoch.survival : stoch.birth)) {
+                    next &= ~(1 << i);
+                }
+            }
+        }
+
+        int bits = size.width - wx * 32;
+        if (bits < 32) {
+            next &= (1u << bits) - 1;
+        }
+
+        int index = base + y * size.words_per_row + wx;
+        if ((size.stats & STATS_POPULATION) != 0) {
+            atomicAdd(stats.data[3 * grid], bitCount(next));
+        }
+        // `cells_out` is only read for these statistics
+        if ((size.stats & STATS_CHANGES) != 0) {
+            if (next != row.y) {
+                stats.data[3 * grid + 1] = 1;
+            }
+            if (next != cells_out.words[index]) {
+                stats.data[3 * grid + 2] = 1;
+            }
         }
 
-        imageStore(img_out, ivec2(gl_GlobalInvocationID.xy), to_write);
+        cells_out.words[index] = next;
     }
     "]
     struct Dummy;
@@ -147,3 +572,175 @@ pub mod fms {
     "]
     struct Dummy;
 }
+
+pub mod tfs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+    #version 450
+
+    layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+    layout(set = 0, binding = 0, r8) uniform readonly image2D img_in;
+
+    layout(set = 0, binding = 1, r8) uniform writeonly image2D img_out;
+
+    // Each cell of `img_out` is copied from the cell of `img_in` at
+    // `matrix * coord + offset`, wrapping around the edges if `wrap` is set
+    layout(set = 0, binding = 2) buffer Transform {
+        int m00;
+        int m01;
+        int m10;
+        int m11;
+        int offset_x;
+        int offset_y;
+        int wrap;
+    } transform;
+
+    void main() {
+        ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+        ivec2 size_out = imageSize(img_out);
+        if (coord.x >= size_out.x || coord.y >= size_out.y) {
+            return;
+        }
+
+        ivec2 size_in = imageSize(img_in);
+        ivec2 source = ivec2(
+            transform.m00 * coord.x + transform.m01 * coord.y + transform.offset_x,
+            transform.m10 * coord.x + transform.m11 * coord.y + transform.offset_y);
+
+        if (transform.wrap != 0) {
+            source = ((source % size_in) + size_in) % size_in;
+        } else if (source.x < 0 || source.x >= size_in.x || source.y < 0 || source.y >= size_in.y) {
+            imageStore(img_out, coord, vec4(0.0));
+            return;
+        }
+
+        imageStore(img_out, coord, imageLoad(img_in, source));
+    }
+    "]
+    struct Dummy;
+}
+
+pub mod pcs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+    #version 450
+
+    layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+    layout(set = 0, binding = 0, r8) uniform readonly image2D img;
+
+    // The counted region of `img`
+    layout(set = 0, binding = 1) buffer Region {
+        int x;
+        int y;
+        int width;
+        int height;
+    } region;
+
+    layout(set = 0, binding = 2) buffer Population {
+        uint count;
+    } population;
+
+    // Living cells of the work group, added to `population` at once
+    shared uint group_count;
+
+    void main() {
+        if (gl_LocalInvocationIndex == 0) {
+            group_count = 0;
+        }
+        barrier();
+
+        ivec2 cell = ivec2(gl_GlobalInvocationID.xy);
+        if (cell.x < region.width && cell.y < region.height
+            && imageLoad(img, cell + ivec2(region.x, region.y)).x == 1.0) {
+            atomicAdd(group_count, 1);
+        }
+        barrier();
+
+        if (gl_LocalInvocationIndex == 0 && group_count > 0) {
+            atomicAdd(population.count, group_count);
+        }
+    }
+    "]
+    struct Dummy;
+}
+
+pub mod chs {
+    #[derive(VulkanoShader)]
+    #[ty = "compute"]
+    #[src = "
+    #version 450
+
+    layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+
+    layout(set = 0, binding = 0, r8) uniform readonly image2D img;
+
+    // Each living cell at (x, y) adds factors[0]^x * factors[1]^y to
+    // sums[0] and factors[2]^x * factors[3]^y to sums[1]
+    layout(set = 0, binding = 1) buffer Hash {
+        uint factors[4];
+        uint population;
+        uint min_x;
+        uint min_y;
+        uint sums[2];
+    } hash;
+
+    // Reduction of the living cells of the work group, added to `hash`
+    // at once
+    shared uint group_population;
+    shared uint group_min_x;
+    shared uint group_min_y;
+    shared uint group_sums[2];
+
+    uint power(uint base, uint exponent) {
+        uint result = 1;
+        while (exponent > 0) {
+            if ((exponent & 1) != 0) {
+                result *= base;
+            }
+            base *= base;
+            exponent >>= 1;
+        }
+        return result;
+    }
+
+    void main() {
+        if (gl_LocalInvocationIndex == 0) {
+            group_population = 0;
+            group_min_x = 0xFFFFFFFF;
+            group_min_y = 0xFFFFFFFF;
+            group_sums[0] = 0;
+            group_sums[1] = 0;
+        }
+        barrier();
+
+        ivec2 size = imageSize(img);
+        uvec2 cell = gl_GlobalInvocationID.xy;
+        if (cell.x < uint(size.x) && cell.y < uint(size.y)
+            && imageLoad(img, ivec2(cell)).x == 1.0) {
+            atomicAdd(group_population, 1);
+            atomicMin(group_min_x, cell.x);
+            atomicMin(group_min_y, cell.y);
+            for (int i = 0; i < 2; i++) {
+                atomicAdd(
+                    group_sums[i],
+                    power(hash.factors[2 * i], cell.x) * power(hash.factors[2 * i + 1], cell.y)
+                );
+            }
+        }
+        barrier();
+
+        if (gl_LocalInvocationIndex == 0 && group_population > 0) {
+            atomicAdd(hash.population, group_population);
+            atomicMin(hash.min_x, group_min_x);
+            atomicMin(hash.min_y, group_min_y);
+            atomicAdd(hash.sums[0], group_sums[0]);
+            atomicAdd(hash.sums[1], group_sums[1]);
+        }
+    }
+    "]
+    struct Dummy;
+}
@@ -1,8 +1,15 @@
 extern crate foundry;
 
+use foundry::batch::GridBatch;
+use foundry::classification::Classification;
+use foundry::editing::BlendMode;
+use foundry::resizing::Anchor;
+use foundry::soup::{SoupOptions, Symmetry};
+use foundry::topology::Topology;
 use foundry::Grid;
 
 use std::fs;
+use std::thread;
 
 #[test]
 fn test_toroidal_load_file() {
@@ -187,3 +194,888 @@ fn test_resizable_save_file() {
 
     fs::remove_file("tests/output_files/sanity_check_resizable.life").unwrap();
 }
+
+#[test]
+fn test_klein_bottle_next_gen() {
+    let mut grid = Grid::from_file("tests/input_files/sanity_check_klein_bottle.life").unwrap();
+
+    assert_eq!(
+        Topology::KleinBottle {
+            horizontal_twist: true
+        },
+        grid.get_topology()
+    );
+    assert_eq!(false, grid.is_toroidal());
+    assert_eq!(false, grid.is_resizable());
+
+    grid.next_gen();
+
+    // The blinker centered on the column 1 crosses the top edge and comes
+    // back mirrored at the bottom, on the column 3 (on a torus, it would
+    // stay on the column 1)
+    for y in 0..5 {
+        for x in 0..5 {
+            let expected = if (x == 1 && (y == 0 || y == 1)) || (x == 3 && y == 4) {
+                255
+            } else {
+                0
+            };
+            assert_eq!(expected, grid.get_cell_state(x, y));
+        }
+    }
+}
+
+#[test]
+fn test_bit_packed_next_gen() {
+    let topologies = [
+        Topology::torus(),
+        Topology::KleinBottle {
+            horizontal_twist: false,
+        },
+        Topology::Plane,
+    ];
+
+    for topology in topologies.iter() {
+        // 70 columns, so that the rows span three words
+        let mut grid = Grid::new_random(
+            &String::from("#Toroidal Life"),
+            *topology,
+            &vec![2, 3],
+            &vec![3],
+            70,
+            40,
+        );
+        let mut packed = grid.clone();
+        packed.set_bit_packed(true);
+        assert!(packed.is_bit_packed());
+
+        for _ in 0..10 {
+            grid.next_gen();
+            packed.next_gen();
+        }
+
+        packed.set_bit_packed(false);
+        assert_eq!(format!("{}", grid), format!("{}", packed));
+    }
+}
+
+#[test]
+fn test_tiled_next_gen() {
+    let topologies = [
+        Topology::torus(),
+        Topology::Torus {
+            horizontal_shift: 0,
+            vertical_shift: 3,
+        },
+        Topology::CrossSurface,
+    ];
+
+    for topology in topologies.iter() {
+        let mut grid = Grid::new_random(
+            &String::from("#Toroidal Life"),
+            *topology,
+            &vec![2, 3],
+            &vec![3],
+            50,
+            40,
+        );
+        grid.set_birth_probability(0.9).unwrap();
+        grid.set_seed(7);
+
+        let mut tiled = grid.clone();
+        tiled.set_max_tile_size(Some(16));
+        assert!(!grid.is_tiled());
+        assert!(tiled.is_tiled());
+
+        for _ in 0..10 {
+            grid.next_gen();
+            tiled.next_gen();
+        }
+
+        assert_eq!(format!("{}", grid), format!("{}", tiled));
+    }
+}
+
+#[test]
+fn test_active_tiles() {
+    let mut grid = Grid::new(
+        &String::from("#Toroidal Life"),
+        Topology::torus(),
+        &vec![2, 3],
+        &vec![3],
+        64,
+        64,
+    );
+
+    // A block and a blinker, in the 8x8 tiles (1, 1) and (5, 5)
+    for &(x, y) in &[
+        (10, 10),
+        (11, 10),
+        (10, 11),
+        (11, 11),
+        (40, 41),
+        (41, 41),
+        (42, 41),
+    ] {
+        grid.set_cell_state(x, y, 255).unwrap();
+    }
+    assert_eq!(64, grid.get_active_tile_count());
+
+    // Only the blinker's tile and its neighbors remain active
+    grid.next_gen();
+    assert_eq!(9, grid.get_active_tile_count());
+
+    grid.next_gen();
+    assert_eq!(9, grid.get_active_tile_count());
+    for &(x, y) in &[
+        (10, 10),
+        (11, 10),
+        (10, 11),
+        (11, 11),
+        (40, 41),
+        (41, 41),
+        (42, 41),
+    ] {
+        assert_eq!(255, grid.get_cell_state(x, y));
+    }
+    assert_eq!(0, grid.get_cell_state(41, 40));
+
+    // An edited tile on the border activates the whole border (28 tiles),
+    // along with its neighbors inside the grid (3 tiles)
+    grid.set_cell_state(0, 30, 255).unwrap();
+    assert_eq!(9 + 28 + 3, grid.get_active_tile_count());
+
+    // Stochastic rules compute every tile, even the block's one which
+    // didn't change during the last deterministic generation
+    grid.next_gen();
+    grid.set_survival_probability(0.0).unwrap();
+    assert_eq!(64, grid.get_active_tile_count());
+    grid.next_gen();
+    assert_eq!(0, grid.get_cell_state(10, 10));
+}
+
+#[test]
+fn test_step_async() {
+    for &bit_packed in &[false, true] {
+        let mut grid = Grid::new_random(
+            &String::from("#Toroidal Life"),
+            Topology::torus(),
+            &vec![2, 3],
+            &vec![3],
+            70,
+            40,
+        );
+        grid.set_survival_probability(0.9).unwrap();
+        grid.set_seed(3);
+        grid.set_bit_packed(bit_packed);
+        let mut stepped = grid.clone();
+
+        {
+            let mut handle = stepped.step_async(5);
+
+            // The previous state is kept until the generations are computed
+            assert_eq!(format!("{}", grid), format!("{}", handle.grid()));
+            // Polling doesn't block, waiting does
+            for _ in 0..1000 {
+                if handle.is_finished() {
+                    break;
+                }
+                thread::yield_now();
+            }
+            handle.wait();
+        }
+
+        // One more generation checks the generation counter was updated
+        for _ in 0..6 {
+            grid.next_gen();
+        }
+        stepped.next_gen();
+
+        assert_eq!(format!("{}", grid), format!("{}", stepped));
+    }
+}
+
+#[test]
+fn test_grid_batch() {
+    let format = String::from("#Toroidal Life");
+    let mut grids: Vec<Grid> = (0..3)
+        .map(|_| Grid::new_random(&format, Topology::torus(), &vec![2, 3], &vec![3], 40, 20))
+        .collect();
+
+    // A block and a blinker in an otherwise empty grid
+    let mut still = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 40, 20);
+    for &(x, y) in &[(1, 1), (2, 1), (1, 2), (2, 2)] {
+        still.set_cell_state(x, y, 255).unwrap();
+    }
+    let mut blinker = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 40, 20);
+    for &(x, y) in &[(10, 10), (11, 10), (12, 10)] {
+        blinker.set_cell_state(x, y, 255).unwrap();
+    }
+    grids.push(still);
+    grids.push(blinker);
+
+    let mut batch = GridBatch::from_grids(&grids).unwrap();
+    assert_eq!(5, batch.get_count());
+    batch.step(10);
+
+    for (i, grid) in grids.iter_mut().enumerate() {
+        for _ in 0..10 {
+            grid.next_gen();
+        }
+        assert_eq!(
+            format!("{}", grid),
+            format!("{}", batch.get_grid(i, &format).unwrap())
+        );
+    }
+
+    assert_eq!(4, batch.get_population(3));
+    assert_eq!(Some(1), batch.get_period(3));
+    assert_eq!(3, batch.get_population(4));
+    assert_eq!(Some(2), batch.get_period(4));
+
+    let other = Grid::new(&format, Topology::Plane, &vec![2, 3], &vec![3], 40, 20);
+    assert!(batch.set_grid(0, &other).is_err());
+}
+
+#[test]
+fn test_bulk_access() {
+    for &bit_packed in &[false, true] {
+        let mut grid = Grid::new(
+            &String::from("#Toroidal Life"),
+            Topology::torus(),
+            &vec![2, 3],
+            &vec![3],
+            40,
+            3,
+        );
+        grid.set_bit_packed(bit_packed);
+
+        grid.set_cells(vec![(1, 0, 255), (39, 2, 255), (5, 1, 255)])
+            .unwrap();
+        assert!(grid.set_cells(vec![(40, 0, 255)]).is_err());
+
+        assert_eq!(
+            vec![(1, 0), (5, 1), (39, 2)],
+            grid.live_cells().collect::<Vec<_>>()
+        );
+        assert_eq!(255, grid.cells()[2 * 40 + 39]);
+        assert_eq!(3, grid.cells().iter().filter(|&&cell| cell == 255).count());
+
+        // The region is clipped by the edges of the grid
+        assert_eq!(vec![0, 0, 0, 0, 255, 0], grid.read_region(38, 1, 3, 2));
+        grid.write_region(0, 1, 2, 2, &[255, 255, 0, 255]);
+        assert_eq!(
+            vec![(1, 0), (0, 1), (1, 1), (5, 1), (1, 2), (39, 2)],
+            grid.live_cells().collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn test_seeded_soups() {
+    let format = String::from("#Toroidal Life");
+    let options = SoupOptions {
+        seed: 12,
+        density: 0.4,
+        region: Some((24, 24, 16, 16)),
+        symmetry: Symmetry::D2Plus2,
+    };
+
+    let grid = Grid::new_random_with(
+        &format,
+        Topology::torus(),
+        &vec![2, 3],
+        &vec![3],
+        64,
+        64,
+        &options,
+    )
+    .unwrap();
+    let same = Grid::new_random_with(
+        &format,
+        Topology::torus(),
+        &vec![2, 3],
+        &vec![3],
+        64,
+        64,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(format!("{}", grid), format!("{}", same));
+
+    for (x, y) in grid.live_cells() {
+        assert!(x >= 24 && x < 40 && y >= 24 && y < 40);
+        assert_eq!(255, grid.get_cell_state(x as i64, (63 - y) as i64));
+    }
+
+    let mut grid = same;
+    assert!(grid.randomize_with(1, 1.5, None).is_err());
+    assert!(grid.randomize_with(1, 0.5, Some((60, 0, 8, 8))).is_err());
+    grid.randomize_with(1, 1.0, Some((0, 0, 2, 2))).unwrap();
+    assert_eq!(255, grid.get_cell_state(1, 1));
+}
+
+#[test]
+fn test_paste_and_extract() {
+    let format = String::from("#Toroidal Life");
+    let mut glider = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 3);
+    glider
+        .set_cells(vec![
+            (1, 0, 255),
+            (2, 1, 255),
+            (0, 2, 255),
+            (1, 2, 255),
+            (2, 2, 255),
+        ])
+        .unwrap();
+
+    // The pasted glider wraps around the torus
+    let mut torus = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 8, 8);
+    torus.set_cell_state(0, 0, 255).unwrap();
+    torus.paste(&glider, 6, 6, BlendMode::Or);
+    for &(x, y) in &[(7, 6), (0, 7), (6, 0), (7, 0), (0, 0)] {
+        assert_eq!(255, torus.get_cell_state(x, y));
+    }
+    assert_eq!(5, torus.live_cells().count());
+
+    torus.paste(&glider, 6, 6, BlendMode::Xor);
+    assert_eq!(0, torus.live_cells().count());
+    torus.paste(&glider, 6, 6, BlendMode::Copy);
+    assert_eq!(255, torus.get_cell_state(0, 0));
+    torus.paste(
+        &Grid::new(&format, Topology::Plane, &vec![], &vec![], 2, 2),
+        7,
+        7,
+        BlendMode::And,
+    );
+    assert_eq!(2, torus.live_cells().count());
+
+    // A resizable grid grows to the north west
+    let mut resizable = glider.clone();
+    resizable.paste(&glider, -4, -1, BlendMode::CopyLive);
+    assert_eq!((7, 4), (resizable.get_width(), resizable.get_height()));
+    assert_eq!(255, resizable.get_cell_state(5, 1));
+    assert_eq!(255, resizable.get_cell_state(2, 1));
+
+    // The extracted region wraps around the torus
+    let extracted = torus.extract(-1, -2, 3, 3);
+    assert_eq!(Topology::Resizable, extracted.get_topology());
+    assert_eq!(255, extracted.get_cell_state(0, 0));
+    assert_eq!(1, extracted.live_cells().count());
+
+    let cut = torus.cut(-1, -2, 3, 3);
+    assert_eq!(format!("{}", extracted), format!("{}", cut));
+    assert_eq!(vec![(6, 0)], torus.live_cells().collect::<Vec<_>>());
+}
+
+#[test]
+fn test_transforms() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::Plane, &vec![2, 3], &vec![3], 4, 2);
+    grid.set_cells(vec![(0, 0, 255), (3, 0, 255), (1, 1, 255)])
+        .unwrap();
+
+    // The width and the height are swapped
+    grid.rotate_cw().unwrap();
+    assert_eq!((2, 4), (grid.get_width(), grid.get_height()));
+    assert_eq!(
+        vec![(1, 0), (0, 1), (1, 3)],
+        grid.live_cells().collect::<Vec<_>>()
+    );
+    grid.rotate_ccw().unwrap();
+    assert_eq!(
+        vec![(0, 0), (3, 0), (1, 1)],
+        grid.live_cells().collect::<Vec<_>>()
+    );
+
+    grid.flip_horizontal().unwrap();
+    assert_eq!(
+        vec![(0, 0), (3, 0), (2, 1)],
+        grid.live_cells().collect::<Vec<_>>()
+    );
+    grid.flip_vertical().unwrap();
+    assert_eq!(
+        vec![(2, 0), (0, 1), (3, 1)],
+        grid.live_cells().collect::<Vec<_>>()
+    );
+    grid.transpose().unwrap();
+    assert_eq!(
+        vec![(1, 0), (0, 2), (1, 3)],
+        grid.live_cells().collect::<Vec<_>>()
+    );
+
+    // The cells moved beyond the edges of a plane are lost
+    grid.shift(1, -1);
+    assert_eq!(vec![(1, 1)], grid.live_cells().collect::<Vec<_>>());
+
+    // They wrap around a torus
+    let mut torus = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 4, 4);
+    torus.set_cell_state(3, 3, 255).unwrap();
+    torus.shift(2, 1);
+    assert_eq!(vec![(1, 0)], torus.live_cells().collect::<Vec<_>>());
+
+    // A resizable grid grows
+    let mut resizable = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 3);
+    resizable.set_cell_state(0, 0, 255).unwrap();
+    resizable.shift(2, -1);
+    assert_eq!((5, 4), (resizable.get_width(), resizable.get_height()));
+    assert_eq!(vec![(2, 0)], resizable.live_cells().collect::<Vec<_>>());
+
+    let mut sphere = Grid::new(&format, Topology::Sphere, &vec![2, 3], &vec![3], 4, 4);
+    assert!(sphere.rotate_cw().is_err());
+    assert!(sphere.transpose().is_ok());
+}
+
+#[test]
+fn test_resize_crop_and_pad() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 4, 4);
+    grid.set_cells(vec![(1, 1, 255), (2, 2, 255)]).unwrap();
+
+    grid.resize(6, 5, Anchor::SouthEast).unwrap();
+    assert_eq!((6, 5), (grid.get_width(), grid.get_height()));
+    assert_eq!(vec![(3, 2), (4, 3)], grid.live_cells().collect::<Vec<_>>());
+
+    grid.resize(2, 2, Anchor::Center).unwrap();
+    assert_eq!(vec![(1, 1)], grid.live_cells().collect::<Vec<_>>());
+    assert!(grid.resize(0, 2, Anchor::Center).is_err());
+
+    grid.pad(1, 0, 2, 3).unwrap();
+    assert_eq!((5, 5), (grid.get_width(), grid.get_height()));
+    assert_eq!(vec![(4, 2)], grid.live_cells().collect::<Vec<_>>());
+
+    grid.crop_to_pattern(1).unwrap();
+    assert_eq!((3, 3), (grid.get_width(), grid.get_height()));
+    assert_eq!(vec![(1, 1)], grid.live_cells().collect::<Vec<_>>());
+    assert!(grid.is_toroidal());
+
+    // Conversions between the resizable and the toroidal grids
+    let mut resizable = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 3);
+    resizable
+        .set_cells(vec![(0, 1, 255), (1, 1, 255), (2, 1, 255)])
+        .unwrap();
+    resizable.make_toroidal(7, 5).unwrap();
+    assert_eq!(Topology::torus(), resizable.get_topology());
+    assert_eq!(
+        vec![(2, 2), (3, 2), (4, 2)],
+        resizable.live_cells().collect::<Vec<_>>()
+    );
+
+    resizable.make_resizable();
+    assert!(resizable.is_resizable());
+    assert_eq!((5, 3), (resizable.get_width(), resizable.get_height()));
+    assert_eq!(
+        vec![(1, 1), (2, 1), (3, 1)],
+        resizable.live_cells().collect::<Vec<_>>()
+    );
+
+    let mut sphere = Grid::new(&format, Topology::Sphere, &vec![2, 3], &vec![3], 4, 4);
+    assert!(sphere.pad(1, 1, 1, 0).is_err());
+    assert!(sphere.pad(1, 1, 1, 1).is_ok());
+}
+
+#[test]
+fn test_journal() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 4, 3);
+    grid.enable_journal(1 << 20);
+    assert!(!grid.can_undo());
+
+    grid.set_cell_state(1, 1, 255).unwrap();
+    grid.begin_transaction();
+    grid.set_cells(vec![(0, 0, 255), (3, 2, 255)]).unwrap();
+    grid.rotate_cw().unwrap();
+    grid.end_transaction();
+    grid.set_survival(&vec![2]);
+    assert_eq!((3, 4), (grid.get_width(), grid.get_height()));
+
+    // The transaction is undone at once
+    assert!(grid.undo());
+    assert_eq!(vec![2, 3], grid.get_survival());
+    assert!(grid.undo());
+    assert_eq!((4, 3), (grid.get_width(), grid.get_height()));
+    assert_eq!(vec![(1, 1)], grid.live_cells().collect::<Vec<_>>());
+    assert!(grid.undo());
+    assert_eq!(0, grid.live_cells().count());
+    assert!(!grid.undo());
+
+    assert!(grid.redo());
+    assert!(grid.redo());
+    assert_eq!((3, 4), (grid.get_width(), grid.get_height()));
+    assert_eq!(3, grid.live_cells().count());
+
+    // A new edit forgets the undone ones
+    grid.paste(&grid.clone(), 1, 0, BlendMode::Or);
+    assert!(!grid.can_redo());
+    assert!(grid.undo());
+    assert_eq!(3, grid.live_cells().count());
+
+    // The cells given several times are restored in order
+    grid.set_cell_state(0, 0, 255).unwrap();
+    grid.set_cells(vec![(0, 0, 0), (0, 0, 255)]).unwrap();
+    assert!(grid.undo());
+    assert!(grid.redo());
+    assert_eq!(255, grid.get_cell_state(0, 0));
+
+    // The file format is restored with the topology
+    grid.make_resizable();
+    assert!(grid.undo());
+    assert_eq!("#Toroidal Life", grid.get_format());
+
+    // Computing a generation clears the journal
+    grid.next_gen();
+    assert!(!grid.can_undo());
+
+    // The oldest edits are forgotten beyond the memory cap
+    grid.enable_journal(1);
+    let state = 255 - grid.get_cell_state(0, 0);
+    grid.set_cell_state(0, 0, state).unwrap();
+    assert!(!grid.can_undo());
+}
+
+#[test]
+fn test_history() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 8, 8);
+    grid.set_cells(vec![
+        (1, 0, 255),
+        (2, 1, 255),
+        (0, 2, 255),
+        (1, 2, 255),
+        (2, 2, 255),
+    ])
+    .unwrap();
+    let initial = format!("{}", grid);
+
+    let mut states = vec![initial.clone()];
+    grid.enable_history(3);
+    for _ in 0..6 {
+        grid.next_gen();
+        states.push(format!("{}", grid));
+    }
+    assert_eq!(6, grid.get_generation());
+
+    grid.rewind(2).unwrap();
+    assert_eq!(4, grid.get_generation());
+    assert_eq!(states[4], format!("{}", grid));
+
+    // The generations between the recorded ones are simulated again
+    grid.seek(6).unwrap();
+    assert_eq!(states[6], format!("{}", grid));
+
+    // Only the last 3 generations are kept
+    assert!(grid.seek(3).is_err());
+    grid.seek(4).unwrap();
+    assert_eq!(states[4], format!("{}", grid));
+    assert!(grid.seek(0).is_err());
+    assert!(grid.rewind(10).is_err());
+
+    // The generations computed in a single step are simulated again
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 8, 8);
+    grid.enable_history(4);
+    grid.set_cells(vec![
+        (1, 0, 255),
+        (2, 1, 255),
+        (0, 2, 255),
+        (1, 2, 255),
+        (2, 2, 255),
+    ])
+    .unwrap();
+    grid.step_async(4).wait();
+    grid.seek(2).unwrap();
+    assert_eq!(states[2], format!("{}", grid));
+}
+
+#[test]
+fn test_generation_metadata() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 8, 8);
+    grid.set_cells(vec![(1, 0, 255), (1, 1, 255), (1, 2, 255)])
+        .unwrap();
+    for _ in 0..5 {
+        grid.next_gen();
+    }
+    assert_eq!(5, grid.get_generation());
+    assert_eq!(5, grid.clone().get_generation());
+
+    // The generation is saved and loaded
+    grid.save_life_grid("tests/output_files/generation.life")
+        .unwrap();
+    let loaded = Grid::from_file("tests/output_files/generation.life").unwrap();
+    fs::remove_file("tests/output_files/generation.life").unwrap();
+    assert_eq!(5, loaded.get_generation());
+    assert_eq!(format!("{}", grid), format!("{}", loaded));
+
+    // It isn't reset by edits unless asked
+    grid.set_cell_state(0, 0, 255).unwrap();
+    assert_eq!(5, grid.get_generation());
+    grid.set_generation_reset_on_edit(true);
+    grid.next_gen();
+    assert_eq!(6, grid.get_generation());
+    grid.shift(1, 1);
+    assert_eq!(0, grid.get_generation());
+}
+
+#[test]
+fn test_population() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 40, 30);
+    grid.randomize_with(3, 0.4, None).unwrap();
+
+    let expected = grid.live_cells().count() as u64;
+    assert_eq!(expected, grid.get_population());
+    assert_eq!(
+        grid.live_cells()
+            .filter(|&(x, y)| x >= 10 && x < 25 && y >= 5)
+            .count() as u64,
+        grid.get_population_in(10, 5, 15, 100)
+    );
+    assert_eq!(0, grid.get_population_in(40, 0, 10, 10));
+
+    // The population of the next generation is counted with it
+    let mut clone = grid.clone();
+    clone.next_gen();
+    assert_eq!(
+        clone.live_cells().count() as u64,
+        grid.next_gen_with_population()
+    );
+
+    // The same population is counted on the CPU
+    grid.set_bit_packed(true);
+    assert_eq!(clone.live_cells().count() as u64, grid.get_population());
+
+    // And by the shader of the bit-packed cells along with the generation
+    clone.next_gen();
+    assert_eq!(
+        clone.live_cells().count() as u64,
+        grid.next_gen_with_population()
+    );
+    grid.set_max_tile_size(Some(16));
+    grid.set_bit_packed(false);
+    assert_eq!(clone.live_cells().count() as u64, grid.get_population());
+}
+
+#[test]
+fn test_classify() {
+    let format = String::from("#Toroidal Life");
+    let glider = vec![
+        (1, 0, 255),
+        (2, 1, 255),
+        (0, 2, 255),
+        (1, 2, 255),
+        (2, 2, 255),
+    ];
+
+    // The displacement is measured across the resizing of the grid
+    let mut grid = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 3);
+    grid.set_cells(glider.clone()).unwrap();
+    let classification = grid.classify(8).unwrap();
+    assert_eq!(
+        Classification::Spaceship {
+            period: 4,
+            dx: 1,
+            dy: 1
+        },
+        classification
+    );
+    assert_eq!(Some("c/4 diagonal".to_string()), classification.get_speed());
+    assert_eq!(0, grid.get_generation());
+
+    // And across the edges of a torus
+    let mut torus = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 8, 8);
+    torus.set_cells(glider).unwrap();
+    torus.flip_horizontal().unwrap();
+    assert_eq!(
+        Classification::Spaceship {
+            period: 4,
+            dx: -1,
+            dy: 1
+        },
+        torus.classify(8).unwrap()
+    );
+
+    let mut blinker = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 1);
+    blinker
+        .set_cells(vec![(0, 0, 255), (1, 0, 255), (2, 0, 255)])
+        .unwrap();
+    assert_eq!(
+        Classification::Oscillator { period: 2 },
+        blinker.classify(8).unwrap()
+    );
+
+    let mut block = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 2, 2);
+    block
+        .set_cells(vec![(0, 0, 255), (1, 0, 255), (0, 1, 255), (1, 1, 255)])
+        .unwrap();
+    assert_eq!(Classification::StillLife, block.classify(8).unwrap());
+    assert_eq!(Classification::Unknown, block.classify(0).unwrap());
+
+    block.set_cells(vec![(0, 0, 0), (1, 0, 0)]).unwrap();
+    assert_eq!(Classification::Extinct, block.classify(8).unwrap());
+}
+
+#[test]
+fn test_census() {
+    let format = String::from("#Toroidal Life");
+    let mut grid = Grid::new(&format, Topology::Plane, &vec![2, 3], &vec![3], 16, 16);
+    // Two blocks, a blinker and a glider
+    grid.set_cells(vec![
+        (0, 0, 255),
+        (1, 0, 255),
+        (0, 1, 255),
+        (1, 1, 255),
+        (3, 0, 255),
+        (4, 0, 255),
+        (3, 1, 255),
+        (4, 1, 255),
+        (10, 1, 255),
+        (10, 2, 255),
+        (10, 3, 255),
+        (3, 10, 255),
+        (4, 11, 255),
+        (2, 12, 255),
+        (3, 12, 255),
+        (4, 12, 255),
+    ])
+    .unwrap();
+
+    let objects = grid.split_objects(1);
+    assert_eq!(4, objects.len());
+    assert_eq!(
+        objects[0].get_canonical_hash(),
+        objects[1].get_canonical_hash()
+    );
+
+    let census = grid.census(1, 8).unwrap();
+    assert_eq!(Some(&2), census.get("xs4_33"));
+    assert_eq!(Some(&1), census.get("xp2_7"));
+    assert_eq!(Some(&1), census.get("xq4_153"));
+
+    // The two blocks form a pseudo still life
+    let census = grid.census(2, 8).unwrap();
+    assert_eq!(Some(&1), census.get("xs8_rr"));
+    assert_eq!(None, census.get("xs4_33"));
+}
+
+#[test]
+fn test_apgcodes() {
+    for &code in &["xs4_33", "xs6_696", "xp2_7", "xq4_153"] {
+        let grid = Grid::from_apgcode(code).unwrap();
+        assert_eq!(code, grid.to_apgcode().unwrap());
+    }
+
+    let beehive = Grid::from_apgcode("xs6_696").unwrap();
+    assert_eq!((3, 4), (beehive.get_width(), beehive.get_height()));
+    assert_eq!(6, beehive.get_population());
+
+    assert!(Grid::from_apgcode("xs4").is_err());
+    assert!(Grid::from_apgcode("yl4_33").is_err());
+    assert!(Grid::from_apgcode("xs4_3!").is_err());
+
+    // The R-pentomino doesn't repeat within the maximum period
+    let format = String::from("#Resizable Life");
+    let mut r_pentomino = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 3);
+    r_pentomino
+        .set_cells(vec![
+            (1, 0, 255),
+            (2, 0, 255),
+            (0, 1, 255),
+            (1, 1, 255),
+            (1, 2, 255),
+        ])
+        .unwrap();
+    assert!(r_pentomino.to_apgcode().is_err());
+}
+
+#[test]
+fn test_run_until_stable() {
+    let format = String::from("#Toroidal Life");
+
+    // The L-tromino becomes a block
+    let mut grid = Grid::new(&format, Topology::Plane, &vec![2, 3], &vec![3], 8, 8);
+    grid.set_cells(vec![(3, 3, 255), (4, 3, 255), (3, 4, 255)])
+        .unwrap();
+    let stabilisation = grid.run_until_stable(10, 4).unwrap().unwrap();
+    assert_eq!(1, stabilisation.get_generation());
+    assert_eq!(1, stabilisation.get_period());
+    assert_eq!(4, stabilisation.get_population());
+    assert_eq!(2, grid.get_generation());
+
+    // The period must fit in the window
+    let mut blinker = Grid::new(&format, Topology::Plane, &vec![2, 3], &vec![3], 8, 8);
+    blinker
+        .set_cells(vec![(3, 4, 255), (4, 4, 255), (5, 4, 255)])
+        .unwrap();
+    assert_eq!(None, blinker.clone().run_until_stable(10, 1).unwrap());
+    let stabilisation = blinker.run_until_stable(10, 4).unwrap().unwrap();
+    assert_eq!(
+        (0, 2, 3),
+        (
+            stabilisation.get_generation(),
+            stabilisation.get_period(),
+            stabilisation.get_population()
+        )
+    );
+
+    // A glider is stable wherever it is
+    let mut glider = Grid::new(&format, Topology::Resizable, &vec![2, 3], &vec![3], 3, 3);
+    glider
+        .set_cells(vec![
+            (1, 0, 255),
+            (2, 1, 255),
+            (0, 2, 255),
+            (1, 2, 255),
+            (2, 2, 255),
+        ])
+        .unwrap();
+    assert_eq!(
+        4,
+        glider
+            .run_until_stable(20, 8)
+            .unwrap()
+            .unwrap()
+            .get_period()
+    );
+
+    grid.set_survival_probability(0.5).unwrap();
+    assert!(grid.run_until_stable(10, 4).is_err());
+}
+
+#[test]
+fn test_pattern_comparison() {
+    let format = String::from("#Toroidal Life");
+    let glider = vec![
+        (1, 0, 255),
+        (2, 1, 255),
+        (0, 2, 255),
+        (1, 2, 255),
+        (2, 2, 255),
+    ];
+
+    let mut grid = Grid::new(&format, Topology::torus(), &vec![2, 3], &vec![3], 8, 8);
+    grid.set_cells(glider.clone()).unwrap();
+    let mut copy = grid.clone();
+    assert!(grid.same_cells(&copy));
+
+    // Moved
+    copy.shift(3, 2);
+    assert!(!grid.same_cells(&copy));
+    assert!(grid.same_pattern(&copy));
+    assert_eq!(grid.get_pattern_hash(), copy.get_pattern_hash());
+
+    // Rotated, on a grid of another size
+    let mut other = Grid::new(&format, Topology::Plane, &vec![2, 3], &vec![3], 5, 4);
+    other.set_cells(glider).unwrap();
+    other.rotate_cw().unwrap();
+    assert!(grid.same_pattern(&other));
+    assert_ne!(grid.get_pattern_hash(), other.get_pattern_hash());
+    assert_eq!(grid.get_canonical_hash(), other.get_canonical_hash());
+
+    let canonical = other.get_canonical_form();
+    assert!(grid.get_canonical_form().same_cells(&canonical));
+    assert_eq!(Topology::Resizable, canonical.get_topology());
+    assert_eq!("xq4_153", canonical.to_apgcode().unwrap());
+
+    copy.next_gen();
+    assert!(!grid.same_pattern(&copy));
+}